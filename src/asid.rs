@@ -0,0 +1,204 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
+use super::sid::Sid;
+
+// ASID messages are system exclusive messages with manufacturer id 0x2d.
+const SYSEX_START: u8 = 0xf0;
+const SYSEX_END: u8 = 0xf7;
+const ASID_ID: u8 = 0x2d;
+
+const CMD_START: u8 = 0x4c;
+const CMD_STOP: u8 = 0x4d;
+const CMD_UPDATE: u8 = 0x4e;
+const CMD_UPDATE_SID2: u8 = 0x50;
+const CMD_UPDATE_SID3: u8 = 0x51;
+
+/// Longest message kept, an update of all registers is 39 bytes.
+const MAX_MESSAGE_LEN: usize = 64;
+
+/// SID register of each ASID register index. The control registers come
+/// last, and twice, so both gate transitions of a hard restart fit in one
+/// update.
+const ASID_REGISTERS: [u8; 28] = [
+    0x00, 0x01, 0x02, 0x03, 0x05, 0x06, // voice 1 except control
+    0x07, 0x08, 0x09, 0x0a, 0x0c, 0x0d, // voice 2 except control
+    0x0e, 0x0f, 0x10, 0x11, 0x13, 0x14, // voice 3 except control
+    0x15, 0x16, 0x17, 0x18, // filter and volume
+    0x04, 0x0b, 0x12, // control, first write
+    0x04, 0x0b, 0x12, // control, second write
+];
+
+/// Decoded ASID event.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AsidEvent {
+    Start,
+    Stop,
+    /// Register write to SID 0, 1 or 2 of a multi-SID setup.
+    Write {
+        sid: usize,
+        reg: u8,
+        value: u8,
+    },
+}
+
+/// Event with the timestamp of the bytes it was decoded from, see
+/// AsidDecoder::decode_timed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimedEvent {
+    pub time: u64,
+    pub event: AsidEvent,
+}
+
+/// Streaming decoder of ASID MIDI system exclusive messages.
+///
+/// The bytes may be fed in chunks of any size, e.g. as received from a MIDI
+/// port. MIDI real-time bytes within messages are skipped, as are other
+/// MIDI messages and system exclusive messages for other manufacturers.
+/// Truncated and malformed ASID messages are dropped.
+///
+/// Register updates carry a mask of the registers written and the values
+/// split into 7-bit data bytes and separate MSBs. The registers of SID 0 are
+/// updated by the original update command, and SID 1 and SID 2 by the
+/// multi-SID extension commands.
+pub struct AsidDecoder {
+    message: Vec<u8>,
+    in_sysex: bool,
+    playing: bool,
+}
+
+impl Default for AsidDecoder {
+    fn default() -> Self {
+        AsidDecoder {
+            message: Vec::with_capacity(MAX_MESSAGE_LEN),
+            in_sysex: false,
+            playing: false,
+        }
+    }
+}
+
+impl AsidDecoder {
+    /// Whether a start message has been received since the last stop.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Decode a chunk of MIDI bytes, calling f with each event decoded.
+    pub fn decode<F: FnMut(AsidEvent)>(&mut self, bytes: &[u8], mut f: F) {
+        for &byte in bytes {
+            match byte {
+                SYSEX_START => {
+                    self.message.clear();
+                    self.in_sysex = true;
+                }
+                SYSEX_END => {
+                    if self.in_sysex {
+                        self.in_sysex = false;
+                        self.decode_message(&mut f);
+                    }
+                }
+                // Real-time messages may appear anywhere.
+                0xf8..=0xff => {}
+                // Any other status byte aborts the message.
+                0x80..=0xf6 => self.in_sysex = false,
+                _ => {
+                    if self.in_sysex {
+                        if self.message.len() < MAX_MESSAGE_LEN {
+                            self.message.push(byte);
+                        } else {
+                            self.in_sysex = false;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Decode a chunk of MIDI bytes received at the given time, in any unit,
+    /// appending the events to a write stream.
+    pub fn decode_timed(&mut self, time: u64, bytes: &[u8], events: &mut Vec<TimedEvent>) {
+        self.decode(bytes, |event| events.push(TimedEvent { time, event }));
+    }
+
+    fn decode_message<F: FnMut(AsidEvent)>(&mut self, f: &mut F) {
+        let message = &self.message;
+        if message.len() < 2 || message[0] != ASID_ID {
+            return;
+        }
+        match message[1] {
+            CMD_START => {
+                self.playing = true;
+                f(AsidEvent::Start);
+            }
+            CMD_STOP => {
+                self.playing = false;
+                f(AsidEvent::Stop);
+            }
+            CMD_UPDATE => Self::decode_update(0, &message[2..], f),
+            CMD_UPDATE_SID2 => Self::decode_update(1, &message[2..], f),
+            CMD_UPDATE_SID3 => Self::decode_update(2, &message[2..], f),
+            // E.g. display text.
+            _ => {}
+        }
+    }
+
+    /// Decode the 4 mask bytes, 4 MSB bytes and the data bytes of an update.
+    fn decode_update<F: FnMut(AsidEvent)>(sid: usize, data: &[u8], f: &mut F) {
+        if data.len() < 8 {
+            return;
+        }
+        let (mask, rest) = data.split_at(4);
+        let (msb, values) = rest.split_at(4);
+        let count = mask
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum::<usize>();
+        if values.len() != count {
+            return;
+        }
+        let mut values = values.iter();
+        for (i, &reg) in ASID_REGISTERS.iter().enumerate() {
+            let (byte, bit) = (i / 7, i % 7);
+            if mask[byte] & (1 << bit) != 0 {
+                let msb = (msb[byte] >> bit) & 0x01;
+                let value = values.next().unwrap() | msb << 7;
+                f(AsidEvent::Write { sid, reg, value });
+            }
+        }
+    }
+}
+
+/// ASID playback target, applying the decoded events to up to three SIDs.
+/// Start and stop messages reset the SIDs.
+#[derive(Default)]
+pub struct AsidPlayer {
+    decoder: AsidDecoder,
+}
+
+impl AsidPlayer {
+    pub fn get_decoder(&self) -> &AsidDecoder {
+        &self.decoder
+    }
+
+    /// Decode a chunk of MIDI bytes and apply the events. Writes to SIDs
+    /// beyond the ones given are dropped.
+    pub fn play(&mut self, sids: &mut [Sid], bytes: &[u8]) {
+        self.decoder.decode(bytes, |event| match event {
+            AsidEvent::Start | AsidEvent::Stop => {
+                for sid in sids.iter_mut() {
+                    sid.reset();
+                }
+            }
+            AsidEvent::Write { sid, reg, value } => {
+                if let Some(sid) = sids.get_mut(sid) {
+                    sid.write(reg, value);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,152 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+use super::chip_profile::ChipProfile;
+use super::error::Error;
+use super::sampler::SamplingMethod;
+use super::sid::Sid;
+use super::ChipModel;
+
+/// Voice control registers, CR1 to CR3.
+const CONTROL_REGISTERS: [usize; 3] = [0x04, 0x0b, 0x12];
+
+/// System clock frequency, i.e. the CPU clock of the machine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Clock {
+    /// PAL C64 with the 6569 VIC-II.
+    Pal,
+    /// NTSC C64 with the 6567R8 VIC-II.
+    Ntsc,
+    /// Early NTSC C64 with the 6567R56A VIC-II, which has 64 instead of 65
+    /// cycles per line, but the same clock frequency.
+    NtscOld,
+    /// Drean C64 (PAL-N) with the 6572 VIC-II.
+    Drean,
+    /// Custom clock frequency in Hz.
+    Custom(u32),
+}
+
+impl Clock {
+    /// Clock frequency in Hz.
+    pub fn frequency(self) -> u32 {
+        match self {
+            Clock::Pal => 985_248,
+            Clock::Ntsc | Clock::NtscOld => 1_022_727,
+            Clock::Drean => 1_023_440,
+            Clock::Custom(clock_freq) => clock_freq,
+        }
+    }
+}
+
+/// Builder for a fully configured SID.
+///
+/// ```
+/// use resid::{ChipModel, Clock, SamplingMethod, SidBuilder};
+///
+/// let sid = SidBuilder::new(ChipModel::Mos8580)
+///     .clock(Clock::Ntsc)
+///     .sample_freq(48000)
+///     .sampling_method(SamplingMethod::Resample)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct SidBuilder {
+    profile: ChipProfile,
+    // Whether the profile is the preset of its chip model, see
+    // Sid::start_recording.
+    preset: bool,
+    clock: Clock,
+    sample_freq: u32,
+    sampling_method: SamplingMethod,
+    filter: bool,
+    external_filter: bool,
+    registers: Option<[u8; 0x19]>,
+}
+
+impl SidBuilder {
+    /// Start from the defaults of Sid::new, i.e. a PAL clock, a sample
+    /// frequency of 44.1kHz with fast sampling, and both filters enabled.
+    pub fn new(chip_model: ChipModel) -> Self {
+        SidBuilder {
+            preset: true,
+            ..SidBuilder::with_profile(ChipProfile::new(chip_model))
+        }
+    }
+
+    pub fn with_profile(profile: ChipProfile) -> Self {
+        SidBuilder {
+            profile,
+            preset: false,
+            clock: Clock::Pal,
+            sample_freq: 44100,
+            sampling_method: SamplingMethod::Fast,
+            filter: true,
+            external_filter: true,
+            registers: None,
+        }
+    }
+
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn sample_freq(mut self, sample_freq: u32) -> Self {
+        self.sample_freq = sample_freq;
+        self
+    }
+
+    pub fn sampling_method(mut self, sampling_method: SamplingMethod) -> Self {
+        self.sampling_method = sampling_method;
+        self
+    }
+
+    pub fn filter(mut self, enabled: bool) -> Self {
+        self.filter = enabled;
+        self
+    }
+
+    pub fn external_filter(mut self, enabled: bool) -> Self {
+        self.external_filter = enabled;
+        self
+    }
+
+    /// Initial values of the write-only registers 0x00 to 0x18, written in
+    /// order once the SID is configured, except for the voice control
+    /// registers, which are written last.
+    pub fn registers(mut self, registers: [u8; 0x19]) -> Self {
+        self.registers = Some(registers);
+        self
+    }
+
+    /// Validate the configuration and create the SID.
+    pub fn build(self) -> Result<Sid, Error> {
+        let mut sid = if self.preset {
+            Sid::new(self.profile.chip_model)
+        } else {
+            Sid::with_profile(&self.profile)
+        };
+        sid.set_sampling_parameters(
+            self.sampling_method,
+            self.clock.frequency(),
+            self.sample_freq,
+        )?;
+        sid.enable_filter(self.filter);
+        sid.enable_external_filter(self.external_filter);
+        if let Some(registers) = self.registers {
+            // The control registers go last, so the envelope rates are in
+            // place when the gate opens.
+            for (reg, &value) in registers.iter().enumerate() {
+                if !CONTROL_REGISTERS.contains(&reg) {
+                    sid.write(reg as u8, value);
+                }
+            }
+            for &reg in CONTROL_REGISTERS.iter() {
+                sid.write(reg as u8, registers[reg]);
+            }
+        }
+        Ok(sid)
+    }
+}
@@ -0,0 +1,165 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::borrow::Cow;
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+
+use core::f64;
+
+use super::combined_waveform::CombinedWaveforms;
+use super::dac::DacConfig;
+use super::external_filter::{self, OutputStage};
+use super::filter;
+use super::voice;
+use super::wave;
+use super::ChipModel;
+
+/// Number of cycles the data bus retains the last value written, as used by
+/// reSID 1.0 for the whole byte: 0x1d00 cycles on the MOS6581 and 0xa2000
+/// cycles on the much slower MOS8580. The presets fade all bits at once.
+const BUS_VALUE_TTL_6581: [u32; 8] = [0x1d00; 8];
+const BUS_VALUE_TTL_8580: [u32; 8] = [0x000a_2000; 8];
+
+/// All per-chip parameters of the emulation.
+///
+/// The presets for the MOS6581 and the MOS8580 match the chips measured for
+/// reSID. Other chip revisions or clones may be modeled by adjusting the
+/// parameters, see Sid::with_profile.
+#[derive(Clone)]
+pub struct ChipProfile {
+    /// Chip model the parameters are based on, e.g. as recorded in write
+    /// logs.
+    pub chip_model: ChipModel,
+    // Voice
+    /// Waveform output "zero" level, see voice.rs.
+    pub wave_zero: i32,
+    /// DC offset of the envelope multiplying DAC, see voice.rs.
+    pub voice_dc: i32,
+    // Envelope Generator
+    /// Model the pipeline delays of the envelope generator as in reSID 1.0.
+    /// reSID 0.16 steps the envelope without delays.
+    pub envelope_delays: bool,
+    // Waveform Generator
+    /// Combined waveform lookup tables.
+    pub combined_waveforms: CombinedWaveforms,
+    /// Number of cycles the noise shift register is clocked after
+    /// accumulator bit 19 is set high. reSID 0.16 clocks it on the same
+    /// cycle, i.e. 0.
+    pub shift_delay: u8,
+    /// Number of cycles the test bit must be held to reset the noise shift
+    /// register.
+    pub shift_register_reset: u32,
+    /// Number of cycles the waveform DAC input holds its value after the
+    /// waveform has been deselected.
+    pub floating_output_ttl: u32,
+    /// Number of cycles between each subsequent bit fade of the floating
+    /// waveform DAC input.
+    pub floating_output_fade: u32,
+    /// Maximum change of the waveform output per cycle with edge slew
+    /// enabled.
+    pub slew_rate: u16,
+    // Filter
+    /// FC to cutoff frequency mapping, as spline interpolation points.
+    pub f0_points: Cow<'static, [(i32, i32)]>,
+    /// Filter quality factor at resonance 0x0 and 0xf. Q is interpolated
+    /// linearly in between.
+    pub q_range: (f64, f64),
+    /// Mixer input DC offset, see filter.rs.
+    pub mixer_dc: i32,
+    // External Filter
+    /// Maximum DC output level of the mixer, removed if the external filter
+    /// is disabled.
+    pub ext_filter_dc: i32,
+    /// Audio output stage RC networks.
+    pub output_stage: OutputStage,
+    // Data Bus
+    /// Number of cycles each bit of the last value written is retained on the
+    /// data bus, and read back from the write-only registers. The bits may be
+    /// given different times to model chips where they fade one by one.
+    pub bus_value_ttl: [u32; 8],
+    // DACs
+    /// Waveform, envelope and volume DAC configuration, or None for ideal
+    /// linear DACs.
+    pub dac: Option<DacConfig>,
+}
+
+impl ChipProfile {
+    pub fn new(chip_model: ChipModel) -> Self {
+        match chip_model {
+            ChipModel::Mos6581 => ChipProfile {
+                chip_model,
+                wave_zero: voice::WAVE_ZERO_6581,
+                voice_dc: voice::VOICE_DC_6581,
+                envelope_delays: true,
+                combined_waveforms: CombinedWaveforms::new(chip_model),
+                shift_delay: wave::SHIFT_DELAY,
+                shift_register_reset: wave::SHIFT_REGISTER_RESET_6581,
+                floating_output_ttl: wave::FLOATING_OUTPUT_TTL_6581,
+                floating_output_fade: wave::FLOATING_OUTPUT_FADE_6581,
+                slew_rate: wave::SLEW_RATE_6581,
+                f0_points: Cow::Borrowed(&filter::FO_POINTS_6581[..]),
+                q_range: filter::Q_RANGE,
+                mixer_dc: filter::MIXER_DC_6581,
+                ext_filter_dc: external_filter::MIXER_DC_6581,
+                output_stage: OutputStage::c64(),
+                bus_value_ttl: BUS_VALUE_TTL_6581,
+                dac: None,
+            },
+            // No DC offsets in the MOS8580.
+            ChipModel::Mos8580 => ChipProfile {
+                chip_model,
+                wave_zero: voice::WAVE_ZERO_8580,
+                voice_dc: 0,
+                envelope_delays: true,
+                combined_waveforms: CombinedWaveforms::new(chip_model),
+                shift_delay: wave::SHIFT_DELAY,
+                shift_register_reset: wave::SHIFT_REGISTER_RESET_8580,
+                floating_output_ttl: wave::FLOATING_OUTPUT_TTL_8580,
+                floating_output_fade: wave::FLOATING_OUTPUT_FADE_8580,
+                slew_rate: wave::SLEW_RATE_8580,
+                f0_points: Cow::Borrowed(&filter::FO_POINTS_8580[..]),
+                q_range: filter::Q_RANGE,
+                mixer_dc: 0,
+                ext_filter_dc: 0,
+                output_stage: OutputStage::c64(),
+                bus_value_ttl: BUS_VALUE_TTL_8580,
+                dac: None,
+            },
+            ChipModel::Clean => ChipProfile {
+                chip_model,
+                f0_points: Cow::Borrowed(&FO_POINTS_LINEAR[..]),
+                q_range: Q_RANGE_IDEAL,
+                output_stage: OutputStage::dc_only(),
+                ..ChipProfile::new(ChipModel::Mos8580)
+            },
+        }
+    }
+
+    /// Idealized chip without DC offsets, with the linear FC to cutoff
+    /// frequency mapping of the specification, a filter which starts out
+    /// without resonance, and with the audio output stage reduced to DC
+    /// removal. The oscillators and envelopes are exact, and the remaining
+    /// parameters are those of the MOS8580, which has none of the MOS6581
+    /// quirks.
+    pub fn clean() -> Self {
+        ChipProfile::new(ChipModel::Clean)
+    }
+}
+
+/// Linear mapping of FC to cutoff frequency from 30Hz to 12kHz, as specified
+/// in the data sheet. The end points are repeated to make the spline linear.
+static FO_POINTS_LINEAR: [(i32, i32); 4] = [(0, 30), (0, 30), (2047, 12000), (2047, 12000)];
+
+/// Q range of the chips shifted to start exactly at the Butterworth response,
+/// Q = 1/sqrt(2), i.e. with a flat passband at zero resonance.
+const Q_RANGE_IDEAL: (f64, f64) = (f64::consts::FRAC_1_SQRT_2, f64::consts::FRAC_1_SQRT_2 + 1.0);
+
+impl From<ChipModel> for ChipProfile {
+    fn from(chip_model: ChipModel) -> Self {
+        ChipProfile::new(chip_model)
+    }
+}
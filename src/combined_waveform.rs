@@ -0,0 +1,215 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
+
+#[cfg(not(feature = "std"))]
+use alloc::borrow::Cow;
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+
+use super::data;
+use super::ChipModel;
+
+/// Parameters of the combined waveform model for one waveform combination.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WaveformConfig {
+    /// Threshold above which an output bit is pulled high.
+    pub bias: f32,
+    /// Strength of the pulse waveform pulling the bits up.
+    pub pulse_strength: f32,
+    /// Attenuation of the sawtooth MSB.
+    pub top_bit: f32,
+    /// Falloff of the influence of neighboring bits on each other.
+    pub distance: f32,
+    /// Mix between sawtooth and triangle bits.
+    pub st_mix: f32,
+}
+
+impl WaveformConfig {
+    pub fn new(bias: f32, pulse_strength: f32, top_bit: f32, distance: f32, st_mix: f32) -> Self {
+        WaveformConfig {
+            bias,
+            pulse_strength,
+            top_bit,
+            distance,
+            st_mix,
+        }
+    }
+}
+
+/// Parametric model of the combined waveforms, as found in reSIDfp.
+///
+/// When waveforms are combined, the waveform selector transistors connect
+/// the outputs of the selected waveforms, and the bits of the resulting
+/// output pull each other up or down depending on their distance. The output
+/// DAC then reads each bit as high or low depending on a threshold.
+///
+/// The model is an approximation which is fitted per chip, thus the presets
+/// do not exactly match the sampled tables used by default.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CombinedWaveformModel {
+    pub st: WaveformConfig,
+    pub pt: WaveformConfig,
+    pub ps: WaveformConfig,
+    pub pst: WaveformConfig,
+}
+
+impl CombinedWaveformModel {
+    /// Parameters fitted by reSIDfp to samples of a MOS6581 and a MOS8580
+    /// chip, respectively. The clean chip uses the MOS8580 parameters.
+    pub fn new(chip_model: ChipModel) -> Self {
+        match chip_model {
+            ChipModel::Mos6581 => CombinedWaveformModel {
+                st: WaveformConfig::new(0.880_815, 0.0, 0.0, 0.327_961_4, 0.599_954_5),
+                pt: WaveformConfig::new(0.892_461_8, 2.014_781, 1.003_332, 0.029_923_22, 0.0),
+                ps: WaveformConfig::new(0.864_650_1, 1.712_586, 1.137_704, 0.028_454_23, 0.0),
+                pst: WaveformConfig::new(0.952_783_4, 1.794_777, 0.0, 0.098_062_72, 0.775_248_2),
+            },
+            ChipModel::Mos8580 | ChipModel::Clean => CombinedWaveformModel {
+                st: WaveformConfig::new(0.978_166_5, 0.0, 0.989_946_9, 8.087_667, 0.822_641_2),
+                pt: WaveformConfig::new(0.909_776_9, 2.039_997, 0.958_409_6, 0.176_544_7, 0.0),
+                ps: WaveformConfig::new(0.923_121_2, 2.084_788, 0.949_389_5, 0.171_251_8, 0.0),
+                pst: WaveformConfig::new(
+                    0.984_555_2,
+                    1.415_612,
+                    0.970_388_3,
+                    3.688_29,
+                    0.826_500_8,
+                ),
+            },
+        }
+    }
+
+    /// 12-bit output of a combined waveform given the upper 12 bits of the
+    /// accumulator. The waveform is given as the upper 4 bits of the control
+    /// register, and the pulse is assumed to be high.
+    pub fn output(&self, waveform: u8, acc: u16) -> u16 {
+        let config = match waveform & 0x07 {
+            0x3 => &self.st,
+            0x5 => &self.pt,
+            0x6 => &self.ps,
+            0x7 => &self.pst,
+            _ => return 0,
+        };
+        calculate_combined_waveform(config, waveform & 0x07, acc)
+    }
+}
+
+/// Combined waveform lookup tables, indexed by the upper 12 bits of the
+/// accumulator. Each entry holds the upper 8 bits of the 12-bit output.
+#[derive(Clone)]
+pub struct CombinedWaveforms {
+    pub st: Cow<'static, [u8]>,
+    pub pt: Cow<'static, [u8]>,
+    pub ps: Cow<'static, [u8]>,
+    pub pst: Cow<'static, [u8]>,
+}
+
+impl CombinedWaveforms {
+    /// Tables sampled from real chips. This is the default. The clean chip
+    /// uses the MOS8580 tables.
+    pub fn new(chip_model: ChipModel) -> Self {
+        match chip_model {
+            ChipModel::Mos6581 => CombinedWaveforms {
+                st: Cow::Borrowed(&data::WAVE6581_ST[..]),
+                pt: Cow::Borrowed(&data::WAVE6581_PT[..]),
+                ps: Cow::Borrowed(&data::WAVE6581_PS[..]),
+                pst: Cow::Borrowed(&data::WAVE6581_PST[..]),
+            },
+            ChipModel::Mos8580 | ChipModel::Clean => CombinedWaveforms {
+                st: Cow::Borrowed(&data::WAVE8580_ST[..]),
+                pt: Cow::Borrowed(&data::WAVE8580_PT[..]),
+                ps: Cow::Borrowed(&data::WAVE8580_PS[..]),
+                pst: Cow::Borrowed(&data::WAVE8580_PST[..]),
+            },
+        }
+    }
+
+    /// Tables generated from the parametric model.
+    pub fn from_model(model: &CombinedWaveformModel) -> Self {
+        let build_table = |waveform: u8| {
+            (0..4096)
+                .map(|acc| (model.output(waveform, acc) >> 4) as u8)
+                .collect::<Vec<u8>>()
+        };
+        CombinedWaveforms {
+            st: Cow::Owned(build_table(0x3)),
+            pt: Cow::Owned(build_table(0x5)),
+            ps: Cow::Owned(build_table(0x6)),
+            pst: Cow::Owned(build_table(0x7)),
+        }
+    }
+}
+
+fn calculate_combined_waveform(config: &WaveformConfig, waveform: u8, acc: u16) -> u16 {
+    let mut o = [0f32; 12];
+
+    // Sawtooth.
+    for (i, bit) in o.iter_mut().enumerate() {
+        *bit = if acc & (1 << i) != 0 { 1.0 } else { 0.0 };
+    }
+
+    if waveform & 0x2 == 0 {
+        // Triangle: the lower 11 bits are shifted up and inverted by the MSB.
+        let msb = acc & 0x800 != 0;
+        for i in (1..12).rev() {
+            o[i] = if msb { 1.0 - o[i - 1] } else { o[i - 1] };
+        }
+        o[0] = 0.0;
+    } else if waveform & 0x3 == 0x3 {
+        // Sawtooth and triangle: the bits are interconnected.
+        // The bottom bit is grounded via the triangle waveform selector.
+        o[0] *= config.st_mix;
+        for i in 1..12 {
+            o[i] = o[i - 1] * (1.0 - config.st_mix) + o[i] * config.st_mix;
+        }
+    }
+
+    // The sawtooth MSB.
+    if waveform & 0x2 != 0 {
+        o[11] *= config.top_bit;
+    }
+
+    // ST and P* waveforms: neighboring bits pull each other.
+    if waveform == 0x3 || waveform > 0x4 {
+        let mut distance_table = [0f32; 12 * 2 + 1];
+        distance_table[12] = 1.0;
+        for i in 1..13 {
+            let weight = 1.0 / (1.0 + (i * i) as f32 * config.distance);
+            distance_table[12 - i] = weight;
+            distance_table[12 + i] = weight;
+        }
+
+        let mut tmp = [0f32; 12];
+        for (i, bit) in tmp.iter_mut().enumerate() {
+            let mut avg = 0.0;
+            let mut n = 0.0;
+            for (j, &o_j) in o.iter().enumerate() {
+                let weight = distance_table[i + 12 - j];
+                avg += o_j * weight;
+                n += weight;
+            }
+            // The pulse is connected to the bit above the MSB.
+            if waveform > 0x4 {
+                let weight = distance_table[i];
+                avg += config.pulse_strength * weight;
+                n += weight;
+            }
+            *bit = (o[i] + avg / n) * 0.5;
+        }
+        o = tmp;
+    }
+
+    let mut value = 0u16;
+    for (i, &bit) in o.iter().enumerate() {
+        if bit > config.bias {
+            value |= 1 << i;
+        }
+    }
+    value
+}
@@ -0,0 +1,112 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
+use super::ChipModel;
+
+/// Configuration of an R-2R ladder DAC.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DacConfig {
+    /// Ratio of the 2R and R resistances.
+    pub two_r_div_r: f64,
+    /// Whether the ladder is terminated by a 2R resistor.
+    pub term: bool,
+}
+
+impl DacConfig {
+    pub fn new(two_r_div_r: f64, term: bool) -> Self {
+        DacConfig { two_r_div_r, term }
+    }
+
+    /// The MOS6581 DACs are lacking the termination resistor, and the
+    /// 2R/R ratio is approximately 2.20. The MOS8580 DACs are correctly
+    /// terminated and have a 2R/R ratio of 2.00, i.e. they are linear, as are
+    /// the DACs of the clean chip.
+    pub fn from_chip_model(chip_model: ChipModel) -> Self {
+        match chip_model {
+            ChipModel::Mos6581 => DacConfig::new(2.20, false),
+            ChipModel::Mos8580 | ChipModel::Clean => DacConfig::new(2.00, true),
+        }
+    }
+}
+
+/// The SID DACs are built up as R-2R ladder as follows:
+/// ``` ignore,
+///          n  n-1      2   1   0    VGND
+///          |   |       |   |   |      |   Termination
+///         2R  2R      2R  2R  2R     2R   only for
+///          |   |       |   |   |      |   MOS 8580
+///      Vo  --R---R--...--R---R--    ---
+/// ```
+/// All MOS 6581 DACs are missing a termination resistor at bit 0. This causes
+/// pronounced errors for the lower 4 - 5 bits (e.g. the output for bit 0 is
+/// actually equal to the output for bit 1), resulting in DAC discontinuities
+/// for the lower bits.
+/// In addition to this, the 6581 DACs exhibit further severe discontinuities
+/// for higher bits, which may be explained by a less than perfect match between
+/// the R and 2R resistors, or by output impedance in the NMOS transistors
+/// providing the bit voltages. A good approximation of the actual DAC output
+/// is achieved for 2R/R ~ 2.20.
+///
+/// The table maps each input value to the DAC output, scaled so that an ideal
+/// DAC (correctly terminated, 2R/R = 2) maps each input value x to x * scale.
+pub fn build_dac_table(bits: usize, config: DacConfig, scale: f64) -> Vec<u16> {
+    let mut vbit = vec![0f64; bits];
+    let r = 1.0;
+    let r2 = config.two_r_div_r * r;
+    for (set_bit, v) in vbit.iter_mut().enumerate() {
+        // Normalized bit voltage.
+        let mut vn = 1.0;
+        // Rn = 2R for correct termination, infinity for missing termination.
+        let mut rn = if config.term { Some(r2) } else { None };
+
+        // Calculate DAC "tail" resistance by repeated parallel substitution.
+        for _bit in 0..set_bit {
+            rn = Some(match rn {
+                None => r + r2,
+                Some(rn) => r + r2 * rn / (r2 + rn), // R + 2R || Rn
+            });
+        }
+
+        // Source transformation for bit voltage.
+        let mut rn = match rn {
+            None => r2,
+            Some(rn) => {
+                let rn = r2 * rn / (r2 + rn); // 2R || Rn
+                vn = vn * rn / r2;
+                rn
+            }
+        };
+
+        // Calculate DAC output voltage by repeated source transformation from
+        // the "tail".
+        for _bit in (set_bit + 1)..bits {
+            rn += r;
+            let i = vn / rn;
+            rn = r2 * rn / (r2 + rn); // 2R || Rn
+            vn = rn * i;
+        }
+
+        *v = vn;
+    }
+
+    // Calculate the voltage for any combination of bits by superpositioning.
+    let max = (1 << bits) as f64 * scale;
+    (0..(1usize << bits))
+        .map(|i| {
+            let vo = vbit
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| i & (1 << j) != 0)
+                .map(|(_, v)| v)
+                .sum::<f64>();
+            (max * vo + 0.5) as u16
+        })
+        .collect()
+}
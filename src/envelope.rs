@@ -41,6 +41,10 @@ const RATE_COUNTER_MSB_MASK: u16 = 0x8000;
 // sustain = release = 0. This ensures that the attack state will not lower
 // the current rate counter period.
 //
+// The values below are the rate counter comparison values, i.e. the rate
+// counter periods minus one, as the rate counter is zeroed one cycle after it
+// has reached the comparison value, see clock().
+//
 // The ENV3 sampling code below yields a maximum timing error of 14 cycles.
 //     lda #$01
 // l1: cmp $d41c
@@ -54,23 +58,23 @@ const RATE_COUNTER_MSB_MASK: u16 = 0x8000;
 // The described method is thus sufficient for exact calculation of the rate
 // periods.
 //
-static RATE_COUNTER_PERIOD: [u16; 16] = [
-    9,     // 2ms*1.0MHz/256 = 7.81
-    32,    // 8ms*1.0MHz/256 = 31.25
-    63,    // 16ms*1.0MHz/256 = 62.50
-    95,    // 24ms*1.0MHz/256 = 93.75
-    149,   // 38ms*1.0MHz/256 = 148.44
-    220,   // 56ms*1.0MHz/256 = 218.75
-    267,   // 68ms*1.0MHz/256 = 265.63
-    313,   // 80ms*1.0MHz/256 = 312.50
-    392,   // 100ms*1.0MHz/256 = 390.63
-    977,   // 250ms*1.0MHz/256 = 976.56
-    1954,  // 500ms*1.0MHz/256 = 1953.13
-    3126,  // 800ms*1.0MHz/256 = 3125.00
-    3907,  // 1 s*1.0MHz/256 =  3906.25
-    11720, // 3 s*1.0MHz/256 = 11718.75
-    19532, // 5 s*1.0MHz/256 = 19531.25
-    31251, // 8 s*1.0MHz/256 = 31250.00
+pub(crate) static RATE_COUNTER_PERIOD: [u16; 16] = [
+    8,     // 2ms*1.0MHz/256 = 7.81
+    31,    // 8ms*1.0MHz/256 = 31.25
+    62,    // 16ms*1.0MHz/256 = 62.50
+    94,    // 24ms*1.0MHz/256 = 93.75
+    148,   // 38ms*1.0MHz/256 = 148.44
+    219,   // 56ms*1.0MHz/256 = 218.75
+    266,   // 68ms*1.0MHz/256 = 265.63
+    312,   // 80ms*1.0MHz/256 = 312.50
+    391,   // 100ms*1.0MHz/256 = 390.63
+    976,   // 250ms*1.0MHz/256 = 976.56
+    1953,  // 500ms*1.0MHz/256 = 1953.13
+    3125,  // 800ms*1.0MHz/256 = 3125.00
+    3906,  // 1 s*1.0MHz/256 =  3906.25
+    11719, // 3 s*1.0MHz/256 = 11718.75
+    19531, // 5 s*1.0MHz/256 = 19531.25
+    31250, // 8 s*1.0MHz/256 = 31250.00
 ];
 
 /// From the sustain levels it follows that both the low and high 4 bits of the
@@ -80,7 +84,7 @@ static SUSTAIN_LEVEL: [u8; 16] = [
     0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
 ];
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum State {
     Attack,
     DecaySustain,
@@ -94,8 +98,14 @@ pub enum State {
 /// decay, in effect further dividing the clock to the envelope counter.
 /// The period of this counter is set to 1, 2, 4, 8, 16, 30 at the envelope
 /// counter values 255, 93, 54, 26, 14, 6, respectively.
+///
+/// Gate changes, the rate counter reset, exponential counter period changes
+/// and envelope counter steps are subject to one to four cycle pipeline
+/// delays, as in reSID 1.0. Without the delays the envelope is stepped as in
+/// reSID 0.16, see set_delays().
 pub struct EnvelopeGenerator {
     // Configuration
+    delays: bool,
     attack: u8,
     decay: u8,
     sustain: u8,
@@ -104,29 +114,44 @@ pub struct EnvelopeGenerator {
     gate: bool,
     // Runtime State
     pub state: State,
+    pub next_state: State,
+    pub state_pipeline: u8,
     pub envelope_counter: u8,
+    pub envelope_pipeline: u8,
+    pub env3: u8,
     pub exponential_counter: u8,
+    pub exponential_pipeline: u8,
     pub exponential_counter_period: u8,
+    pub new_exponential_counter_period: u8,
     pub hold_zero: bool,
     pub rate_counter: u16,
     pub rate_counter_period: u16,
+    pub reset_rate_counter: bool,
 }
 
 impl Default for EnvelopeGenerator {
     fn default() -> Self {
         let mut envelope = EnvelopeGenerator {
+            delays: true,
             attack: 0,
             decay: 0,
             sustain: 0,
             release: 0,
             gate: false,
             state: State::Release,
+            next_state: State::Release,
+            state_pipeline: 0,
             envelope_counter: 0,
+            envelope_pipeline: 0,
+            env3: 0,
             exponential_counter: 0,
+            exponential_pipeline: 0,
             exponential_counter_period: 0,
+            new_exponential_counter_period: 0,
             hold_zero: false,
             rate_counter: 0,
             rate_counter_period: 0,
+            reset_rate_counter: false,
         };
         envelope.reset();
         envelope
@@ -148,6 +173,26 @@ impl EnvelopeGenerator {
         self.sustain << 4 | self.release
     }
 
+    /// Model the pipeline delays of reSID 1.0, or step the envelope on the
+    /// cycle the rate counter reaches the rate period as in reSID 0.16.
+    /// Pending state changes are completed, and pending envelope steps are
+    /// dropped.
+    pub fn set_delays(&mut self, enabled: bool) {
+        if !enabled {
+            while self.state_pipeline != 0 {
+                self.state_change();
+            }
+            if self.new_exponential_counter_period != 0 {
+                self.exponential_counter_period = self.new_exponential_counter_period;
+                self.new_exponential_counter_period = 0;
+            }
+            self.envelope_pipeline = 0;
+            self.exponential_pipeline = 0;
+            self.reset_rate_counter = false;
+        }
+        self.delays = enabled;
+    }
+
     pub fn set_attack_decay(&mut self, value: u8) {
         self.attack = (value >> 4) & 0x0f;
         self.decay = value & 0x0f;
@@ -160,18 +205,46 @@ impl EnvelopeGenerator {
         }
     }
 
+    /// The rate counter is never reset on gate changes, thus there will be a
+    /// delay before the envelope counter starts counting up (attack) or down
+    /// (release). The state change itself is delayed by the state pipeline,
+    /// see state_change().
     pub fn set_control(&mut self, value: u8) {
         let gate = value.get_bit(0);
-        if !self.gate && gate {
+        if !self.delays {
+            if !self.gate && gate {
+                // Gate bit on: Start attack, decay, sustain.
+                self.state = State::Attack;
+                self.rate_counter_period = RATE_COUNTER_PERIOD[self.attack as usize];
+                // Switching to attack state unlocks the zero freeze.
+                self.hold_zero = false;
+            } else if self.gate && !gate {
+                // Gate bit off: Start release.
+                self.state = State::Release;
+                self.rate_counter_period = RATE_COUNTER_PERIOD[self.release as usize];
+            }
+        } else if !self.gate && gate {
             // Gate bit on: Start attack, decay, sustain.
-            self.state = State::Attack;
-            self.rate_counter_period = RATE_COUNTER_PERIOD[self.attack as usize];
-            // Switching to attack state unlocks the zero freeze.
-            self.hold_zero = false;
+            self.next_state = State::Attack;
+            self.state_pipeline = 2;
+            // A rate counter reset or exponential counter reset in progress
+            // delays the first envelope step, or the attack state.
+            if self.reset_rate_counter || self.exponential_pipeline == 2 {
+                self.envelope_pipeline =
+                    if self.exponential_counter_period == 1 || self.exponential_pipeline == 2 {
+                        2
+                    } else {
+                        4
+                    };
+            } else if self.exponential_pipeline == 1 {
+                self.state_pipeline = 3;
+            }
         } else if self.gate && !gate {
             // Gate bit off: Start release.
-            self.state = State::Release;
-            self.rate_counter_period = RATE_COUNTER_PERIOD[self.release as usize];
+            // A pending envelope counter decrement delays the release by one
+            // more cycle.
+            self.next_state = State::Release;
+            self.state_pipeline = if self.envelope_pipeline != 0 { 3 } else { 2 };
         }
         self.gate = gate;
     }
@@ -186,150 +259,167 @@ impl EnvelopeGenerator {
 
     #[inline]
     pub fn clock(&mut self) {
-        // Check for ADSR delay bug.
-        // If the rate counter comparison value is set below the current value of the
-        // rate counter, the counter will continue counting up until it wraps around
-        // to zero at 2^15 = 0x8000, and then count rate_period - 1 before the
-        // envelope can finally be stepped.
-        // This has been verified by sampling ENV3.
-        self.rate_counter += 1;
-        if self.rate_counter & RATE_COUNTER_MSB_MASK != 0 {
+        if !self.delays {
             self.rate_counter += 1;
-            self.rate_counter &= RATE_COUNTER_MASK;
+            if self.rate_counter & RATE_COUNTER_MSB_MASK != 0 {
+                self.rate_counter += 1;
+                self.rate_counter &= RATE_COUNTER_MASK;
+            }
+            if self.rate_counter == self.rate_counter_period + 1 {
+                self.rate_counter = 0;
+                self.step();
+            }
+            return;
         }
-        if self.rate_counter == self.rate_counter_period {
-            self.rate_counter = 0;
-            // The first envelope step in the attack state also resets the exponential
-            // counter. This has been verified by sampling ENV3.
-            self.exponential_counter += 1; // TODO check w/ ref impl
-            if self.state == State::Attack
-                || self.exponential_counter == self.exponential_counter_period
-            {
-                self.exponential_counter = 0;
-                // Check whether the envelope counter is frozen at zero.
-                if self.hold_zero {
-                    return;
-                }
+        // The ENV3 value is sampled at the first phase of the clock.
+        self.env3 = self.envelope_counter;
+        // A change of the exponential counter period takes effect one cycle
+        // after the envelope counter has reached the threshold value.
+        if self.new_exponential_counter_period != 0 {
+            self.exponential_counter_period = self.new_exponential_counter_period;
+            self.new_exponential_counter_period = 0;
+        }
+        if self.state_pipeline != 0 {
+            self.state_change();
+        }
+        // The envelope counter is stepped two cycles after the rate counter
+        // has been reset in the attack state, and one cycle after the
+        // exponential counter has been reset in the decay/sustain and release
+        // states.
+        if self.envelope_pipeline != 0 {
+            self.envelope_pipeline -= 1;
+            if self.envelope_pipeline == 0 && !self.hold_zero {
                 match self.state {
                     State::Attack => {
                         // The envelope counter can flip from 0xff to 0x00 by changing state to
                         // release, then to attack. The envelope counter is then frozen at
                         // zero; to unlock this situation the state must be changed to release,
                         // then to attack. This has been verified by sampling ENV3.
-                        self.envelope_counter += 1;
+                        self.envelope_counter = self.envelope_counter.wrapping_add(1);
                         if self.envelope_counter == 0xff {
                             self.state = State::DecaySustain;
                             self.rate_counter_period = RATE_COUNTER_PERIOD[self.decay as usize];
                         }
                     }
-                    State::DecaySustain => {
-                        if self.envelope_counter != SUSTAIN_LEVEL[self.sustain as usize] {
-                            self.envelope_counter -= 1;
-                        }
-                    }
-                    State::Release => {
+                    State::DecaySustain | State::Release => {
                         // The envelope counter can flip from 0x00 to 0xff by changing state to
                         // attack, then to release. The envelope counter will then continue
                         // counting down in the release state.
                         // This has been verified by sampling ENV3.
                         // NB! The operation below requires two's complement integer.
-                        self.envelope_counter -= 1;
+                        self.envelope_counter = self.envelope_counter.wrapping_sub(1);
                     }
                 }
-                // Check for change of exponential counter period.
-                match self.envelope_counter {
-                    0xff => self.exponential_counter_period = 1,
-                    0x5d => self.exponential_counter_period = 2,
-                    0x36 => self.exponential_counter_period = 4,
-                    0x1a => self.exponential_counter_period = 8,
-                    0x0e => self.exponential_counter_period = 16,
-                    0x06 => self.exponential_counter_period = 30,
-                    0x00 => {
-                        self.exponential_counter_period = 1;
-                        // When the envelope counter is changed to zero, it is frozen at zero.
-                        // This has been verified by sampling ENV3.
-                        self.hold_zero = true;
-                    }
-                    _ => {}
+                self.set_exponential_counter();
+            }
+        }
+        if self.exponential_pipeline != 0 {
+            self.exponential_pipeline -= 1;
+            if self.exponential_pipeline == 0 {
+                self.exponential_counter = 0;
+                if (self.state == State::DecaySustain
+                    && self.envelope_counter != SUSTAIN_LEVEL[self.sustain as usize])
+                    || self.state == State::Release
+                {
+                    self.envelope_pipeline = 1;
                 }
             }
+        } else if self.reset_rate_counter {
+            // The rate counter is reset one cycle after it has reached the
+            // rate period.
+            self.rate_counter = 0;
+            self.reset_rate_counter = false;
+            if self.state == State::Attack {
+                // The first envelope step in the attack state also resets the exponential
+                // counter. This has been verified by sampling ENV3.
+                self.exponential_counter = 0;
+                self.envelope_pipeline = 2;
+            } else if !self.hold_zero {
+                self.exponential_counter += 1;
+                if self.exponential_counter == self.exponential_counter_period {
+                    // The exponential counter is reset one cycle later if its
+                    // period != 1.
+                    self.exponential_pipeline = if self.exponential_counter_period != 1 {
+                        2
+                    } else {
+                        1
+                    };
+                }
+            }
+        }
+        // Check for ADSR delay bug.
+        // If the rate counter comparison value is set below the current value of the
+        // rate counter, the counter will continue counting up until it wraps around
+        // to zero at 2^15 = 0x8000, and then count rate_period - 1 before the
+        // envelope can finally be stepped.
+        // This has been verified by sampling ENV3.
+        if self.rate_counter != self.rate_counter_period {
+            self.rate_counter += 1;
+            if self.rate_counter & RATE_COUNTER_MSB_MASK != 0 {
+                self.rate_counter += 1;
+                self.rate_counter &= RATE_COUNTER_MASK;
+            }
+        } else {
+            self.reset_rate_counter = true;
         }
     }
 
+    /// The envelope is stepped through clock(), so all pipeline delays are
+    /// modeled exactly. Only the rate counter is advanced in bulk between
+    /// envelope steps, and ENV3 is sampled on the last cycle.
     #[inline]
     pub fn clock_delta(&mut self, mut delta: u32) {
-        // NB! This requires two's complement integer.
-        let mut rate_step = self.rate_counter_period as i32 - self.rate_counter as i32;
-        if rate_step <= 0 {
-            rate_step += 0x7fff;
-        }
-        while delta != 0 {
-            if delta < rate_step as u32 {
-                self.rate_counter += delta as u16;
-                if self.rate_counter & RATE_COUNTER_MSB_MASK != 0 {
-                    self.rate_counter += 1;
-                    self.rate_counter &= RATE_COUNTER_MASK;
+        if !self.delays {
+            // NB! This requires two's complement integer.
+            let mut rate_step = (self.rate_counter_period + 1) as i32 - self.rate_counter as i32;
+            if rate_step <= 0 {
+                rate_step += 0x7fff;
+            }
+            while delta != 0 {
+                if delta < rate_step as u32 {
+                    self.rate_counter += delta as u16;
+                    if self.rate_counter & RATE_COUNTER_MSB_MASK != 0 {
+                        self.rate_counter += 1;
+                        self.rate_counter &= RATE_COUNTER_MASK;
+                    }
+                    return;
                 }
-                return;
+                self.rate_counter = 0;
+                delta -= rate_step as u32;
+                self.step();
+                rate_step = (self.rate_counter_period + 1) as i32;
             }
-            self.rate_counter = 0;
-            delta -= rate_step as u32;
-            // The first envelope step in the attack state also resets the exponential
-            // counter. This has been verified by sampling ENV3.
-            self.exponential_counter += 1; // TODO check w/ ref impl
-            if self.state == State::Attack
-                || self.exponential_counter == self.exponential_counter_period
+            return;
+        }
+        while delta != 0 {
+            if self.state_pipeline == 0
+                && self.envelope_pipeline == 0
+                && self.exponential_pipeline == 0
+                && self.new_exponential_counter_period == 0
+                && !self.reset_rate_counter
+                && self.rate_counter != self.rate_counter_period
             {
-                self.exponential_counter = 0;
-                // Check whether the envelope counter is frozen at zero.
-                if self.hold_zero {
-                    rate_step = self.rate_counter_period as i32;
-                    continue;
-                }
-                match self.state {
-                    State::Attack => {
-                        // The envelope counter can flip from 0xff to 0x00 by changing state to
-                        // release, then to attack. The envelope counter is then frozen at
-                        // zero; to unlock this situation the state must be changed to release,
-                        // then to attack. This has been verified by sampling ENV3.
-                        self.envelope_counter += 1;
-                        if self.envelope_counter == 0xff {
-                            self.state = State::DecaySustain;
-                            self.rate_counter_period = RATE_COUNTER_PERIOD[self.decay as usize];
-                        }
-                    }
-                    State::DecaySustain => {
-                        if self.envelope_counter != SUSTAIN_LEVEL[self.sustain as usize] {
-                            self.envelope_counter -= 1;
-                        }
-                    }
-                    State::Release => {
-                        // The envelope counter can flip from 0x00 to 0xff by changing state to
-                        // attack, then to release. The envelope counter will then continue
-                        // counting down in the release state.
-                        // This has been verified by sampling ENV3.
-                        // NB! The operation below requires two's complement integer.
-                        self.envelope_counter -= 1;
-                    }
+                // Number of cycles until the rate counter reaches the rate
+                // period, including the wraparound of the ADSR delay bug.
+                // NB! This requires two's complement integer.
+                let mut rate_step = self.rate_counter_period as i32 - self.rate_counter as i32;
+                if rate_step < 0 {
+                    rate_step += 0x7fff;
                 }
-                // Check for change of exponential counter period.
-                match self.envelope_counter {
-                    0xff => self.exponential_counter_period = 1,
-                    0x5d => self.exponential_counter_period = 2,
-                    0x36 => self.exponential_counter_period = 4,
-                    0x1a => self.exponential_counter_period = 8,
-                    0x0e => self.exponential_counter_period = 16,
-                    0x06 => self.exponential_counter_period = 30,
-                    0x00 => {
-                        self.exponential_counter_period = 1;
-                        // When the envelope counter is changed to zero, it is frozen at zero.
-                        // This has been verified by sampling ENV3.
-                        self.hold_zero = true;
+                // Skip the cycles before the rate counter reset, leaving the
+                // last cycle to sample ENV3.
+                let skip = (rate_step as u32).min(delta - 1);
+                if skip != 0 {
+                    self.rate_counter += skip as u16;
+                    if self.rate_counter & RATE_COUNTER_MSB_MASK != 0 {
+                        self.rate_counter += 1;
+                        self.rate_counter &= RATE_COUNTER_MASK;
                     }
-                    _ => {}
+                    delta -= skip;
                 }
             }
-            rate_step = self.rate_counter_period as i32;
+            self.clock();
+            delta -= 1;
         }
     }
 
@@ -338,8 +428,13 @@ impl EnvelopeGenerator {
         self.envelope_counter
     }
 
+    /// ENV3 is read one cycle behind the envelope output.
     pub fn read_env(&self) -> u8 {
-        self.output()
+        if self.delays {
+            self.env3
+        } else {
+            self.envelope_counter
+        }
     }
 
     pub fn reset(&mut self) {
@@ -349,11 +444,109 @@ impl EnvelopeGenerator {
         self.release = 0;
         self.gate = false;
         self.state = State::Release;
+        self.next_state = State::Release;
+        self.state_pipeline = 0;
         self.envelope_counter = 0;
+        self.envelope_pipeline = 0;
+        self.env3 = 0;
         self.exponential_counter = 0;
+        self.exponential_pipeline = 0;
         self.exponential_counter_period = 1;
+        self.new_exponential_counter_period = 0;
         self.hold_zero = true;
         self.rate_counter = 0;
         self.rate_counter_period = RATE_COUNTER_PERIOD[self.release as usize];
+        self.reset_rate_counter = false;
+    }
+
+    /// Envelope step without pipeline delays, as in reSID 0.16.
+    fn step(&mut self) {
+        // The first envelope step in the attack state also resets the exponential
+        // counter. This has been verified by sampling ENV3.
+        self.exponential_counter += 1;
+        if self.state == State::Attack
+            || self.exponential_counter == self.exponential_counter_period
+        {
+            self.exponential_counter = 0;
+            // Check whether the envelope counter is frozen at zero.
+            if self.hold_zero {
+                return;
+            }
+            match self.state {
+                State::Attack => {
+                    self.envelope_counter = self.envelope_counter.wrapping_add(1);
+                    if self.envelope_counter == 0xff {
+                        self.state = State::DecaySustain;
+                        self.rate_counter_period = RATE_COUNTER_PERIOD[self.decay as usize];
+                    }
+                }
+                State::DecaySustain => {
+                    if self.envelope_counter != SUSTAIN_LEVEL[self.sustain as usize] {
+                        self.envelope_counter -= 1;
+                    }
+                }
+                State::Release => {
+                    // NB! The operation below requires two's complement integer.
+                    self.envelope_counter = self.envelope_counter.wrapping_sub(1);
+                }
+            }
+            // The exponential counter period changes immediately.
+            self.set_exponential_counter();
+            if self.new_exponential_counter_period != 0 {
+                self.exponential_counter_period = self.new_exponential_counter_period;
+                self.new_exponential_counter_period = 0;
+            }
+        }
+    }
+
+    /// Check for change of exponential counter period. The new period takes
+    /// effect on the next cycle.
+    #[inline]
+    fn set_exponential_counter(&mut self) {
+        match self.envelope_counter {
+            0xff => self.new_exponential_counter_period = 1,
+            0x5d => self.new_exponential_counter_period = 2,
+            0x36 => self.new_exponential_counter_period = 4,
+            0x1a => self.new_exponential_counter_period = 8,
+            0x0e => self.new_exponential_counter_period = 16,
+            0x06 => self.new_exponential_counter_period = 30,
+            0x00 => {
+                self.new_exponential_counter_period = 1;
+                // When the envelope counter is changed to zero, it is frozen at zero.
+                // This has been verified by sampling ENV3.
+                self.hold_zero = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// State pipeline:
+    /// On gate on, the decay rate is "accidentally" enabled during the first
+    /// cycle, and the attack state and rate are enabled on the second cycle.
+    /// On gate off, the release state is entered after two cycles from the
+    /// attack state, and after one cycle from the decay/sustain state.
+    fn state_change(&mut self) {
+        self.state_pipeline -= 1;
+        match self.next_state {
+            State::Attack => {
+                if self.state_pipeline == 1 {
+                    self.rate_counter_period = RATE_COUNTER_PERIOD[self.decay as usize];
+                } else if self.state_pipeline == 0 {
+                    self.state = State::Attack;
+                    self.rate_counter_period = RATE_COUNTER_PERIOD[self.attack as usize];
+                    // Switching to attack state unlocks the zero freeze.
+                    self.hold_zero = false;
+                }
+            }
+            State::DecaySustain => {}
+            State::Release => {
+                if (self.state == State::Attack && self.state_pipeline == 0)
+                    || (self.state == State::DecaySustain && self.state_pipeline == 1)
+                {
+                    self.state = State::Release;
+                    self.rate_counter_period = RATE_COUNTER_PERIOD[self.release as usize];
+                }
+            }
+        }
     }
 }
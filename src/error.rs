@@ -0,0 +1,75 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+use core::fmt;
+
+use super::sampler::SamplingMethod;
+
+/// Errors reported for invalid register offsets or configuration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// Register offset above 0x1c.
+    InvalidRegister(u8),
+    /// Voice number above 2.
+    InvalidVoice(usize),
+    /// Malformed or unsupported Standard MIDI File.
+    InvalidMidiFile(&'static str),
+    /// Malformed or unsupported register write log.
+    InvalidWriteLog(&'static str),
+    /// Recording of a SID with chip parameters or settings which are not
+    /// stored in the write log, see Sid::start_recording.
+    UnsupportedRecording,
+    /// Sample frequency of zero, or too low for the filter effect.
+    InvalidSampleFrequency(u32),
+    /// Sample frequency above the clock frequency.
+    SampleFrequencyAboveClock { clock_freq: u32, sample_freq: u32 },
+    /// Too many cycles per sample for the sampling method, see
+    /// SamplingMethod.
+    SampleFrequencyTooLow {
+        method: SamplingMethod,
+        clock_freq: u32,
+        sample_freq: u32,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidRegister(reg) => write!(f, "invalid register 0x{:02x}", reg),
+            Error::InvalidVoice(voice) => write!(f, "invalid voice {}", voice),
+            Error::InvalidMidiFile(reason) => write!(f, "invalid MIDI file: {}", reason),
+            Error::InvalidWriteLog(reason) => write!(f, "invalid write log: {}", reason),
+            Error::UnsupportedRecording => {
+                write!(
+                    f,
+                    "write log cannot record custom chip parameters or settings"
+                )
+            }
+            Error::InvalidSampleFrequency(sample_freq) => {
+                write!(f, "invalid sample frequency {}Hz", sample_freq)
+            }
+            Error::SampleFrequencyAboveClock {
+                clock_freq,
+                sample_freq,
+            } => write!(
+                f,
+                "sample frequency {}Hz above clock frequency {}Hz",
+                sample_freq, clock_freq
+            ),
+            Error::SampleFrequencyTooLow {
+                method,
+                clock_freq,
+                sample_freq,
+            } => write!(
+                f,
+                "sample frequency {}Hz too low for clock frequency {}Hz with {:?} sampling",
+                sample_freq, clock_freq, method
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
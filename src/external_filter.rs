@@ -3,12 +3,23 @@
 // Portions (c) 2004 Dag Lem <resid@nimrod.no>
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+use core::f64;
+
+use super::chip_profile::ChipProfile;
 use super::ChipModel;
 
+/// Approximately 20kHz. w0lp*delta_t*(Vi - Vlp) exceeds 32 bits at this limit
+/// and is computed in 64 bits in clock_delta.
+const W0_LP_MAX: i32 = 1 << 17;
+/// w0hp*delta_t*(Vlp - Vhp) is computed in 32 bits, with delta_t of up to 8
+/// cycles and Vlp - Vhp of up to 2^19 at full scale.
+const W0_HP_MAX: i32 = 1 << 8;
+
 /// Maximum mixer DC output level; to be removed if the external
 /// filter is turned off: ((wave DC + voice DC)*voices + mixer DC)*volume
 /// See voice.cc and filter.cc for an explanation of the values.
-const MIXER_DC_6581: i32 = ((((0x800 - 0x380) + 0x800) * 0xff * 3 - 0xfff * 0xff / 18) >> 7) * 0x0f;
+pub(crate) const MIXER_DC_6581: i32 =
+    ((((0x800 - 0x380) + 0x800) * 0xff * 3 - 0xfff * 0xff / 18) >> 7) * 0x0f;
 
 /// Low-pass:  R = 10kOhm, C = 1000pF; w0l = 1/RC = 1/(1e4*1e-9) = 100000
 /// High-pass: R =  1kOhm, C =   10uF; w0h = 1/RC = 1/(1e3*1e-5) =    100
@@ -17,6 +28,83 @@ const MIXER_DC_6581: i32 = ((((0x800 - 0x380) + 0x800) * 0xff * 3 - 0xfff * 0xff
 const W0_LP: i32 = 104_858;
 const W0_HP: i32 = 105;
 
+/// A first order RC network of the audio output stage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RcNetwork {
+    /// Resistance in Ohm.
+    pub r: f64,
+    /// Capacitance in Farad.
+    pub c: f64,
+}
+
+impl RcNetwork {
+    pub fn new(r: f64, c: f64) -> Self {
+        RcNetwork { r, c }
+    }
+
+    /// 3-dB frequency in Hz.
+    pub fn cutoff_hz(&self) -> f64 {
+        1.0 / (2.0 * f64::consts::PI * self.r * self.c)
+    }
+
+    /// w0 = 1/RC, multiplied with 1.048576 to facilitate division by 1 000 000
+    /// by right-shifting 20 times.
+    fn w0(&self) -> i32 {
+        (1.0 / (self.r * self.c) * 1.048_576 + 0.5) as i32
+    }
+}
+
+/// Configuration of the audio output stage, a low-pass STC network followed
+/// by a high-pass STC network. The low-pass stage may be omitted.
+///
+/// The low-pass 3-dB frequency is limited to approximately 20kHz to keep the
+/// delta_t cycle filter stable, and the high-pass 3-dB frequency to
+/// approximately 40Hz to keep its integer arithmetics from overflowing. Note
+/// that the integer arithmetics of the high-pass stage leave a residual DC
+/// level inversely proportional to its 3-dB frequency, so going much below
+/// 16Hz is not recommended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputStage {
+    pub low_pass: Option<RcNetwork>,
+    pub high_pass: RcNetwork,
+}
+
+impl OutputStage {
+    pub fn new(low_pass: Option<RcNetwork>, high_pass: RcNetwork) -> Self {
+        OutputStage {
+            low_pass,
+            high_pass,
+        }
+    }
+
+    /// Commodore 64, see ExternalFilter.
+    pub fn c64() -> Self {
+        OutputStage::new(
+            Some(RcNetwork::new(10e3, 1000e-12)),
+            RcNetwork::new(1e3, 10e-6),
+        )
+    }
+
+    /// SID card with an op-amp output buffer and no low-pass network, AC
+    /// coupled by 1uF into a 10kOhm line input, i.e. a 3-dB frequency of
+    /// 16Hz. This is typical of PC and USB SID cards.
+    pub fn sid_card() -> Self {
+        OutputStage::new(None, RcNetwork::new(10e3, 1e-6))
+    }
+
+    /// Raw chip output with only the DC level removed by the high-pass
+    /// network of the Commodore 64.
+    pub fn dc_only() -> Self {
+        OutputStage::new(None, RcNetwork::new(1e3, 10e-6))
+    }
+}
+
+impl Default for OutputStage {
+    fn default() -> Self {
+        OutputStage::c64()
+    }
+}
+
 /// The audio output stage in a Commodore 64 consists of two STC networks,
 /// a low-pass filter with 3-dB frequency 16kHz followed by a high-pass
 /// filter with 3-dB frequency 16Hz (the latter provided an audio equipment
@@ -28,10 +116,16 @@ const W0_HP: i32 = 105;
 /// additional low-pass and high-pass 3dB-frequencies in the order of hundreds
 /// of kHz. This calls for a sampling frequency of several MHz, which is far
 /// too high for practical use.
+///
+/// Other boards and SID cards may be modeled by configuring the RC networks,
+/// see OutputStage.
 pub struct ExternalFilter {
     // Configuration
     enabled: bool,
     mixer_dc: i32,
+    ext_in_dc: i32,
+    output_stage: OutputStage,
+    lp_enabled: bool,
     w0_lp: i32,
     w0_hp: i32,
     // Runtime State
@@ -42,27 +136,61 @@ pub struct ExternalFilter {
 
 impl ExternalFilter {
     pub fn new(chip_model: ChipModel) -> Self {
-        let mixer_dc = match chip_model {
-            ChipModel::Mos6581 => MIXER_DC_6581,
-            ChipModel::Mos8580 => 0,
-        };
+        ExternalFilter::with_profile(&ChipProfile::new(chip_model))
+    }
+
+    pub fn with_profile(profile: &ChipProfile) -> Self {
         let mut filter = ExternalFilter {
             enabled: true,
-            mixer_dc,
+            mixer_dc: profile.ext_filter_dc,
+            ext_in_dc: 0,
+            output_stage: OutputStage::c64(),
+            lp_enabled: true,
             w0_lp: W0_LP,
             w0_hp: W0_HP,
             vlp: 0,
             vhp: 0,
             vo: 0,
         };
+        filter.set_output_stage(profile.output_stage);
         filter.reset();
         filter
     }
 
+    pub fn get_output_stage(&self) -> OutputStage {
+        self.output_stage
+    }
+
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
     }
 
+    /// Set the DC level of the EXT IN input, e.g. the MOS8580 digi boost.
+    /// Like the mixer DC, its maximum output level is removed if the filter
+    /// is turned off.
+    pub fn set_ext_in_dc(&mut self, ext_in_dc: i32) {
+        self.ext_in_dc = (ext_in_dc >> 7) * 0x0f;
+    }
+
+    /// Switch the chip parameters in place. The filter state is retained.
+    pub fn set_profile(&mut self, profile: &ChipProfile) {
+        self.mixer_dc = profile.ext_filter_dc;
+        self.set_output_stage(profile.output_stage);
+    }
+
+    /// Reconfigure the RC networks. The filter state is retained.
+    pub fn set_output_stage(&mut self, output_stage: OutputStage) {
+        self.output_stage = output_stage;
+        if let Some(low_pass) = output_stage.low_pass {
+            self.lp_enabled = true;
+            self.w0_lp = low_pass.w0().min(W0_LP_MAX);
+        } else {
+            self.lp_enabled = false;
+            self.w0_lp = 0;
+        }
+        self.w0_hp = output_stage.high_pass.w0().min(W0_HP_MAX);
+    }
+
     #[inline]
     pub fn clock(&mut self, vi: i32) {
         // delta_t is converted to seconds given a 1MHz clock by dividing
@@ -72,7 +200,11 @@ impl ExternalFilter {
         // Vlp = Vlp + w0lp*(Vi - Vlp)*delta_t;
         // Vhp = Vhp + w0hp*(Vlp - Vhp)*delta_t;
         if self.enabled {
-            let dvlp = ((self.w0_lp >> 8) * (vi - self.vlp)) >> 12;
+            let dvlp = if self.lp_enabled {
+                ((self.w0_lp >> 8) * (vi - self.vlp)) >> 12
+            } else {
+                vi - self.vlp
+            };
             let dvhp = (self.w0_hp * (self.vlp - self.vhp)) >> 20;
             self.vo = self.vlp - self.vhp;
             self.vlp += dvlp;
@@ -80,7 +212,7 @@ impl ExternalFilter {
         } else {
             self.vlp = 0;
             self.vhp = 0;
-            self.vo = vi - self.mixer_dc;
+            self.vo = vi - self.mixer_dc - self.ext_in_dc;
         }
     }
 
@@ -100,7 +232,12 @@ impl ExternalFilter {
                 // Vo  = Vlp - Vhp;
                 // Vlp = Vlp + w0lp*(Vi - Vlp)*delta_t;
                 // Vhp = Vhp + w0hp*(Vlp - Vhp)*delta_t;
-                let dvlp = (((self.w0_lp * delta_flt as i32) >> 8) * (vi - self.vlp)) >> 12;
+                let dvlp = if self.lp_enabled {
+                    ((((self.w0_lp * delta_flt as i32) >> 8) as i64 * (vi - self.vlp) as i64) >> 12)
+                        as i32
+                } else {
+                    vi - self.vlp
+                };
                 let dvhp = (self.w0_hp * delta_flt as i32 * (self.vlp - self.vhp)) >> 20;
                 self.vo = self.vlp - self.vhp;
                 self.vlp += dvlp;
@@ -110,7 +247,7 @@ impl ExternalFilter {
         } else {
             self.vlp = 0;
             self.vhp = 0;
-            self.vo = vi - self.mixer_dc;
+            self.vo = vi - self.mixer_dc - self.ext_in_dc;
         }
     }
 
@@ -7,12 +7,28 @@
 
 #[cfg(not(feature = "std"))]
 use alloc::prelude::*;
+#[cfg(not(feature = "std"))]
+use libm::F64Ext;
+
 use core::f64;
 
+use super::chip_profile::ChipProfile;
+use super::dac::{build_dac_table, DacConfig};
 use super::spline;
 use super::ChipModel;
 
-const MIXER_DC: i32 = (-0xfff * 0xff / 18) >> 7;
+/// The mixer has a small input DC offset. This is found as follows:
+///
+/// The "zero" output level of the mixer measured on the SID audio
+/// output pin is 5.50V at zero volume, and 5.44V at full
+/// volume. This yields a DC offset of (5.44V - 5.50V) = -0.06V.
+///
+/// The DC offset is thus -0.06V/1.05V ~ -1/18 of the dynamic range
+/// of one voice. See voice.rs for measurement of the dynamic range.
+///
+/// This DC offset is multiplied by the volume, and is what makes samples
+/// played by writing the volume register audible.
+pub(crate) const MIXER_DC_6581: i32 = (-0xfff * 0xff / 18) >> 7;
 
 // Maximum cutoff frequency is specified as
 // FCmax = 2.6e-5/C = 2.6e-5/2200e-12 = 11818.
@@ -35,7 +51,10 @@ const MIXER_DC: i32 = (-0xfff * 0xff / 18) >> 7;
 //
 // NB! Cutoff frequency characteristics may vary, we have modeled two
 // particular Commodore 64s.
-static FO_POINTS_6581: [(i32, i32); 31] = [
+/// Q is controlled linearly by res. Q has approximate range [0.707, 1.7].
+pub(crate) const Q_RANGE: (f64, f64) = (0.707, 1.707);
+
+pub(crate) static FO_POINTS_6581: [(i32, i32); 31] = [
     //  FC      f         FCHI FCLO
     // ----------------------------
     (0, 220),      // 0x00      - repeated end point
@@ -71,7 +90,7 @@ static FO_POINTS_6581: [(i32, i32); 31] = [
     (2047, 18000), // 0xff 0x07 - repeated end point
 ];
 
-static FO_POINTS_8580: [(i32, i32); 19] = [
+pub(crate) static FO_POINTS_8580: [(i32, i32); 19] = [
     //  FC      f         FCHI FCLO
     // ----------------------------
     (0, 0),        // 0x00      - repeated end point
@@ -129,10 +148,14 @@ pub struct Filter {
     fc: u16,
     filt: u8,
     res: u8,
+    q_range: (f64, f64),
     // Mode
     voice3_off: bool,
     hp_bp_lp: u8,
     vol: u8,
+    // Volume DAC
+    volume_dac: Option<Vec<u16>>,
+    volume: i32,
     // Runtime State
     pub vhp: i32,
     pub vbp: i32,
@@ -146,41 +169,103 @@ pub struct Filter {
     w0_ceil_dt: i32,
     // Cutoff Freq Tables
     f0: [i32; 2048],
-    f0_points: &'static [(i32, i32)],
 }
 
 impl Filter {
     pub fn new(chip_model: ChipModel) -> Self {
-        let f0_points = match chip_model {
-            ChipModel::Mos6581 => &FO_POINTS_6581[0..],
-            ChipModel::Mos8580 => &FO_POINTS_8580[0..],
-        };
+        Filter::with_profile(&ChipProfile::new(chip_model))
+    }
+
+    pub fn with_profile(profile: &ChipProfile) -> Self {
         let mut filter = Filter {
             enabled: true,
             fc: 0,
             filt: 0,
             res: 0,
+            q_range: profile.q_range,
             voice3_off: false,
             hp_bp_lp: 0,
             vol: 0,
+            volume_dac: None,
+            volume: 0,
             vhp: 0,
             vbp: 0,
             vlp: 0,
             vnf: 0,
-            mixer_dc: MIXER_DC,
+            mixer_dc: profile.mixer_dc,
             q_1024_div: 0,
             w0: 0,
             w0_ceil_1: 0,
             w0_ceil_dt: 0,
             f0: [0; 2048],
-            f0_points,
         };
-        filter.set_f0();
+        filter.set_f0(&profile.f0_points);
+        filter.set_volume_dac(profile.dac);
         filter.set_q();
         filter.set_w0();
         filter
     }
 
+    /// Cutoff frequency in Hz for an 11-bit FC value, looked up in the
+    /// interpolated cutoff frequency table of the current chip model.
+    pub fn cutoff_hz(&self, fc: u16) -> f64 {
+        self.f0[(fc & 0x7ff) as usize] as f64
+    }
+
+    /// Filter quality factor for a 4-bit resonance value.
+    pub fn q(&self, res: u8) -> f64 {
+        let (q_min, q_max) = self.q_range;
+        q_min + (q_max - q_min) * (res & 0x0f) as f64 / 15.0
+    }
+
+    /// Frequency response of the filter for the given FC, resonance and
+    /// HP/BP/LP mode bits (as in MODE/VOL bits 4-6, i.e. LP = 0x1,
+    /// BP = 0x2, HP = 0x4), evaluated at each of the frequencies in Hz.
+    /// Returns the magnitude and phase (in radians) of the mixed filter
+    /// output relative to the filter input.
+    ///
+    /// The response is derived from the difference equations used by clock():
+    /// ``` ignore,
+    /// Vbp = -a/(z - 1)*Vhp
+    /// Vlp = -a/(z - 1)*Vbp
+    /// Vhp = Vbp/Q - Vlp - Vi
+    /// ```
+    /// with a = w0*delta_t, which solved for Vi yields
+    /// ``` ignore,
+    /// Hhp = -(z - 1)^2/D, Hbp = a*(z - 1)/D, Hlp = -a^2/D,
+    /// D = (z - 1)^2 + a/Q*(z - 1) + a^2
+    /// ```
+    pub fn frequency_response(&self, fc: u16, res: u8, mode: u8, freqs: &[f64]) -> Vec<(f64, f64)> {
+        let (_, w0_ceil_1, _) = self.compute_w0(fc & 0x7ff);
+        let a = w0_ceil_1 as f64 / (1 << 20) as f64;
+        let q_div = Self::compute_q_1024_div(self.q(res)) as f64 / 1024.0;
+        freqs
+            .iter()
+            .map(|&freq| {
+                // delta_t = 1 is converted to seconds given a 1MHz clock.
+                let theta = 2.0 * f64::consts::PI * freq / 1_000_000.0;
+                // u = z - 1
+                let u = (theta.cos() - 1.0, theta.sin());
+                let u2 = complex_mul(u, u);
+                let d = (u2.0 + a * q_div * u.0 + a * a, u2.1 + a * q_div * u.1);
+                let mut h = (0.0, 0.0);
+                if mode & 0x01 != 0 {
+                    h.0 -= a * a;
+                }
+                if mode & 0x02 != 0 {
+                    h.0 += a * u.0;
+                    h.1 += a * u.1;
+                }
+                if mode & 0x04 != 0 {
+                    h.0 -= u2.0;
+                    h.1 -= u2.1;
+                }
+                let h = complex_div(h, d);
+                ((h.0 * h.0 + h.1 * h.1).sqrt(), h.1.atan2(h.0))
+            })
+            .collect()
+    }
+
     pub fn get_fc_hi(&self) -> u8 {
         (self.fc >> 3) as u8
     }
@@ -189,6 +274,10 @@ impl Filter {
         (self.fc & 0x007) as u8
     }
 
+    pub fn get_mixer_dc(&self) -> i32 {
+        self.mixer_dc
+    }
+
     pub fn get_mode_vol(&self) -> u8 {
         let value = if self.voice3_off { 0x80 } else { 0 };
         value | (self.hp_bp_lp << 4) | (self.vol & 0x0f)
@@ -202,6 +291,19 @@ impl Filter {
         self.enabled = enabled;
     }
 
+    /// Model the volume DAC with the given R-2R ladder configuration, or as
+    /// an ideal linear DAC if none is given.
+    pub fn set_volume_dac(&mut self, config: Option<DacConfig>) {
+        // The volume is scaled by 256 to retain the DAC non-linearity.
+        self.volume_dac = config.map(|config| build_dac_table(4, config, 256.0));
+        self.set_volume();
+    }
+
+    /// Set the mixer input DC offset, which is multiplied by the volume.
+    pub fn set_mixer_dc(&mut self, mixer_dc: i32) {
+        self.mixer_dc = mixer_dc;
+    }
+
     pub fn set_fc_hi(&mut self, value: u8) {
         let result = ((value as u16) << 3) & 0x7f8 | self.fc & 0x007;
         self.fc = result;
@@ -218,6 +320,18 @@ impl Filter {
         self.voice3_off = value & 0x80 != 0;
         self.hp_bp_lp = (value >> 4) & 0x07;
         self.vol = value & 0x0f;
+        self.set_volume();
+    }
+
+    /// Switch the chip parameters in place, retaining the registers and the
+    /// runtime state.
+    pub fn set_profile(&mut self, profile: &ChipProfile) {
+        self.mixer_dc = profile.mixer_dc;
+        self.q_range = profile.q_range;
+        self.set_f0(&profile.f0_points);
+        self.set_volume_dac(profile.dac);
+        self.set_q();
+        self.set_w0();
     }
 
     pub fn set_res_filt(&mut self, value: u8) {
@@ -476,7 +590,7 @@ impl Filter {
     pub fn output(&self) -> i32 {
         // This is handy for testing.
         if !self.enabled {
-            (self.vnf + self.mixer_dc) * self.vol as i32
+            ((self.vnf + self.mixer_dc) * self.volume) >> 8
         } else {
             // Mix highpass, bandpass, and lowpass outputs. The sum is not
             // weighted, this can be confirmed by sampling sound output for
@@ -498,7 +612,7 @@ impl Filter {
             };
             // Sum non-filtered and filtered output.
             // Multiply the sum with volume.
-            (self.vnf + vf + self.mixer_dc) * self.vol as i32
+            ((self.vnf + vf + self.mixer_dc) * self.volume) >> 8
         }
     }
 
@@ -509,6 +623,7 @@ impl Filter {
         self.voice3_off = false;
         self.hp_bp_lp = 0;
         self.vol = 0;
+        self.set_volume();
         self.vhp = 0;
         self.vbp = 0;
         self.vlp = 0;
@@ -517,10 +632,9 @@ impl Filter {
         self.set_q();
     }
 
-    fn set_f0(&mut self) {
-        let points = self
-            .f0_points
-            .into_iter()
+    fn set_f0(&mut self, f0_points: &[(i32, i32)]) {
+        let points = f0_points
+            .iter()
             .map(|&pt| spline::Point {
                 x: pt.0 as f64,
                 y: pt.1 as f64,
@@ -532,35 +646,59 @@ impl Filter {
         self.f0[..2048].clone_from_slice(&output[..2048]);
     }
 
+    fn set_volume(&mut self) {
+        self.volume = match self.volume_dac {
+            Some(ref dac) => dac[self.vol as usize] as i32,
+            None => (self.vol as i32) << 8,
+        };
+    }
+
     fn set_q(&mut self) {
-        // Q is controlled linearly by res. Q has approximate range [0.707, 1.7].
         // As resonance is increased, the filter must be clocked more often to keep
         // stable.
+        self.q_1024_div = Self::compute_q_1024_div(self.q(self.res));
+    }
+
+    fn set_w0(&mut self) {
+        let (w0, w0_ceil_1, w0_ceil_dt) = self.compute_w0(self.fc);
+        self.w0 = w0;
+        self.w0_ceil_1 = w0_ceil_1;
+        self.w0_ceil_dt = w0_ceil_dt;
+    }
 
+    fn compute_q_1024_div(q: f64) -> i32 {
         // The coefficient 1024 is dispensed of later by right-shifting 10 times
         // (2 ^ 10 = 1024).
-        self.q_1024_div = (1024.0 / (0.707 + 1.0 * self.res as f64 / 15.0)) as i32;
+        (1024.0 / q) as i32
     }
 
-    fn set_w0(&mut self) {
+    fn compute_w0(&self, fc: u16) -> (i32, i32, i32) {
         // Multiply with 1.048576 to facilitate division by 1 000 000 by right-
         // shifting 20 times (2 ^ 20 = 1048576).
-        self.w0 = (2.0 * f64::consts::PI * self.f0[self.fc as usize] as f64 * 1.048_576) as i32;
+        let w0 = (2.0 * f64::consts::PI * self.f0[fc as usize] as f64 * 1.048_576) as i32;
 
         // Limit f0 to 16kHz to keep 1 cycle filter stable.
         let w0_max_1 = (2.0 * f64::consts::PI * 16000.0 * 1.048_576) as i32;
-        self.w0_ceil_1 = if self.w0 <= w0_max_1 {
-            self.w0
-        } else {
-            w0_max_1
-        };
+        let w0_ceil_1 = if w0 <= w0_max_1 { w0 } else { w0_max_1 };
 
         // Limit f0 to 4kHz to keep delta_t cycle filter stable.
         let w0_max_dt = (2.0 * f64::consts::PI * 4000.0 * 1.048_576) as i32;
-        self.w0_ceil_dt = if self.w0 <= w0_max_dt {
-            self.w0
-        } else {
-            w0_max_dt
-        };
+        let w0_ceil_dt = if w0 <= w0_max_dt { w0 } else { w0_max_dt };
+
+        (w0, w0_ceil_1, w0_ceil_dt)
     }
 }
+
+#[inline]
+fn complex_mul(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+#[inline]
+fn complex_div(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    let norm = b.0 * b.0 + b.1 * b.1;
+    (
+        (a.0 * b.0 + a.1 * b.1) / norm,
+        (a.1 * b.0 - a.0 * b.1) / norm,
+    )
+}
@@ -0,0 +1,234 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
+
+#[cfg(not(feature = "std"))]
+use libm::F64Ext;
+
+use core::f64;
+
+use super::error::Error;
+use super::filter::Filter;
+use super::ChipModel;
+
+const FILTER_CLOCK_FREQ: f64 = 1_000_000.0;
+
+const OUTPUT_HALF: i32 = 1 << 15;
+
+const FIXP_SHIFT: u32 = 16;
+const FIXP_MASK: u32 = 0xffff;
+// Maximum number of cycles per sample, bounded by the fixpoint sample offset.
+const CYCLES_PER_SAMPLE_MAX: u64 = 0xffff;
+
+pub const MODE_LP: u8 = 0x01;
+pub const MODE_BP: u8 = 0x02;
+pub const MODE_HP: u8 = 0x04;
+
+/// The SID filter as a standalone audio effect.
+///
+/// PCM samples at an arbitrary sampling frequency are routed through the
+/// filter via EXT IN. Each input sample is held for the number of 1MHz filter
+/// cycles spanning one sample period, so the filter integrators run exactly
+/// as they do inside the chip, including the chip specific FC to cutoff
+/// frequency mapping.
+///
+/// The filter integrators operate on integers, and small input signals are
+/// thus subject to truncation errors. To make the most of the available
+/// resolution, samples are scaled up as far as the current cutoff frequency
+/// allows without overflowing the integrators, and the filter output is
+/// scaled back down accordingly.
+pub struct FilterEffect {
+    // Dependencies
+    filter: Filter,
+    // Configuration
+    cycles_per_sample: u32,
+    fc: u16,
+    res: u8,
+    mode: u8,
+    // Runtime State
+    gain_shift: i32,
+    offset: u32,
+}
+
+impl FilterEffect {
+    /// Create a filter effect for the sample frequency in Hz. Returns an
+    /// error if the sample frequency is invalid, see set_sample_freq.
+    pub fn new(chip_model: ChipModel, sample_freq: u32) -> Result<Self, Error> {
+        let mut effect = FilterEffect {
+            filter: Filter::new(chip_model),
+            cycles_per_sample: 0,
+            fc: 0,
+            res: 0,
+            mode: MODE_LP,
+            gain_shift: 0,
+            offset: 0,
+        };
+        effect.set_cutoff(0);
+        effect.set_sample_freq(sample_freq)?;
+        effect.set_resonance(0);
+        effect.set_mode(MODE_LP);
+        Ok(effect)
+    }
+
+    pub fn get_cutoff(&self) -> u16 {
+        self.fc
+    }
+
+    /// Cutoff frequency in Hz for the current FC value.
+    pub fn get_cutoff_hz(&self) -> f64 {
+        self.filter.cutoff_hz(self.fc)
+    }
+
+    pub fn get_mode(&self) -> u8 {
+        self.mode
+    }
+
+    pub fn get_resonance(&self) -> u8 {
+        self.res
+    }
+
+    pub fn get_filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    /// Set the 11-bit FC value.
+    pub fn set_cutoff(&mut self, fc: u16) {
+        self.fc = fc & 0x07ff;
+        self.filter.set_fc_lo((self.fc & 0x007) as u8);
+        self.filter.set_fc_hi((self.fc >> 3) as u8);
+        self.set_gain_shift();
+    }
+
+    /// Set the FC value whose cutoff frequency is closest to the given
+    /// frequency in Hz. The range of reachable frequencies is that of the
+    /// chip model.
+    pub fn set_cutoff_hz(&mut self, freq: f64) {
+        let mut fc = 0;
+        let mut error = f64::MAX;
+        for i in 0..0x0800 {
+            let error_i = (self.filter.cutoff_hz(i) - freq).abs();
+            if error_i < error {
+                error = error_i;
+                fc = i;
+            }
+        }
+        self.set_cutoff(fc);
+    }
+
+    /// Set the mode bits, any combination of MODE_LP, MODE_BP and MODE_HP.
+    pub fn set_mode(&mut self, mode: u8) {
+        self.mode = mode & 0x07;
+        self.filter.set_mode_vol((self.mode << 4) | 0x0f);
+    }
+
+    /// Set the 4-bit resonance value.
+    pub fn set_resonance(&mut self, res: u8) {
+        self.res = res & 0x0f;
+        // Route EXT IN into the filter.
+        self.filter.set_res_filt((self.res << 4) | 0x08);
+    }
+
+    /// Set the sample frequency in Hz. Returns an error if the sample
+    /// frequency is zero, above the 1MHz filter clock, or so low that a
+    /// sample spans 65535 or more filter cycles, i.e. below 16Hz.
+    pub fn set_sample_freq(&mut self, sample_freq: u32) -> Result<(), Error> {
+        FilterEffect::check_sample_freq(sample_freq)?;
+        self.cycles_per_sample =
+            (FILTER_CLOCK_FREQ / sample_freq as f64 * (1 << FIXP_SHIFT) as f64 + 0.5) as u32;
+        self.offset = 0;
+        Ok(())
+    }
+
+    /// Validate a sample frequency, see set_sample_freq.
+    pub fn check_sample_freq(sample_freq: u32) -> Result<(), Error> {
+        let clock_freq = FILTER_CLOCK_FREQ as u32;
+        if sample_freq == 0 || clock_freq as u64 >= CYCLES_PER_SAMPLE_MAX * sample_freq as u64 {
+            return Err(Error::InvalidSampleFrequency(sample_freq));
+        }
+        if sample_freq > clock_freq {
+            return Err(Error::SampleFrequencyAboveClock {
+                clock_freq,
+                sample_freq,
+            });
+        }
+        Ok(())
+    }
+
+    /// Filter one sample.
+    pub fn process(&mut self, sample: i16) -> i16 {
+        // Scale to the filter input level, which is 13 bits for the voices.
+        // The filter scales EXT IN down by 7 bits.
+        let vi = if self.gain_shift >= 0 {
+            (sample as i32) << self.gain_shift
+        } else {
+            (sample as i32) >> -self.gain_shift
+        };
+        let ext_in = vi << 7;
+        self.offset += self.cycles_per_sample;
+        let cycles = self.offset >> FIXP_SHIFT;
+        self.offset &= FIXP_MASK;
+        for _i in 0..cycles {
+            self.filter.clock(0, 0, 0, ext_in);
+        }
+        // Mix highpass, bandpass, and lowpass outputs.
+        let mut vf = 0;
+        if self.mode & MODE_LP != 0 {
+            vf += self.filter.vlp;
+        }
+        if self.mode & MODE_BP != 0 {
+            vf += self.filter.vbp;
+        }
+        if self.mode & MODE_HP != 0 {
+            vf += self.filter.vhp;
+        }
+        // The filter outputs are inverted.
+        let output = if self.gain_shift >= 0 {
+            -vf >> self.gain_shift
+        } else {
+            -vf << -self.gain_shift
+        };
+        if output >= OUTPUT_HALF {
+            (OUTPUT_HALF - 1) as i16
+        } else if output < -OUTPUT_HALF {
+            (-OUTPUT_HALF) as i16
+        } else {
+            output as i16
+        }
+    }
+
+    /// Filter samples in place.
+    pub fn process_buffer(&mut self, buffer: &mut [i16]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.process(*sample);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.filter.vhp = 0;
+        self.filter.vbp = 0;
+        self.filter.vlp = 0;
+        self.filter.vnf = 0;
+        self.offset = 0;
+    }
+
+    fn set_gain_shift(&mut self) {
+        // The filter computes w0*Vhp in 32 bits, see Filter::clock. Allow for
+        // filter outputs of up to 4 times the full scale input, i.e. 2^17, and
+        // use at least the 13-bit resolution of the voices.
+        let w0_max_1 = 2.0 * f64::consts::PI * 16000.0 * 1.048_576;
+        let w0 = (2.0 * f64::consts::PI * self.filter.cutoff_hz(self.fc) * 1.048_576)
+            .min(w0_max_1)
+            .max(1.0);
+        let gain_shift = ((f64::from(1 << 14) / w0).log2().floor() as i32).clamp(-3, 4);
+        // Rescale runtime state to the new input level.
+        let delta = gain_shift - self.gain_shift;
+        let rescale = |v: i32| if delta >= 0 { v << delta } else { v >> -delta };
+        self.filter.vhp = rescale(self.filter.vhp);
+        self.filter.vbp = rescale(self.filter.vbp);
+        self.filter.vlp = rescale(self.filter.vlp);
+        self.gain_shift = gain_shift;
+    }
+}
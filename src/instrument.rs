@@ -0,0 +1,456 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
+
+#[cfg(not(feature = "std"))]
+use libm::F64Ext;
+
+use super::builder::Clock;
+use super::envelope::RATE_COUNTER_PERIOD;
+use super::error::Error;
+use super::registers::{AttackDecay, Control, SustainRelease, Waveform};
+use super::sid::Sid;
+
+/// Default number of cycles a hard restart holds the envelope at the fastest
+/// release rate before the gate is set. This exceeds the 15-bit rate counter
+/// period, so the rate counter has wrapped around at least once and the ADSR
+/// delay bug cannot delay the attack.
+pub const HARD_RESTART_CYCLES: u32 = 0x8000;
+
+/// Number of cycles after a hard restart sets the gate until the attack and
+/// decay rates are written. The state pipeline compares the rate counter with
+/// the decay rate period on the first cycle of the attack, and a slower decay
+/// rate than the release rate of the hard restart could let the rate counter
+/// run past the attack rate period, see envelope.rs.
+const ATTACK_RATE_DELAY: u32 = 2;
+
+/// Frequency in Hz of a MIDI note number, with A4 (note 69) at 440Hz.
+pub fn note_to_hz(note: u8) -> f64 {
+    440.0 * 2.0f64.powf((note as f64 - 69.0) / 12.0)
+}
+
+/// Nominal attack time in ms of each rate index, i.e. the time to count from
+/// 0 to 255. The nominal decay and release times are three times longer.
+fn attack_ms(rate: usize, clock: Clock) -> f64 {
+    (RATE_COUNTER_PERIOD[rate] + 1) as f64 * 256.0 * 1000.0 / clock.frequency() as f64
+}
+
+/// Rate index with the nominal time nearest to the given time in ms.
+fn nearest_rate(time_ms: f64, scale: f64, clock: Clock) -> u8 {
+    let mut rate = 0;
+    for i in 1..RATE_COUNTER_PERIOD.len() {
+        let error = (attack_ms(i, clock) * scale - time_ms).abs();
+        if error < (attack_ms(rate, clock) * scale - time_ms).abs() {
+            rate = i;
+        }
+    }
+    rate as u8
+}
+
+/// Sound of a note, with the envelope given in ms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instrument {
+    pub waveform: Waveform,
+    /// 12-bit pulse width.
+    pub pulse_width: u16,
+    pub attack_ms: f64,
+    pub decay_ms: f64,
+    /// 4-bit sustain level.
+    pub sustain: u8,
+    pub release_ms: f64,
+    /// Reset the envelope and the oscillator before each note, see
+    /// NotePlayer::note_on.
+    pub hard_restart: bool,
+}
+
+impl Default for Instrument {
+    fn default() -> Self {
+        Instrument {
+            waveform: Waveform::Pulse,
+            pulse_width: 0x800,
+            attack_ms: 2.0,
+            decay_ms: 750.0,
+            sustain: 0x0f,
+            release_ms: 300.0,
+            hard_restart: false,
+        }
+    }
+}
+
+impl Instrument {
+    /// Attack and decay rates nearest to the attack and decay times.
+    pub fn attack_decay(&self, clock: Clock) -> AttackDecay {
+        AttackDecay {
+            attack: nearest_rate(self.attack_ms, 1.0, clock),
+            decay: nearest_rate(self.decay_ms, 3.0, clock),
+        }
+    }
+
+    /// Sustain level and release rate nearest to the release time.
+    pub fn sustain_release(&self, clock: Clock) -> SustainRelease {
+        SustainRelease {
+            sustain: self.sustain & 0x0f,
+            release: nearest_rate(self.release_ms, 3.0, clock),
+        }
+    }
+
+    fn control(&self, gate: bool) -> Control {
+        let mut control = Control::default();
+        control.set_waveform(self.waveform);
+        control.gate = gate;
+        control
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PendingNote {
+    // Cycles until the hard restart sets the gate, or until the envelope
+    // rates are written once the gate is set.
+    cycles: u32,
+    gate: bool,
+    frequency: f64,
+    instrument: Instrument,
+    // Cycles the note has been held, and whether it has been released.
+    held: u32,
+    released: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+struct VoiceState {
+    note: Option<u8>,
+    gate: bool,
+    // Order of the last note on or off, used to pick the voice to allocate.
+    age: u64,
+    // Note waiting for a hard restart to complete.
+    pending: Option<PendingNote>,
+    // Cycles until the gate of a note released during its hard restart is
+    // cleared.
+    release: Option<u32>,
+}
+
+impl VoiceState {
+    fn is_busy(&self) -> bool {
+        self.gate || self.pending.is_some() || self.release.is_some()
+    }
+}
+
+/// Polyphonic synthesizer playing notes on the three voices of a SID.
+///
+/// Notes are allocated to a free voice, preferring the one released the
+/// longest ago. If all voices are playing, the oldest note is replaced.
+///
+/// Voice numbers above 2 are rejected with Error::InvalidVoice.
+///
+/// ```
+/// use resid::instrument::{Instrument, NotePlayer};
+/// use resid::{ChipModel, Clock, Sid};
+///
+/// let mut player = NotePlayer::new(Sid::new(ChipModel::Mos8580), Clock::Pal);
+/// player.set_volume(0x0f);
+/// player.note_on(60, &Instrument::default());
+/// let mut buffer = [0i16; 441];
+/// player.sample(9852, &mut buffer, 1);
+/// player.note_off(60);
+/// ```
+pub struct NotePlayer {
+    sid: Sid,
+    clock: Clock,
+    voices: [VoiceState; 3],
+    age: u64,
+    hard_restart_cycles: u32,
+}
+
+impl NotePlayer {
+    pub fn new(sid: Sid, clock: Clock) -> Self {
+        NotePlayer {
+            sid,
+            clock,
+            voices: [VoiceState::default(); 3],
+            age: 0,
+            hard_restart_cycles: HARD_RESTART_CYCLES,
+        }
+    }
+
+    pub fn get_sid(&self) -> &Sid {
+        &self.sid
+    }
+
+    pub fn get_sid_mut(&mut self) -> &mut Sid {
+        &mut self.sid
+    }
+
+    pub fn into_sid(self) -> Sid {
+        self.sid
+    }
+
+    /// Number of cycles a hard restart releases the envelope before the note
+    /// starts, HARD_RESTART_CYCLES by default.
+    pub fn set_hard_restart_cycles(&mut self, cycles: u32) {
+        self.hard_restart_cycles = cycles;
+    }
+
+    /// Set the 4-bit master volume, retaining the filter mode.
+    pub fn set_volume(&mut self, volume: u8) {
+        let mut mode_vol = self.sid.get_mode_vol();
+        mode_vol.volume = volume & 0x0f;
+        self.sid.set_mode_vol(mode_vol);
+    }
+
+    /// Play a MIDI note. Returns the allocated voice.
+    ///
+    /// The gate is set right away. A voice which is still playing continues
+    /// its envelope, i.e. the note is played legato, and the attack of a
+    /// voice which was just released may be delayed by the ADSR delay bug,
+    /// or stuck at zero if the envelope counter flips from 0xff.
+    /// With a hard restart, the envelope is first released at the fastest
+    /// rate with the oscillator held by the test bit, and the note starts
+    /// after the hard restart cycles, about 33ms by default. A note released
+    /// before then still plays, for as long as it was held.
+    pub fn note_on(&mut self, note: u8, instrument: &Instrument) -> usize {
+        let voice = self.allocate(Some(note));
+        self.start(voice, Some(note), note_to_hz(note), instrument);
+        voice
+    }
+
+    /// Play a frequency in Hz. Returns the allocated voice, which is
+    /// released with note_off_voice.
+    pub fn note_on_hz(&mut self, frequency: f64, instrument: &Instrument) -> usize {
+        let voice = self.allocate(None);
+        self.start(voice, None, frequency, instrument);
+        voice
+    }
+
+    /// Play a frequency in Hz on the given voice, e.g. for a fixed mapping of
+    /// channels to voices. The note is started like by note_on.
+    pub fn note_on_voice(
+        &mut self,
+        voice: usize,
+        frequency: f64,
+        instrument: &Instrument,
+    ) -> Result<(), Error> {
+        check_voice(voice)?;
+        self.start(voice, None, frequency, instrument);
+        Ok(())
+    }
+
+    /// Change the frequency of the note on the voice, e.g. for pitch bend.
+    pub fn set_frequency_hz(&mut self, voice: usize, frequency: f64) -> Result<(), Error> {
+        check_voice(voice)?;
+        match self.voices[voice].pending {
+            Some(ref mut pending) => pending.frequency = frequency,
+            None => {
+                if let Some(mut control) = self.sid.voice(voice) {
+                    control.set_frequency_hz(frequency, self.clock);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Release all voices playing the MIDI note.
+    pub fn note_off(&mut self, note: u8) {
+        for voice in 0..3 {
+            if self.voices[voice].gate && self.voices[voice].note == Some(note) {
+                self.release(voice);
+            }
+        }
+    }
+
+    pub fn note_off_voice(&mut self, voice: usize) -> Result<(), Error> {
+        check_voice(voice)?;
+        self.release(voice);
+        Ok(())
+    }
+
+    pub fn all_notes_off(&mut self) {
+        for voice in 0..3 {
+            if self.voices[voice].gate {
+                self.release(voice);
+            }
+        }
+    }
+
+    /// Clock the SID, starting notes once their hard restart is complete, and
+    /// releasing them once they have been held as long as requested.
+    pub fn clock_delta(&mut self, mut delta: u32) {
+        while delta > 0 {
+            let step = self.next_pending(delta);
+            self.sid.clock_delta(step);
+            self.advance_pending(step);
+            delta -= step;
+        }
+    }
+
+    /// Clock the SID with audio sampling like Sid::sample, starting and
+    /// releasing notes like clock_delta.
+    pub fn sample(
+        &mut self,
+        mut delta: u32,
+        buffer: &mut [i16],
+        interleave: usize,
+    ) -> (usize, u32) {
+        let mut index = 0;
+        while delta > 0 {
+            let step = self.next_pending(delta);
+            let start = (index * interleave).min(buffer.len());
+            let (samples, next_delta) = self.sid.sample(step, &mut buffer[start..], interleave);
+            index += samples;
+            self.advance_pending(step - next_delta);
+            delta -= step - next_delta;
+            if next_delta > 0 {
+                break;
+            }
+        }
+        (index, delta)
+    }
+
+    fn allocate(&mut self, note: Option<u8>) -> usize {
+        // Retrigger a voice already playing the note.
+        if note.is_some() {
+            if let Some(voice) =
+                (0..3).find(|&i| self.voices[i].gate && self.voices[i].note == note)
+            {
+                return voice;
+            }
+        }
+        // Otherwise pick the voice released the longest ago, or the oldest
+        // note if all voices are playing.
+        let voices = &self.voices;
+        (0..3)
+            .min_by_key(|&i| (voices[i].is_busy(), voices[i].age))
+            .unwrap()
+    }
+
+    fn start(&mut self, voice: usize, note: Option<u8>, frequency: f64, instrument: &Instrument) {
+        self.age += 1;
+        let state = &mut self.voices[voice];
+        state.note = note;
+        state.gate = true;
+        state.age = self.age;
+        state.release = None;
+        if let Some(ref mut pending) = state.pending {
+            // Retriggered during a hard restart, which continues.
+            pending.frequency = frequency;
+            pending.instrument = *instrument;
+            pending.held = 0;
+            pending.released = false;
+            return;
+        }
+        if instrument.hard_restart {
+            // Release the envelope at the fastest rate, with the oscillator
+            // held by the test bit, and set the gate once it has reached
+            // zero.
+            state.pending = Some(PendingNote {
+                cycles: self.hard_restart_cycles,
+                gate: false,
+                frequency,
+                instrument: *instrument,
+                held: 0,
+                released: false,
+            });
+            let mut control = instrument.control(false);
+            control.test = true;
+            if let Some(mut voice_control) = self.sid.voice(voice) {
+                voice_control
+                    .set_control(control)
+                    .set_attack_decay(AttackDecay::default())
+                    .set_sustain_release(SustainRelease::default());
+            }
+        } else {
+            self.gate_on(voice, frequency, instrument);
+        }
+    }
+
+    fn release(&mut self, voice: usize) {
+        self.age += 1;
+        let state = &mut self.voices[voice];
+        state.gate = false;
+        state.age = self.age;
+        if let Some(ref mut pending) = state.pending {
+            // The note is released once it has played as long as it was held.
+            pending.released = true;
+            return;
+        }
+        if let Some(mut control) = self.sid.voice(voice) {
+            control.set_gate(false);
+        }
+    }
+
+    fn gate_on(&mut self, voice: usize, frequency: f64, instrument: &Instrument) {
+        let clock = self.clock;
+        if let Some(mut control) = self.sid.voice(voice) {
+            control
+                .set_attack_decay(instrument.attack_decay(clock))
+                .set_sustain_release(instrument.sustain_release(clock))
+                .set_pulse_width(instrument.pulse_width)
+                .set_frequency_hz(frequency, clock)
+                .set_control(instrument.control(true));
+        }
+    }
+
+    fn next_pending(&self, delta: u32) -> u32 {
+        self.voices
+            .iter()
+            .filter_map(|state| match (state.pending, state.release) {
+                (Some(pending), _) => Some(pending.cycles),
+                (None, release) => release,
+            })
+            .fold(delta, |step, cycles| step.min(cycles.max(1)))
+    }
+
+    fn advance_pending(&mut self, delta: u32) {
+        for voice in 0..3 {
+            let state = self.voices[voice];
+            if let Some(mut pending) = state.pending {
+                if !pending.released {
+                    pending.held = pending.held.saturating_add(delta);
+                }
+                if pending.cycles <= delta && !pending.gate {
+                    // Set the gate with the rates of the hard restart.
+                    pending.cycles = ATTACK_RATE_DELAY;
+                    pending.gate = true;
+                    self.voices[voice].pending = Some(pending);
+                    let clock = self.clock;
+                    if let Some(mut control) = self.sid.voice(voice) {
+                        control
+                            .set_pulse_width(pending.instrument.pulse_width)
+                            .set_frequency_hz(pending.frequency, clock)
+                            .set_control(pending.instrument.control(true));
+                    }
+                } else if pending.cycles <= delta {
+                    self.voices[voice].pending = None;
+                    if pending.released {
+                        // The gate is cleared as long after it was set as the
+                        // note was held.
+                        self.voices[voice].release =
+                            Some(pending.held.saturating_sub(ATTACK_RATE_DELAY));
+                    }
+                    self.gate_on(voice, pending.frequency, &pending.instrument);
+                } else {
+                    pending.cycles -= delta;
+                    self.voices[voice].pending = Some(pending);
+                }
+            } else if let Some(release) = state.release {
+                if release <= delta {
+                    self.voices[voice].release = None;
+                    if let Some(mut control) = self.sid.voice(voice) {
+                        control.set_gate(false);
+                    }
+                } else {
+                    self.voices[voice].release = Some(release - delta);
+                }
+            }
+        }
+    }
+}
+
+fn check_voice(voice: usize) -> Result<(), Error> {
+    if voice < 3 {
+        Ok(())
+    } else {
+        Err(Error::InvalidVoice(voice))
+    }
+}
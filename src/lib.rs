@@ -11,24 +11,43 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate core;
 
+pub mod asid;
+mod builder;
+pub mod chip_profile;
+pub mod combined_waveform;
+pub mod dac;
 mod data;
 pub mod envelope;
+mod error;
 pub mod external_filter;
 pub mod filter;
+pub mod filter_effect;
+pub mod instrument;
 #[cfg(not(feature = "std"))]
 mod math;
+#[cfg(feature = "std")]
+pub mod network;
+pub mod pot;
+pub mod registers;
 pub mod sampler;
 mod sid;
+pub mod smf;
 pub mod spline;
 pub mod synth;
 pub mod voice;
 pub mod wave;
+pub mod write_log;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ChipModel {
     Mos6581,
     Mos8580,
+    /// Idealized chip, see ChipProfile::clean.
+    Clean,
 }
 
+pub use self::builder::{Clock, SidBuilder};
+pub use self::chip_profile::ChipProfile;
+pub use self::error::Error;
 pub use self::sampler::SamplingMethod;
-pub use self::sid::Sid;
+pub use self::sid::{Reg, Sid};
@@ -0,0 +1,359 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+
+use super::builder::{Clock, SidBuilder};
+use super::error::Error;
+use super::sampler::SamplingMethod;
+use super::sid::Sid;
+use super::ChipModel;
+
+/// Default TCP port of the Network SID Device protocol.
+pub const DEFAULT_PORT: u16 = 6581;
+
+const PROTOCOL_VERSION: u8 = 4;
+/// The chip of a write is selected by the upper 3 bits of the register.
+const MAX_SID_COUNT: usize = 8;
+
+// Commands
+const FLUSH: u8 = 0;
+const TRY_SET_SID_COUNT: u8 = 1;
+const MUTE: u8 = 2;
+const TRY_RESET: u8 = 3;
+const TRY_DELAY: u8 = 4;
+const TRY_WRITE: u8 = 5;
+const TRY_READ: u8 = 6;
+const GET_VERSION: u8 = 7;
+const TRY_SET_SAMPLING: u8 = 8;
+const SET_CLOCKING: u8 = 9;
+const GET_CONFIG_COUNT: u8 = 10;
+const GET_CONFIG_INFO: u8 = 11;
+const SET_SID_POSITION: u8 = 12;
+const SET_SID_LEVEL: u8 = 13;
+const TRY_SET_SID_MODEL: u8 = 14;
+const SET_DELAY: u8 = 15;
+const SET_FADE_IN: u8 = 16;
+const SET_FADE_OUT: u8 = 17;
+const SET_SID_HEADER: u8 = 18;
+
+// Responses
+const OK: u8 = 0;
+const BUSY: u8 = 1;
+const ERR: u8 = 2;
+const READ: u8 = 3;
+const VERSION: u8 = 4;
+const COUNT: u8 = 5;
+const INFO: u8 = 6;
+
+/// Chip configurations offered to clients, selected by index.
+const CONFIGS: [(ChipModel, &str); 2] = [
+    (ChipModel::Mos6581, "resid-rs MOS6581"),
+    (ChipModel::Mos8580, "resid-rs MOS8580"),
+];
+
+/// Network SID Device, emulating the chips driven by a client such as
+/// JSidplay2 or ACID64.
+///
+/// Each request consists of a command, a SID number, a 16-bit big-endian
+/// data length and the data. Writes carry the number of cycles to clock
+/// before each write, so the chips are clocked exactly as on the client.
+/// The mixed output of all chips is buffered until taken by take_samples,
+/// or passed to the audio sink of serve after each request. Writes and
+/// delays are answered busy while the buffer is full, and the client retries
+/// them later.
+///
+/// Mute, stereo position, level, delay, fade and tune header commands are
+/// acknowledged, but have no effect.
+pub struct NetworkSidDevice {
+    // Configuration
+    clock: Clock,
+    sample_freq: u32,
+    sampling_method: SamplingMethod,
+    chip_models: Vec<ChipModel>,
+    max_samples: usize,
+    // Runtime State
+    sids: Vec<Sid>,
+    samples: Vec<i16>,
+    mix: Vec<i32>,
+}
+
+impl NetworkSidDevice {
+    /// Create a device with a single MOS6581 on a PAL clock. Up to
+    /// max_samples output samples are buffered. Fails if the sample
+    /// frequency is invalid, see Sid::set_sampling_parameters.
+    pub fn new(sample_freq: u32, max_samples: usize) -> Result<Self, Error> {
+        let mut device = NetworkSidDevice {
+            clock: Clock::Pal,
+            sample_freq,
+            sampling_method: SamplingMethod::Fast,
+            chip_models: vec![ChipModel::Mos6581],
+            max_samples,
+            sids: Vec::new(),
+            samples: Vec::new(),
+            mix: Vec::new(),
+        };
+        device.configure()?;
+        Ok(device)
+    }
+
+    pub fn get_sids(&self) -> &[Sid] {
+        &self.sids
+    }
+
+    /// Take the buffered output samples.
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        self.samples.split_off(0)
+    }
+
+    /// Process a request, appending the response to response.
+    pub fn process(&mut self, command: u8, sid: u8, data: &[u8], response: &mut Vec<u8>) {
+        let sid = sid as usize;
+        match command {
+            FLUSH => {
+                self.samples.clear();
+                response.push(OK);
+            }
+            TRY_SET_SID_COUNT => {
+                if sid == 0 || sid > MAX_SID_COUNT {
+                    response.push(ERR);
+                    return;
+                }
+                self.chip_models.resize(sid, ChipModel::Mos6581);
+                response.push(self.reconfigure());
+            }
+            TRY_RESET => {
+                for chip in self.sids.iter_mut() {
+                    chip.reset();
+                    // The volume is set after the reset to avoid clicks.
+                    if let Some(&volume) = data.first() {
+                        chip.write(0x18, volume);
+                    }
+                }
+                self.samples.clear();
+                response.push(OK);
+            }
+            TRY_DELAY => {
+                if data.len() != 2 {
+                    response.push(ERR);
+                } else if self.is_busy() {
+                    response.push(BUSY);
+                } else {
+                    self.clock_delta(read_cycles(data));
+                    response.push(OK);
+                }
+            }
+            TRY_WRITE => {
+                if data.len() & 0x03 != 0 || !self.check_writes(data) {
+                    response.push(ERR);
+                } else if self.is_busy() {
+                    response.push(BUSY);
+                } else {
+                    self.write_packets(data);
+                    response.push(OK);
+                }
+            }
+            TRY_READ => {
+                // Writes followed by the cycles and register of the read.
+                if data.len() < 3 || (data.len() - 3) & 0x03 != 0 {
+                    response.push(ERR);
+                    return;
+                }
+                let (writes, read) = data.split_at(data.len() - 3);
+                let chip = (read[2] >> 5) as usize;
+                if !self.check_writes(writes) || chip >= self.sids.len() {
+                    response.push(ERR);
+                } else if self.is_busy() {
+                    response.push(BUSY);
+                } else {
+                    self.write_packets(writes);
+                    self.clock_delta(read_cycles(read));
+                    response.push(READ);
+                    response.push(self.sids[chip].read(read[2] & 0x1f));
+                }
+            }
+            GET_VERSION => {
+                response.push(VERSION);
+                response.push(PROTOCOL_VERSION);
+            }
+            TRY_SET_SAMPLING => {
+                let previous = self.sampling_method;
+                self.sampling_method = match data.first() {
+                    Some(0) => SamplingMethod::Fast,
+                    Some(1) => SamplingMethod::Resample,
+                    _ => {
+                        response.push(ERR);
+                        return;
+                    }
+                };
+                let result = self.reconfigure();
+                if result != OK {
+                    self.sampling_method = previous;
+                }
+                response.push(result);
+            }
+            SET_CLOCKING => {
+                self.clock = match data.first() {
+                    Some(0) => Clock::Pal,
+                    Some(1) => Clock::Ntsc,
+                    _ => {
+                        response.push(ERR);
+                        return;
+                    }
+                };
+                response.push(self.reconfigure());
+            }
+            GET_CONFIG_COUNT => {
+                response.push(COUNT);
+                response.push(CONFIGS.len() as u8);
+            }
+            GET_CONFIG_INFO => match CONFIGS.get(sid) {
+                Some(&(chip_model, name)) => {
+                    response.push(INFO);
+                    // The clean chip is announced as a MOS8580.
+                    response.push(match chip_model {
+                        ChipModel::Mos6581 => 0,
+                        ChipModel::Mos8580 | ChipModel::Clean => 1,
+                    });
+                    response.extend_from_slice(name.as_bytes());
+                    response.push(0);
+                }
+                None => response.push(ERR),
+            },
+            TRY_SET_SID_MODEL => match data.first().and_then(|&i| CONFIGS.get(i as usize)) {
+                Some(&(chip_model, _name)) if sid < self.sids.len() => {
+                    self.chip_models[sid] = chip_model;
+                    self.sids[sid].set_chip_model(chip_model);
+                    response.push(OK);
+                }
+                _ => response.push(ERR),
+            },
+            MUTE | SET_SID_POSITION | SET_SID_LEVEL | SET_DELAY | SET_FADE_IN | SET_FADE_OUT
+            | SET_SID_HEADER => response.push(OK),
+            _ => response.push(ERR),
+        }
+    }
+
+    /// Serve a client connection until it is closed, passing the output
+    /// samples to sink after each request, e.g. to play them.
+    pub fn serve<S, F>(&mut self, mut stream: S, mut sink: F) -> io::Result<()>
+    where
+        S: Read + Write,
+        F: FnMut(&[i16]),
+    {
+        let mut header = [0u8; 4];
+        let mut data = Vec::new();
+        let mut response = Vec::new();
+        loop {
+            match stream.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            }
+            let len = (header[2] as usize) << 8 | header[3] as usize;
+            data.resize(len, 0);
+            stream.read_exact(&mut data)?;
+            response.clear();
+            self.process(header[0], header[1], &data, &mut response);
+            if !self.samples.is_empty() {
+                sink(&self.samples);
+                self.samples.clear();
+            }
+            stream.write_all(&response)?;
+        }
+    }
+
+    fn configure(&mut self) -> Result<(), Error> {
+        let mut sids = Vec::with_capacity(self.chip_models.len());
+        for &chip_model in self.chip_models.iter() {
+            let sid = SidBuilder::new(chip_model)
+                .clock(self.clock)
+                .sample_freq(self.sample_freq)
+                .sampling_method(self.sampling_method)
+                .build()?;
+            sids.push(sid);
+        }
+        self.sids = sids;
+        self.samples.clear();
+        Ok(())
+    }
+
+    /// Create the chips with the current configuration, retaining the
+    /// chips if the configuration is invalid.
+    fn reconfigure(&mut self) -> u8 {
+        let chip_models = self.chip_models.clone();
+        match self.configure() {
+            Ok(()) => OK,
+            Err(_) => {
+                self.chip_models = chip_models;
+                ERR
+            }
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        self.samples.len() >= self.max_samples
+    }
+
+    fn check_writes(&self, data: &[u8]) -> bool {
+        data.chunks(4)
+            .all(|packet| ((packet[2] >> 5) as usize) < self.sids.len())
+    }
+
+    /// Clock the chips by the cycles of each packet, then write the value.
+    fn write_packets(&mut self, data: &[u8]) {
+        for packet in data.chunks(4) {
+            self.clock_delta(read_cycles(packet));
+            let chip = (packet[2] >> 5) as usize;
+            self.sids[chip].write(packet[2] & 0x1f, packet[3]);
+        }
+    }
+
+    fn clock_delta(&mut self, cycles: u32) {
+        let mut buffer = [0i16; 1024];
+        for (i, sid) in self.sids.iter_mut().enumerate() {
+            let mut index = 0;
+            let mut delta = cycles;
+            while delta > 0 {
+                let (samples, next_delta) = sid.sample(delta, &mut buffer, 1);
+                if i == 0 {
+                    self.mix.resize(index + samples, 0);
+                }
+                for (j, &sample) in buffer[..samples].iter().enumerate() {
+                    if let Some(mixed) = self.mix.get_mut(index + j) {
+                        *mixed += sample as i32;
+                    }
+                }
+                index += samples;
+                delta = next_delta;
+            }
+        }
+        for sample in self.mix.drain(..) {
+            self.samples.push(sample.clamp(-32768, 32767) as i16);
+        }
+    }
+}
+
+fn read_cycles(data: &[u8]) -> u32 {
+    (data[0] as u32) << 8 | data[1] as u32
+}
+
+/// Accept clients one at a time on the address, e.g.
+/// ("127.0.0.1", DEFAULT_PORT), serving each with the device and passing
+/// the output samples to sink. Runs until an error occurs.
+pub fn run<A, F>(addr: A, device: &mut NetworkSidDevice, mut sink: F) -> io::Result<()>
+where
+    A: ToSocketAddrs,
+    F: FnMut(&[i16]),
+{
+    let listener = TcpListener::bind(addr)?;
+    for stream in listener.incoming() {
+        let stream = stream?;
+        stream.set_nodelay(true)?;
+        device.serve(stream, &mut sink)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
+
+/// Number of cycles of one measurement, i.e. between each POT register update.
+const MEASUREMENT_CYCLES: u32 = 512;
+/// Number of cycles the POT capacitor is discharged at the start of each
+/// measurement.
+const DISCHARGE_CYCLES: u32 = 256;
+
+/// Pot resistance yielding the maximum count, with the 1000pF capacitors
+/// found in the Commodore 64. The count is approximately proportional to the
+/// resistance.
+const FULL_SCALE_RESISTANCE: f64 = 470_000.0;
+
+/// Potentiometer (paddle) input.
+///
+/// The pot inputs are measured by a capacitor charged through the pot. Each
+/// measurement takes 512 cycles. For the first 256 cycles the capacitor is
+/// discharged. The capacitor is then released, and an 8-bit counter counts
+/// the cycles until the capacitor voltage crosses a threshold. The count at
+/// the threshold crossing is latched into the POT register. If the threshold
+/// is not crossed, the count ends at 255.
+///
+/// Changes of the pot input thus show up in the POT register within 512
+/// cycles.
+pub struct Potentiometer {
+    // Configuration
+    value: u8,
+    // Runtime State
+    pub cycle: u32,
+    pub latched: u8,
+}
+
+impl Default for Potentiometer {
+    fn default() -> Self {
+        let mut pot = Potentiometer {
+            value: 0xff,
+            cycle: 0,
+            latched: 0,
+        };
+        pot.reset();
+        pot
+    }
+}
+
+impl Potentiometer {
+    pub fn get_value(&self) -> u8 {
+        self.value
+    }
+
+    /// Set the pot input as the count at the threshold crossing. An open
+    /// input, i.e. with no paddle connected, counts to 255.
+    pub fn set_value(&mut self, value: u8) {
+        self.value = value;
+    }
+
+    /// Set the pot input as a resistance in Ohm.
+    pub fn set_resistance(&mut self, resistance: f64) {
+        let count = resistance.max(0.0) / FULL_SCALE_RESISTANCE * 255.0 + 0.5;
+        self.value = if count >= 255.0 { 255 } else { count as u8 };
+    }
+
+    #[inline]
+    pub fn clock(&mut self) {
+        self.clock_delta(1);
+    }
+
+    #[inline]
+    pub fn clock_delta(&mut self, delta: u32) {
+        // Find the first threshold crossing within the delta period.
+        let latch_cycle = DISCHARGE_CYCLES + self.value as u32;
+        let next_latch_cycle = if latch_cycle > self.cycle {
+            latch_cycle
+        } else {
+            latch_cycle + MEASUREMENT_CYCLES
+        };
+        let cycle = self.cycle as u64 + delta as u64;
+        if cycle >= next_latch_cycle as u64 {
+            self.latched = self.value;
+        }
+        self.cycle = (cycle % MEASUREMENT_CYCLES as u64) as u32;
+    }
+
+    pub fn read(&self) -> u8 {
+        self.latched
+    }
+
+    pub fn reset(&mut self) {
+        self.cycle = 0;
+        self.latched = 0xff;
+    }
+}
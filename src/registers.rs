@@ -0,0 +1,313 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
+
+use core::convert::TryFrom;
+
+use bit_field::BitField;
+
+use super::builder::Clock;
+use super::sid::{Reg, Sid};
+
+/// Waveform selected by the control register bits 4-7.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    None,
+    Triangle,
+    Sawtooth,
+    Pulse,
+    Noise,
+}
+
+/// Voice control register.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Control {
+    pub gate: bool,
+    pub sync: bool,
+    pub ring_mod: bool,
+    pub test: bool,
+    pub triangle: bool,
+    pub sawtooth: bool,
+    pub pulse: bool,
+    pub noise: bool,
+}
+
+impl Control {
+    /// Select a single waveform, deselecting any other.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.triangle = waveform == Waveform::Triangle;
+        self.sawtooth = waveform == Waveform::Sawtooth;
+        self.pulse = waveform == Waveform::Pulse;
+        self.noise = waveform == Waveform::Noise;
+    }
+}
+
+impl From<u8> for Control {
+    fn from(value: u8) -> Self {
+        Control {
+            gate: value.get_bit(0),
+            sync: value.get_bit(1),
+            ring_mod: value.get_bit(2),
+            test: value.get_bit(3),
+            triangle: value.get_bit(4),
+            sawtooth: value.get_bit(5),
+            pulse: value.get_bit(6),
+            noise: value.get_bit(7),
+        }
+    }
+}
+
+impl From<Control> for u8 {
+    fn from(control: Control) -> Self {
+        let mut value = 0u8;
+        value.set_bit(0, control.gate);
+        value.set_bit(1, control.sync);
+        value.set_bit(2, control.ring_mod);
+        value.set_bit(3, control.test);
+        value.set_bit(4, control.triangle);
+        value.set_bit(5, control.sawtooth);
+        value.set_bit(6, control.pulse);
+        value.set_bit(7, control.noise);
+        value
+    }
+}
+
+/// Voice attack and decay register. The rates are 4 bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AttackDecay {
+    pub attack: u8,
+    pub decay: u8,
+}
+
+impl From<u8> for AttackDecay {
+    fn from(value: u8) -> Self {
+        AttackDecay {
+            attack: value >> 4,
+            decay: value & 0x0f,
+        }
+    }
+}
+
+impl From<AttackDecay> for u8 {
+    fn from(attack_decay: AttackDecay) -> Self {
+        (attack_decay.attack & 0x0f) << 4 | attack_decay.decay & 0x0f
+    }
+}
+
+/// Voice sustain and release register. The sustain level and release rate
+/// are 4 bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SustainRelease {
+    pub sustain: u8,
+    pub release: u8,
+}
+
+impl From<u8> for SustainRelease {
+    fn from(value: u8) -> Self {
+        SustainRelease {
+            sustain: value >> 4,
+            release: value & 0x0f,
+        }
+    }
+}
+
+impl From<SustainRelease> for u8 {
+    fn from(sustain_release: SustainRelease) -> Self {
+        (sustain_release.sustain & 0x0f) << 4 | sustain_release.release & 0x0f
+    }
+}
+
+/// Filter resonance and routing register. The resonance is 4 bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResFilt {
+    pub resonance: u8,
+    pub filt1: bool,
+    pub filt2: bool,
+    pub filt3: bool,
+    pub filt_ext: bool,
+}
+
+impl From<u8> for ResFilt {
+    fn from(value: u8) -> Self {
+        ResFilt {
+            resonance: value >> 4,
+            filt1: value.get_bit(0),
+            filt2: value.get_bit(1),
+            filt3: value.get_bit(2),
+            filt_ext: value.get_bit(3),
+        }
+    }
+}
+
+impl From<ResFilt> for u8 {
+    fn from(res_filt: ResFilt) -> Self {
+        let mut value = (res_filt.resonance & 0x0f) << 4;
+        value.set_bit(0, res_filt.filt1);
+        value.set_bit(1, res_filt.filt2);
+        value.set_bit(2, res_filt.filt3);
+        value.set_bit(3, res_filt.filt_ext);
+        value
+    }
+}
+
+/// Filter mode and volume register. The volume is 4 bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModeVol {
+    pub volume: u8,
+    pub low_pass: bool,
+    pub band_pass: bool,
+    pub high_pass: bool,
+    pub voice3_off: bool,
+}
+
+impl From<u8> for ModeVol {
+    fn from(value: u8) -> Self {
+        ModeVol {
+            volume: value & 0x0f,
+            low_pass: value.get_bit(4),
+            band_pass: value.get_bit(5),
+            high_pass: value.get_bit(6),
+            voice3_off: value.get_bit(7),
+        }
+    }
+}
+
+impl From<ModeVol> for u8 {
+    fn from(mode_vol: ModeVol) -> Self {
+        let mut value = mode_vol.volume & 0x0f;
+        value.set_bit(4, mode_vol.low_pass);
+        value.set_bit(5, mode_vol.band_pass);
+        value.set_bit(6, mode_vol.high_pass);
+        value.set_bit(7, mode_vol.voice3_off);
+        value
+    }
+}
+
+/// Register level control of a single voice, see Sid::voice.
+///
+/// All setters write the voice registers through Sid::write, so the bus value
+/// and the register write timing are exactly as for direct writes.
+///
+/// ```
+/// use resid::registers::Waveform;
+/// use resid::{ChipModel, Clock, Sid};
+///
+/// let mut sid = Sid::new(ChipModel::Mos6581);
+/// sid.voice(0)
+///     .unwrap()
+///     .set_waveform(Waveform::Pulse)
+///     .set_pulse_width(0x800)
+///     .set_frequency_hz(440.0, Clock::Pal)
+///     .set_gate(true);
+/// ```
+pub struct VoiceControl<'a> {
+    sid: &'a mut Sid,
+    offset: u8,
+}
+
+impl<'a> VoiceControl<'a> {
+    /// Typed register access to voice 0, 1 or 2. Returns None for any other
+    /// voice.
+    pub(crate) fn new(sid: &'a mut Sid, voice: usize) -> Option<Self> {
+        if voice < 3 {
+            Some(VoiceControl {
+                sid,
+                offset: voice as u8 * 7,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn read(&self, reg: Reg) -> u8 {
+        match Reg::try_from(self.offset + reg.addr()) {
+            Ok(reg) => self.sid.get_register(reg),
+            Err(_) => 0,
+        }
+    }
+
+    fn write(&mut self, reg: Reg, value: u8) -> &mut Self {
+        self.sid.write(self.offset + reg.addr(), value);
+        self
+    }
+
+    pub fn get_attack_decay(&self) -> AttackDecay {
+        AttackDecay::from(self.read(Reg::AD1))
+    }
+
+    pub fn get_control(&self) -> Control {
+        Control::from(self.read(Reg::CR1))
+    }
+
+    pub fn get_frequency(&self) -> u16 {
+        let hi = self.read(Reg::FREQHI1);
+        let lo = self.read(Reg::FREQLO1);
+        (hi as u16) << 8 | lo as u16
+    }
+
+    pub fn get_pulse_width(&self) -> u16 {
+        let hi = self.read(Reg::PWHI1);
+        let lo = self.read(Reg::PWLO1);
+        (hi as u16) << 8 | lo as u16
+    }
+
+    pub fn get_sustain_release(&self) -> SustainRelease {
+        SustainRelease::from(self.read(Reg::SR1))
+    }
+
+    pub fn set_attack_decay(&mut self, attack_decay: AttackDecay) -> &mut Self {
+        self.write(Reg::AD1, attack_decay.into())
+    }
+
+    pub fn set_control(&mut self, control: Control) -> &mut Self {
+        self.write(Reg::CR1, control.into())
+    }
+
+    pub fn set_frequency(&mut self, frequency: u16) -> &mut Self {
+        self.write(Reg::FREQLO1, frequency as u8);
+        self.write(Reg::FREQHI1, (frequency >> 8) as u8)
+    }
+
+    /// Set the oscillator frequency in Hz, for the given system clock. The
+    /// frequency is rounded to the nearest register value, and limited to
+    /// the maximum register value.
+    pub fn set_frequency_hz(&mut self, frequency: f64, clock: Clock) -> &mut Self {
+        // The 24-bit accumulator is incremented by the register value each
+        // cycle.
+        let value = frequency * (1u32 << 24) as f64 / clock.frequency() as f64 + 0.5;
+        let value = if value >= 65535.0 {
+            0xffff
+        } else if value > 0.0 {
+            value as u16
+        } else {
+            0
+        };
+        self.set_frequency(value)
+    }
+
+    pub fn set_gate(&mut self, gate: bool) -> &mut Self {
+        let mut control = self.get_control();
+        control.gate = gate;
+        self.set_control(control)
+    }
+
+    /// Set the 12-bit pulse width.
+    pub fn set_pulse_width(&mut self, pulse_width: u16) -> &mut Self {
+        self.write(Reg::PWLO1, pulse_width as u8);
+        self.write(Reg::PWHI1, (pulse_width >> 8) as u8 & 0x0f)
+    }
+
+    pub fn set_sustain_release(&mut self, sustain_release: SustainRelease) -> &mut Self {
+        self.write(Reg::SR1, sustain_release.into())
+    }
+
+    /// Select a single waveform, retaining the other control bits.
+    pub fn set_waveform(&mut self, waveform: Waveform) -> &mut Self {
+        let mut control = self.get_control();
+        control.set_waveform(waveform);
+        self.set_control(control)
+    }
+}
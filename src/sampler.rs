@@ -17,6 +17,7 @@ use libm::F64Ext;
 
 use core::f64;
 
+use super::error::Error;
 #[cfg(not(feature = "std"))]
 use super::math;
 use super::synth::Synth;
@@ -32,11 +33,23 @@ const FIR_RES_FAST: i32 = 51473;
 const FIR_RES_INTERPOLATE: i32 = 285;
 const FIR_SHIFT: i32 = 15;
 const RING_SIZE: usize = 16384;
+// Maximum FIR filter order + 1, per cycle per sample.
+const FIR_N_MAX: u64 = 125;
+// Maximum number of cycles per sample, bounded by the fixpoint sample offset.
+const CYCLES_PER_SAMPLE_MAX: u64 = 16384;
 
 const FIXP_SHIFT: i32 = 16;
 const FIXP_MASK: i32 = 0xffff;
 
-#[derive(Clone, Copy, PartialEq)]
+/// Audio sampling method.
+///
+/// All methods require a sample frequency above zero and at most equal to
+/// the clock frequency. Fast and Interpolate support clock frequencies up to
+/// 16384 times the sample frequency. Resample and ResampleFast keep the
+/// filter input in a ring buffer of 16384 cycles, which limits the clock
+/// frequency to 131 times the sample frequency, e.g. a sample frequency of
+/// at least 7.6kHz for a PAL clock.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SamplingMethod {
     Fast,
     Interpolate,
@@ -84,7 +97,13 @@ impl Sampler {
         }
     }
 
-    pub fn set_parameters(&mut self, method: SamplingMethod, clock_freq: u32, sample_freq: u32) {
+    pub fn set_parameters(
+        &mut self,
+        method: SamplingMethod,
+        clock_freq: u32,
+        sample_freq: u32,
+    ) -> Result<(), Error> {
+        Self::check_parameters(method, clock_freq, sample_freq)?;
         self.cycles_per_sample =
             (clock_freq as f64 / sample_freq as f64 * (1 << FIXP_SHIFT) as f64 + 0.5) as u32;
         self.sampling_method = method;
@@ -100,6 +119,38 @@ impl Sampler {
         self.index = 0;
         self.offset = 0;
         self.prev_sample = 0;
+        Ok(())
+    }
+
+    /// Check the supported range of clock and sample frequencies, see
+    /// SamplingMethod.
+    pub fn check_parameters(
+        method: SamplingMethod,
+        clock_freq: u32,
+        sample_freq: u32,
+    ) -> Result<(), Error> {
+        if sample_freq == 0 {
+            return Err(Error::InvalidSampleFrequency(sample_freq));
+        }
+        if sample_freq > clock_freq {
+            return Err(Error::SampleFrequencyAboveClock {
+                clock_freq,
+                sample_freq,
+            });
+        }
+        let max_cycles_per_sample = match method {
+            SamplingMethod::Fast | SamplingMethod::Interpolate => CYCLES_PER_SAMPLE_MAX,
+            // The sample ring buffer must hold the FIR filter input.
+            SamplingMethod::Resample | SamplingMethod::ResampleFast => RING_SIZE as u64 / FIR_N_MAX,
+        };
+        if clock_freq as u64 >= max_cycles_per_sample * sample_freq as u64 {
+            return Err(Error::SampleFrequencyTooLow {
+                method,
+                clock_freq,
+                sample_freq,
+            });
+        }
+        Ok(())
     }
 
     pub fn reset(&mut self) {
@@ -3,12 +3,32 @@
 // Portions (c) 2004 Dag Lem <resid@nimrod.no>
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+use core::convert::TryFrom;
+
+use super::chip_profile::ChipProfile;
+use super::combined_waveform::CombinedWaveforms;
+use super::dac::DacConfig;
 use super::envelope::State as EnvState;
+use super::error::Error;
+use super::external_filter::OutputStage;
+use super::pot::Potentiometer;
+use super::registers::{ModeVol, ResFilt, VoiceControl};
 use super::sampler::{Sampler, SamplingMethod};
 use super::synth::Synth;
+use super::write_log::{RegisterWrite, WriteLog};
 use super::ChipModel;
 
-#[derive(Clone, Copy)]
+/// The MOS8580 "digi boost" hardware hack connects EXT IN to ground via a
+/// resistor, which corresponds to a constant input at the minimum sample
+/// value. The resulting DC level is multiplied by the volume, like the
+/// mixer DC offset of the MOS6581.
+const DIGI_BOOST_INPUT: i32 = -32768;
+
+/// The SID decodes only the five low address lines, so the registers are
+/// mirrored every 32 bytes in the $D400-$D7FF window.
+const REG_MASK: u8 = 0x1f;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Reg {
     FREQLO1,
     FREQHI1,
@@ -42,130 +62,294 @@ pub enum Reg {
 }
 
 impl Reg {
+    /// Panics on offsets above 0x1c.
+    #[deprecated(note = "use Reg::try_from, which returns an error instead")]
     pub fn from(reg: u8) -> Reg {
-        match reg {
-            0x00 => Reg::FREQLO1,
-            0x01 => Reg::FREQHI1,
-            0x02 => Reg::PWLO1,
-            0x03 => Reg::PWHI1,
-            0x04 => Reg::CR1,
-            0x05 => Reg::AD1,
-            0x06 => Reg::SR1,
-            0x07 => Reg::FREQLO2,
-            0x08 => Reg::FREQHI2,
-            0x09 => Reg::PWLO2,
-            0x0a => Reg::PWHI2,
-            0x0b => Reg::CR2,
-            0x0c => Reg::AD2,
-            0x0d => Reg::SR2,
-            0x0e => Reg::FREQLO3,
-            0x0f => Reg::FREQHI3,
-            0x10 => Reg::PWLO3,
-            0x11 => Reg::PWHI3,
-            0x12 => Reg::CR3,
-            0x13 => Reg::AD3,
-            0x14 => Reg::SR3,
-            0x15 => Reg::FCLO,
-            0x16 => Reg::FCHI,
-            0x17 => Reg::RESFILT,
-            0x18 => Reg::MODVOL,
-            0x19 => Reg::POTX,
-            0x1a => Reg::POTY,
-            0x1b => Reg::OSC3,
-            0x1c => Reg::ENV3,
-            _ => panic!("invalid reg {}", reg),
+        match Reg::try_from(reg) {
+            Ok(reg) => reg,
+            Err(_) => panic!("invalid reg {}", reg),
         }
     }
 
-    #[allow(dead_code)]
     pub fn addr(self) -> u8 {
         self as u8
     }
 }
 
+impl TryFrom<u8> for Reg {
+    type Error = Error;
+
+    fn try_from(reg: u8) -> Result<Self, Self::Error> {
+        match reg {
+            0x00 => Ok(Reg::FREQLO1),
+            0x01 => Ok(Reg::FREQHI1),
+            0x02 => Ok(Reg::PWLO1),
+            0x03 => Ok(Reg::PWHI1),
+            0x04 => Ok(Reg::CR1),
+            0x05 => Ok(Reg::AD1),
+            0x06 => Ok(Reg::SR1),
+            0x07 => Ok(Reg::FREQLO2),
+            0x08 => Ok(Reg::FREQHI2),
+            0x09 => Ok(Reg::PWLO2),
+            0x0a => Ok(Reg::PWHI2),
+            0x0b => Ok(Reg::CR2),
+            0x0c => Ok(Reg::AD2),
+            0x0d => Ok(Reg::SR2),
+            0x0e => Ok(Reg::FREQLO3),
+            0x0f => Ok(Reg::FREQHI3),
+            0x10 => Ok(Reg::PWLO3),
+            0x11 => Ok(Reg::PWHI3),
+            0x12 => Ok(Reg::CR3),
+            0x13 => Ok(Reg::AD3),
+            0x14 => Ok(Reg::SR3),
+            0x15 => Ok(Reg::FCLO),
+            0x16 => Ok(Reg::FCHI),
+            0x17 => Ok(Reg::RESFILT),
+            0x18 => Ok(Reg::MODVOL),
+            0x19 => Ok(Reg::POTX),
+            0x1a => Ok(Reg::POTY),
+            0x1b => Ok(Reg::OSC3),
+            0x1c => Ok(Reg::ENV3),
+            _ => Err(Error::InvalidRegister(reg)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct State {
     // Sid
     pub sid_register: [u8; 32],
     pub bus_value: u8,
-    pub bus_value_ttl: u32,
+    pub bus_value_ttl: [u32; 8],
+    pub cycle: u64,
     pub ext_in: i32,
+    pub pot_cycle: [u32; 2],
     // Wave
     pub accumulator: [u32; 3],
     pub shift_register: [u32; 3],
+    pub shift_pipeline: [u8; 3],
+    pub shift_register_reset: [u32; 3],
+    pub floating_output: [u16; 3],
+    pub floating_output_ttl: [u32; 3],
+    pub slew_output: [u16; 3],
     // Envelope
     pub envelope_state: [u8; 3],
+    pub next_envelope_state: [u8; 3],
+    pub envelope_state_pipeline: [u8; 3],
     pub envelope_counter: [u8; 3],
+    pub envelope_pipeline: [u8; 3],
+    pub env3: [u8; 3],
     pub exponential_counter: [u8; 3],
+    pub exponential_pipeline: [u8; 3],
     pub exponential_counter_period: [u8; 3],
+    pub new_exponential_counter_period: [u8; 3],
     pub hold_zero: [u8; 3],
     pub rate_counter: [u16; 3],
     pub rate_counter_period: [u16; 3],
+    pub reset_rate_counter: [u8; 3],
 }
 
 pub struct Sid {
     // Functional Units
     sampler: Sampler,
+    pot_x: Potentiometer,
+    pot_y: Potentiometer,
+    // Configuration
+    chip_model: ChipModel,
+    sampling_method: SamplingMethod,
+    clock_freq: u32,
+    sample_freq: u32,
+    bus_value_ttl_time: [u32; 8],
+    // Chip parameters or settings changed from those of Sid::new, which are
+    // not stored in write logs.
+    custom_settings: bool,
     // Runtime State
     bus_value: u8,
-    bus_value_ttl: u32,
+    bus_value_ttl: [u32; 8],
+    cycle: u64,
+    recording: Option<WriteLog>,
 }
 
 impl Sid {
     pub fn new(chip_model: ChipModel) -> Self {
-        let synth = Synth::new(chip_model);
+        let mut sid = Sid::with_profile(&ChipProfile::new(chip_model));
+        sid.custom_settings = false;
+        sid
+    }
+
+    /// Create a SID with custom chip parameters, e.g. to model a specific chip
+    /// revision or clone.
+    pub fn with_profile(profile: &ChipProfile) -> Self {
+        let synth = Synth::with_profile(profile);
         let mut sid = Sid {
             sampler: Sampler::new(synth),
+            pot_x: Potentiometer::default(),
+            pot_y: Potentiometer::default(),
+            chip_model: profile.chip_model,
+            sampling_method: SamplingMethod::Fast,
+            clock_freq: 0,
+            sample_freq: 0,
+            bus_value_ttl_time: profile.bus_value_ttl,
+            custom_settings: true,
             bus_value: 0,
-            bus_value_ttl: 0,
+            bus_value_ttl: [0; 8],
+            cycle: 0,
+            recording: None,
         };
-        sid.set_sampling_parameters(SamplingMethod::Fast, 985_248, 44100);
+        sid.set_sampling_parameters(SamplingMethod::Fast, 985_248, 44100)
+            .unwrap();
         sid
     }
 
+    /// Set the sampling method, the clock frequency and the sample frequency
+    /// in Hz. Returns an error if the frequencies are outside the range
+    /// supported by the sampling method, see SamplingMethod.
     pub fn set_sampling_parameters(
         &mut self,
         method: SamplingMethod,
         clock_freq: u32,
         sample_freq: u32,
-    ) {
-        self.sampler.set_parameters(method, clock_freq, sample_freq);
+    ) -> Result<(), Error> {
+        self.sampler
+            .set_parameters(method, clock_freq, sample_freq)?;
+        self.sampling_method = method;
+        self.clock_freq = clock_freq;
+        self.sample_freq = sample_freq;
+        Ok(())
     }
 
     pub fn clock(&mut self) {
+        self.cycle += 1;
         // Age bus value.
-        if self.bus_value_ttl > 0 {
-            self.bus_value_ttl -= 1;
-            if self.bus_value_ttl == 0 {
-                self.bus_value = 0;
-            }
-        }
+        self.age_bus_value(1);
+        // Clock pot measurement.
+        self.pot_x.clock();
+        self.pot_y.clock();
         // Clock synthesizer.
         self.sampler.synth.clock();
     }
 
     pub fn clock_delta(&mut self, delta: u32) {
+        self.cycle += delta as u64;
         // Age bus value.
-        if self.bus_value_ttl >= delta {
-            self.bus_value_ttl -= delta;
-        } else {
-            self.bus_value_ttl = 0;
-        }
-        if self.bus_value_ttl == 0 {
-            self.bus_value = 0;
-        }
+        self.age_bus_value(delta);
+        // Clock pot measurement.
+        self.pot_x.clock_delta(delta);
+        self.pot_y.clock_delta(delta);
         // Clock synthesizer.
         self.sampler.synth.clock_delta(delta);
     }
 
+    /// Switch the chip model while playing. The registers, oscillator phases,
+    /// envelope states and filter states are retained, whereas the chip
+    /// parameters, including any set by e.g. set_dac or set_output_stage, are
+    /// replaced by those of the chip model.
+    pub fn set_chip_model(&mut self, chip_model: ChipModel) {
+        let custom_settings = self.custom_settings;
+        self.set_profile(&ChipProfile::new(chip_model));
+        self.custom_settings = custom_settings;
+    }
+
+    /// Switch the chip parameters while playing, see set_chip_model.
+    pub fn set_profile(&mut self, profile: &ChipProfile) {
+        self.custom_settings = true;
+        self.sampler.synth.set_profile(profile);
+        self.chip_model = profile.chip_model;
+        self.bus_value_ttl_time = profile.bus_value_ttl;
+    }
+
+    /// Replace the combined waveform lookup tables of all voices.
+    pub fn set_combined_waveforms(&mut self, combined_waveforms: CombinedWaveforms) {
+        self.custom_settings = true;
+        for voice in self.sampler.synth.voices.iter() {
+            voice
+                .wave
+                .borrow_mut()
+                .set_combined_waveforms(combined_waveforms.clone());
+        }
+    }
+
+    /// Model the non-linearity of the waveform, envelope and volume DACs,
+    /// e.g. `DacConfig::from_chip_model(ChipModel::Mos6581)`. The DACs are
+    /// ideal by default.
+    pub fn set_dac(&mut self, config: Option<DacConfig>) {
+        self.custom_settings |= config.is_some();
+        for voice in self.sampler.synth.voices.iter_mut() {
+            voice.set_dac(config);
+        }
+        self.sampler.synth.filter.set_volume_dac(config);
+    }
+
+    /// Model the limited slew rate of the waveform outputs, which makes pulse
+    /// and sawtooth edges ramp over a few cycles.
+    pub fn enable_edge_slew(&mut self, enabled: bool) {
+        self.custom_settings |= enabled;
+        for voice in self.sampler.synth.voices.iter() {
+            voice.wave.borrow_mut().set_slew_enabled(enabled);
+        }
+    }
+
     pub fn enable_external_filter(&mut self, enabled: bool) {
+        self.custom_settings |= !enabled;
         self.sampler.synth.ext_filter.set_enabled(enabled);
     }
 
+    /// Select the audio output stage RC networks, e.g. to model a different
+    /// board. This takes effect immediately, without a reset.
+    pub fn set_output_stage(&mut self, output_stage: OutputStage) {
+        self.custom_settings = true;
+        self.sampler.synth.ext_filter.set_output_stage(output_stage);
+    }
+
     pub fn enable_filter(&mut self, enabled: bool) {
+        self.custom_settings |= !enabled;
         self.sampler.synth.filter.set_enabled(enabled);
     }
 
+    /// Enable the MOS8580 "digi boost" hardware hack, which makes samples
+    /// played by writing the volume register audible. The boost is added to
+    /// any EXT IN input.
+    pub fn enable_digi_boost(&mut self, enabled: bool) {
+        self.custom_settings |= enabled;
+        let ext_in_dc = if enabled {
+            (DIGI_BOOST_INPUT << 4) * 3
+        } else {
+            0
+        };
+        self.sampler.synth.ext_in_dc = ext_in_dc;
+        self.sampler.synth.ext_filter.set_ext_in_dc(ext_in_dc);
+    }
+
+    /// Set the mixer input DC offset, which is multiplied by the volume.
+    /// This determines the loudness of samples played by writing the volume
+    /// register.
+    pub fn set_mixer_dc(&mut self, mixer_dc: i32) {
+        self.custom_settings = true;
+        self.sampler.synth.filter.set_mixer_dc(mixer_dc);
+    }
+
+    /// Set the waveform output "zero" level and the envelope DAC DC offset
+    /// of all voices.
+    pub fn set_voice_dc(&mut self, wave_zero: i32, voice_dc: i32) {
+        self.custom_settings = true;
+        for voice in self.sampler.synth.voices.iter_mut() {
+            voice.set_wave_zero(wave_zero);
+            voice.set_voice_dc(voice_dc);
+        }
+    }
+
+    /// Set the pot inputs as the POTX and POTY counts, see Potentiometer.
+    pub fn set_pot(&mut self, x: u8, y: u8) {
+        self.pot_x.set_value(x);
+        self.pot_y.set_value(y);
+    }
+
+    /// Set the pot inputs as resistances in Ohm, see Potentiometer.
+    pub fn set_pot_resistance(&mut self, x: f64, y: f64) {
+        self.pot_x.set_resistance(x);
+        self.pot_y.set_resistance(y);
+    }
+
     pub fn input(&mut self, sample: i32) {
         // Voice outputs are 20 bits. Scale up to match three voices in order
         // to facilitate simulation of the MOS8580 "digi boost" hardware hack.
@@ -176,10 +360,51 @@ impl Sid {
         self.sampler.synth.output()
     }
 
+    /// Number of cycles clocked since the SID was created. This is not
+    /// affected by reset.
+    pub fn get_cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Record all subsequent writes into a new log with the chip model and
+    /// sampling parameters of the SID, replacing any recording in progress,
+    /// see WriteLog.
+    ///
+    /// The log does not store other chip parameters or settings, so a SID
+    /// created with a custom profile, or with any setting changed from those
+    /// of Sid::new, e.g. by enable_filter(false), set_dac or
+    /// enable_digi_boost, cannot be recorded.
+    pub fn start_recording(&mut self) -> Result<(), Error> {
+        if self.custom_settings {
+            return Err(Error::UnsupportedRecording);
+        }
+        let mut log = WriteLog::new(
+            self.chip_model,
+            self.sampling_method,
+            self.clock_freq,
+            self.sample_freq,
+        );
+        log.start = self.cycle;
+        log.end = self.cycle;
+        self.recording = Some(log);
+        Ok(())
+    }
+
+    /// Stop recording, returning the log if recording.
+    pub fn stop_recording(&mut self) -> Option<WriteLog> {
+        let cycle = self.cycle;
+        self.recording.take().map(|mut log| {
+            log.end = cycle;
+            log
+        })
+    }
+
     pub fn reset(&mut self) {
         self.sampler.reset();
+        self.pot_x.reset();
+        self.pot_y.reset();
         self.bus_value = 0;
-        self.bus_value_ttl = 0;
+        self.bus_value_ttl = [0; 8];
     }
 
     /// SID clocking with audio sampling.
@@ -199,25 +424,107 @@ impl Sid {
     /// }
     /// ```
     pub fn sample(&mut self, delta: u32, buffer: &mut [i16], interleave: usize) -> (usize, u32) {
-        self.sampler.clock(delta, buffer, interleave)
+        let (samples, next_delta) = self.sampler.clock(delta, buffer, interleave);
+        self.cycle += (delta - next_delta) as u64;
+        // Age bus value.
+        self.age_bus_value(delta - next_delta);
+        // Clock pot measurement.
+        self.pot_x.clock_delta(delta - next_delta);
+        self.pot_y.clock_delta(delta - next_delta);
+        (samples, next_delta)
+    }
+
+    /// The data bus value of the last write is read back from the write-only
+    /// registers. Each bit fades to zero once its charge has leaked away.
+    #[inline]
+    fn age_bus_value(&mut self, delta: u32) {
+        if self.bus_value != 0 {
+            for (bit, ttl) in self.bus_value_ttl.iter_mut().enumerate() {
+                if *ttl > delta {
+                    *ttl -= delta;
+                } else {
+                    *ttl = 0;
+                    self.bus_value &= !(1 << bit);
+                }
+            }
+        }
     }
 
     // -- Device I/O
 
+    /// Read a register. Only the five low bits of the address are decoded,
+    /// so any address in the $D400-$D7FF window may be passed truncated to
+    /// u8. The write-only and unused registers return the data bus value.
     pub fn read(&self, reg: u8) -> u8 {
-        match Reg::from(reg) {
-            Reg::POTX => 0,
-            Reg::POTY => 0,
-            Reg::OSC3 => self.sampler.synth.voices[2].wave.borrow().read_osc(),
-            Reg::ENV3 => self.sampler.synth.voices[2].envelope.read_env(),
+        match Reg::try_from(reg & REG_MASK) {
+            Ok(Reg::POTX) => self.pot_x.read(),
+            Ok(Reg::POTY) => self.pot_y.read(),
+            Ok(Reg::OSC3) => self.sampler.synth.voices[2].wave.borrow().read_osc(),
+            Ok(Reg::ENV3) => self.sampler.synth.voices[2].envelope.read_env(),
             _ => self.bus_value,
         }
     }
 
+    /// Value of a register as last written, or as read for the read-only
+    /// registers. Unlike read, this does not return the bus value for the
+    /// write-only registers.
+    pub fn get_register(&self, reg: Reg) -> u8 {
+        let voice = |i: usize| &self.sampler.synth.voices[i];
+        let filter = &self.sampler.synth.filter;
+        match reg {
+            Reg::FREQLO1 => voice(0).wave.borrow().get_frequency_lo(),
+            Reg::FREQHI1 => voice(0).wave.borrow().get_frequency_hi(),
+            Reg::PWLO1 => voice(0).wave.borrow().get_pulse_width_lo(),
+            Reg::PWHI1 => voice(0).wave.borrow().get_pulse_width_hi(),
+            Reg::CR1 => voice(0).wave.borrow().get_control() | voice(0).envelope.get_control(),
+            Reg::AD1 => voice(0).envelope.get_attack_decay(),
+            Reg::SR1 => voice(0).envelope.get_sustain_release(),
+            Reg::FREQLO2 => voice(1).wave.borrow().get_frequency_lo(),
+            Reg::FREQHI2 => voice(1).wave.borrow().get_frequency_hi(),
+            Reg::PWLO2 => voice(1).wave.borrow().get_pulse_width_lo(),
+            Reg::PWHI2 => voice(1).wave.borrow().get_pulse_width_hi(),
+            Reg::CR2 => voice(1).wave.borrow().get_control() | voice(1).envelope.get_control(),
+            Reg::AD2 => voice(1).envelope.get_attack_decay(),
+            Reg::SR2 => voice(1).envelope.get_sustain_release(),
+            Reg::FREQLO3 => voice(2).wave.borrow().get_frequency_lo(),
+            Reg::FREQHI3 => voice(2).wave.borrow().get_frequency_hi(),
+            Reg::PWLO3 => voice(2).wave.borrow().get_pulse_width_lo(),
+            Reg::PWHI3 => voice(2).wave.borrow().get_pulse_width_hi(),
+            Reg::CR3 => voice(2).wave.borrow().get_control() | voice(2).envelope.get_control(),
+            Reg::AD3 => voice(2).envelope.get_attack_decay(),
+            Reg::SR3 => voice(2).envelope.get_sustain_release(),
+            Reg::FCLO => filter.get_fc_lo(),
+            Reg::FCHI => filter.get_fc_hi(),
+            Reg::RESFILT => filter.get_res_filt(),
+            Reg::MODVOL => filter.get_mode_vol(),
+            Reg::POTX | Reg::POTY | Reg::OSC3 | Reg::ENV3 => self.read(reg.addr()),
+        }
+    }
+
+    /// Write a register. The register is decoded like in read. Writes to the
+    /// read-only and unused registers only set the data bus value.
     pub fn write(&mut self, reg: u8, value: u8) {
+        if let Some(ref mut log) = self.recording {
+            log.writes.push(RegisterWrite {
+                cycle: self.cycle,
+                reg,
+                value,
+            });
+        }
+        // Each bit written as one is retained for a chip specific time.
         self.bus_value = value;
-        self.bus_value_ttl = 0x2000;
-        match Reg::from(reg) {
+        for (bit, ttl) in self.bus_value_ttl.iter_mut().enumerate() {
+            *ttl = if value & (1 << bit) != 0 {
+                self.bus_value_ttl_time[bit]
+            } else {
+                0
+            };
+        }
+        let reg = match Reg::try_from(reg & REG_MASK) {
+            Ok(reg) => reg,
+            Err(_) => return,
+        };
+        match reg {
             Reg::FREQLO1 => {
                 self.sampler.synth.voices[0]
                     .wave
@@ -345,62 +652,103 @@ impl Sid {
         }
     }
 
+    // -- Typed Register I/O
+
+    /// Typed register access to voice 0, 1 or 2, see VoiceControl. Returns
+    /// None for any other voice.
+    pub fn voice(&mut self, voice: usize) -> Option<VoiceControl<'_>> {
+        VoiceControl::new(self, voice)
+    }
+
+    /// Set the 11-bit filter cutoff frequency register value.
+    pub fn set_filter_cutoff(&mut self, fc: u16) {
+        self.write(Reg::FCLO.addr(), fc as u8 & 0x07);
+        self.write(Reg::FCHI.addr(), (fc >> 3) as u8);
+    }
+
+    pub fn get_mode_vol(&self) -> ModeVol {
+        ModeVol::from(self.get_register(Reg::MODVOL))
+    }
+
+    pub fn set_mode_vol(&mut self, mode_vol: ModeVol) {
+        self.write(Reg::MODVOL.addr(), mode_vol.into());
+    }
+
+    pub fn get_res_filt(&self) -> ResFilt {
+        ResFilt::from(self.get_register(Reg::RESFILT))
+    }
+
+    pub fn set_res_filt(&mut self, res_filt: ResFilt) {
+        self.write(Reg::RESFILT.addr(), res_filt.into());
+    }
+
     // -- State
 
     pub fn read_state(&self) -> State {
         let mut state = State {
             sid_register: [0; 32],
             bus_value: 0,
-            bus_value_ttl: 0,
+            bus_value_ttl: [0; 8],
+            cycle: 0,
             ext_in: 0,
+            pot_cycle: [0; 2],
             accumulator: [0; 3],
             shift_register: [0; 3],
+            shift_pipeline: [0; 3],
+            shift_register_reset: [0; 3],
+            floating_output: [0; 3],
+            floating_output_ttl: [0; 3],
+            slew_output: [0; 3],
             envelope_state: [0; 3],
+            next_envelope_state: [0; 3],
+            envelope_state_pipeline: [0; 3],
             envelope_counter: [0; 3],
+            envelope_pipeline: [0; 3],
+            env3: [0; 3],
             exponential_counter: [0; 3],
+            exponential_pipeline: [0; 3],
             exponential_counter_period: [0; 3],
+            new_exponential_counter_period: [0; 3],
             hold_zero: [0; 3],
             rate_counter: [0; 3],
             rate_counter_period: [0; 3],
+            reset_rate_counter: [0; 3],
         };
-        for i in 0..3 {
-            let j = i * 7;
-            let wave = self.sampler.synth.voices[i].wave.borrow();
-            let envelope = &self.sampler.synth.voices[i].envelope;
-            state.sid_register[j] = wave.get_frequency_lo();
-            state.sid_register[j + 1] = wave.get_frequency_hi();
-            state.sid_register[j + 2] = wave.get_pulse_width_lo();
-            state.sid_register[j + 3] = wave.get_pulse_width_hi();
-            state.sid_register[j + 4] = wave.get_control() | envelope.get_control();
-            state.sid_register[j + 5] = envelope.get_attack_decay();
-            state.sid_register[j + 6] = envelope.get_sustain_release();
-        }
-        let filter = &self.sampler.synth.filter;
-        state.sid_register[0x15] = filter.get_fc_lo();
-        state.sid_register[0x16] = filter.get_fc_hi();
-        state.sid_register[0x17] = filter.get_res_filt();
-        state.sid_register[0x18] = filter.get_mode_vol();
-        for i in 0x19..0x1d {
-            state.sid_register[i] = self.read(i as u8);
-        }
-        for i in 0x1d..0x20 {
-            state.sid_register[i] = 0;
+        for i in 0..0x20 {
+            state.sid_register[i] = match Reg::try_from(i as u8) {
+                Ok(reg) => self.get_register(reg),
+                Err(_) => 0,
+            };
         }
         state.bus_value = self.bus_value;
         state.bus_value_ttl = self.bus_value_ttl;
+        state.cycle = self.cycle;
         state.ext_in = self.sampler.synth.ext_in;
+        state.pot_cycle = [self.pot_x.cycle, self.pot_y.cycle];
         for i in 0..3 {
             let wave = self.sampler.synth.voices[i].wave.borrow();
             let envelope = &self.sampler.synth.voices[i].envelope;
             state.accumulator[i] = wave.get_acc();
             state.shift_register[i] = wave.get_shift();
+            state.shift_pipeline[i] = wave.shift_pipeline;
+            state.shift_register_reset[i] = wave.shift_register_reset;
+            state.floating_output[i] = wave.floating_output;
+            state.floating_output_ttl[i] = wave.floating_output_ttl;
+            state.slew_output[i] = wave.slew_output;
             state.envelope_state[i] = envelope.state as u8;
+            state.next_envelope_state[i] = envelope.next_state as u8;
+            state.envelope_state_pipeline[i] = envelope.state_pipeline;
             state.envelope_counter[i] = envelope.envelope_counter;
+            state.envelope_pipeline[i] = envelope.envelope_pipeline;
+            state.env3[i] = envelope.env3;
             state.exponential_counter[i] = envelope.exponential_counter;
+            state.exponential_pipeline[i] = envelope.exponential_pipeline;
             state.exponential_counter_period[i] = envelope.exponential_counter_period;
+            state.new_exponential_counter_period[i] = envelope.new_exponential_counter_period;
             state.hold_zero[i] = if envelope.hold_zero { 1 } else { 0 };
             state.rate_counter[i] = envelope.rate_counter;
             state.rate_counter_period[i] = envelope.rate_counter_period;
+            state.reset_rate_counter[i] = if envelope.reset_rate_counter { 1 } else { 0 };
         }
         state
     }
@@ -411,23 +759,42 @@ impl Sid {
         }
         self.bus_value = state.bus_value;
         self.bus_value_ttl = state.bus_value_ttl;
+        self.cycle = state.cycle;
         self.sampler.synth.ext_in = state.ext_in;
+        self.pot_x.cycle = state.pot_cycle[0];
+        self.pot_x.latched = state.sid_register[0x19];
+        self.pot_y.cycle = state.pot_cycle[1];
+        self.pot_y.latched = state.sid_register[0x1a];
         for i in 0..3 {
+            let mut wave = self.sampler.synth.voices[i].wave.borrow_mut();
             let envelope = &mut self.sampler.synth.voices[i].envelope;
-            self.sampler.synth.voices[i].wave.borrow_mut().acc = state.accumulator[i];
-            self.sampler.synth.voices[i].wave.borrow_mut().shift = state.shift_register[i];
-            envelope.state = match state.envelope_state[i] {
+            wave.acc = state.accumulator[i];
+            wave.shift = state.shift_register[i];
+            wave.shift_pipeline = state.shift_pipeline[i];
+            wave.shift_register_reset = state.shift_register_reset[i];
+            wave.floating_output = state.floating_output[i];
+            wave.floating_output_ttl = state.floating_output_ttl[i];
+            wave.slew_output = state.slew_output[i];
+            let env_state = |value: u8| match value {
                 0 => EnvState::Attack,
                 1 => EnvState::DecaySustain,
                 2 => EnvState::Release,
                 _ => panic!("invalid envelope state"),
             };
+            envelope.state = env_state(state.envelope_state[i]);
+            envelope.next_state = env_state(state.next_envelope_state[i]);
+            envelope.state_pipeline = state.envelope_state_pipeline[i];
             envelope.envelope_counter = state.envelope_counter[i];
+            envelope.envelope_pipeline = state.envelope_pipeline[i];
+            envelope.env3 = state.env3[i];
             envelope.exponential_counter = state.exponential_counter[i];
+            envelope.exponential_pipeline = state.exponential_pipeline[i];
             envelope.exponential_counter_period = state.exponential_counter_period[i];
+            envelope.new_exponential_counter_period = state.new_exponential_counter_period[i];
             envelope.hold_zero = state.hold_zero[i] != 0;
             envelope.rate_counter = state.rate_counter[i];
             envelope.rate_counter_period = state.rate_counter_period[i];
+            envelope.reset_rate_counter = state.reset_rate_counter[i] != 0;
         }
     }
 }
@@ -0,0 +1,525 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+#[cfg(not(feature = "std"))]
+use libm::F64Ext;
+
+use super::builder::Clock;
+use super::error::Error;
+use super::instrument::{note_to_hz, Instrument, NotePlayer};
+use super::registers::{ModeVol, ResFilt};
+use super::sid::Sid;
+
+/// Tempo in microseconds per quarter note until the first tempo event.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+const CC_VOLUME: u8 = 7;
+const CC_RESONANCE: u8 = 71;
+const CC_CUTOFF: u8 = 74;
+
+/// MIDI event, with channel and note numbers starting at 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    /// Pitch bend from -8192 to 8191.
+    PitchBend {
+        channel: u8,
+        value: i16,
+    },
+    /// Microseconds per quarter note.
+    Tempo(u32),
+    EndOfTrack,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrackEvent {
+    /// Ticks since the previous event of the track.
+    pub delta: u32,
+    pub event: Event,
+}
+
+/// Standard MIDI File of format 0 or 1, with the events that are relevant
+/// to playback. Other events, e.g. system exclusive and text, are skipped.
+pub struct Smf {
+    pub format: u16,
+    pub ticks_per_quarter: u16,
+    pub tracks: Vec<Vec<TrackEvent>>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.data.len() - self.pos {
+            return Err(Error::InvalidMidiFile("unexpected end of data"));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?;
+        Ok(bytes
+            .iter()
+            .fold(0, |value, &byte| value << 8 | byte as u32))
+    }
+
+    /// Variable length quantity of at most 4 bytes.
+    fn var_len(&mut self) -> Result<u32, Error> {
+        let mut value = 0u32;
+        for _i in 0..4 {
+            let byte = self.u8()?;
+            value = value << 7 | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::InvalidMidiFile("invalid variable length quantity"))
+    }
+}
+
+impl Smf {
+    pub fn parse(data: &[u8]) -> Result<Smf, Error> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.bytes(4)? != b"MThd" {
+            return Err(Error::InvalidMidiFile("missing header chunk"));
+        }
+        let header_len = reader.u32()? as usize;
+        if header_len < 6 {
+            return Err(Error::InvalidMidiFile("invalid header chunk"));
+        }
+        let format = reader.u16()?;
+        let track_count = reader.u16()?;
+        let division = reader.u16()?;
+        reader.bytes(header_len - 6)?;
+        if format > 1 {
+            return Err(Error::InvalidMidiFile("unsupported format"));
+        }
+        if division & 0x8000 != 0 || division == 0 {
+            return Err(Error::InvalidMidiFile("unsupported time division"));
+        }
+        let mut tracks = Vec::new();
+        while tracks.len() < track_count as usize && !reader.is_empty() {
+            let chunk_type = reader.bytes(4)?;
+            let chunk_len = reader.u32()? as usize;
+            let chunk = reader.bytes(chunk_len)?;
+            // Unknown chunk types are skipped.
+            if chunk_type == b"MTrk" {
+                tracks.push(Smf::parse_track(chunk)?);
+            }
+        }
+        Ok(Smf {
+            format,
+            ticks_per_quarter: division,
+            tracks,
+        })
+    }
+
+    fn parse_track(data: &[u8]) -> Result<Vec<TrackEvent>, Error> {
+        let mut reader = Reader { data, pos: 0 };
+        let mut events = Vec::new();
+        let mut running_status = None;
+        // Delta of skipped events, added to the next event.
+        let mut delta = 0u32;
+        while !reader.is_empty() {
+            delta = delta.saturating_add(reader.var_len()?);
+            let mut status = reader.u8()?;
+            let event = match status {
+                0xf0 | 0xf7 => {
+                    // System exclusive events cancel running status.
+                    running_status = None;
+                    let len = reader.var_len()? as usize;
+                    reader.bytes(len)?;
+                    None
+                }
+                0xff => {
+                    // Meta events cancel running status.
+                    running_status = None;
+                    let meta_type = reader.u8()?;
+                    let len = reader.var_len()? as usize;
+                    let meta = reader.bytes(len)?;
+                    match meta_type {
+                        0x2f => Some(Event::EndOfTrack),
+                        0x51 if len == 3 => Some(Event::Tempo(
+                            (meta[0] as u32) << 16 | (meta[1] as u32) << 8 | meta[2] as u32,
+                        )),
+                        _ => None,
+                    }
+                }
+                _ => {
+                    let first = if status & 0x80 == 0 {
+                        // Running status, the status byte is the first data
+                        // byte.
+                        let data = status;
+                        status = running_status
+                            .ok_or(Error::InvalidMidiFile("data byte without status"))?;
+                        data
+                    } else {
+                        running_status = Some(status);
+                        reader.u8()?
+                    };
+                    let channel = status & 0x0f;
+                    match status & 0xf0 {
+                        0x80 => Some(Event::NoteOff {
+                            channel,
+                            note: first & 0x7f,
+                            velocity: reader.u8()? & 0x7f,
+                        }),
+                        0x90 => Some(Event::NoteOn {
+                            channel,
+                            note: first & 0x7f,
+                            velocity: reader.u8()? & 0x7f,
+                        }),
+                        0xb0 => Some(Event::ControlChange {
+                            channel,
+                            controller: first & 0x7f,
+                            value: reader.u8()? & 0x7f,
+                        }),
+                        0xc0 => Some(Event::ProgramChange {
+                            channel,
+                            program: first & 0x7f,
+                        }),
+                        0xe0 => {
+                            let value = ((reader.u8()? & 0x7f) as i16) << 7 | (first & 0x7f) as i16;
+                            Some(Event::PitchBend {
+                                channel,
+                                value: value - 0x2000,
+                            })
+                        }
+                        // Polyphonic and channel pressure.
+                        0xa0 => {
+                            reader.u8()?;
+                            None
+                        }
+                        0xd0 => None,
+                        _ => return Err(Error::InvalidMidiFile("invalid status byte")),
+                    }
+                }
+            };
+            if let Some(event) = event {
+                events.push(TrackEvent { delta, event });
+                delta = 0;
+                if event == Event::EndOfTrack {
+                    break;
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// All events of all tracks in playback order, with the time in cycles
+    /// of the given clock. Events at the same tick are ordered by track.
+    pub fn timeline(&self, clock: Clock) -> Vec<(u64, Event)> {
+        let mut events = Vec::new();
+        for (track, track_events) in self.tracks.iter().enumerate() {
+            let mut tick = 0u64;
+            for track_event in track_events {
+                tick += track_event.delta as u64;
+                events.push((tick, track, track_event.event));
+            }
+        }
+        events.sort_by_key(|&(tick, track, _)| (tick, track));
+        // Convert ticks to cycles with the tempo in effect, without
+        // accumulating rounding errors.
+        let clock_freq = clock.frequency() as u128;
+        let ticks_per_quarter = self.ticks_per_quarter as u128;
+        let mut tempo = DEFAULT_TEMPO as u128;
+        let mut tempo_tick = 0u64;
+        let mut tempo_cycle = 0u64;
+        let mut timeline = Vec::with_capacity(events.len());
+        for (tick, _track, event) in events {
+            let ticks = (tick - tempo_tick) as u128;
+            let cycle =
+                tempo_cycle + (ticks * tempo * clock_freq / (ticks_per_quarter * 1_000_000)) as u64;
+            if let Event::Tempo(new_tempo) = event {
+                tempo = new_tempo as u128;
+                tempo_tick = tick;
+                tempo_cycle = cycle;
+            }
+            timeline.push((cycle, event));
+        }
+        timeline
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Channel {
+    // Checked by set_channel_voice, so the player accepts it.
+    voice: Option<usize>,
+    instrument: Instrument,
+    filter: bool,
+    pitch_bend: i16,
+}
+
+/// Offline renderer of Standard MIDI Files.
+///
+/// Each MIDI channel is mapped to a fixed voice, which plays one note at a
+/// time with the instrument of the channel. By default, channels 0 to 2 are
+/// mapped to voices 0 to 2. Notes are played with a NotePlayer, which
+/// honours the hard restart setting of the instruments. A note starting as
+/// the previous note of the channel ends is played legato without a hard
+/// restart. Velocity is not used.
+///
+/// Pitch bend is applied to the frequency of the voice, and the filter
+/// cutoff (CC74), resonance (CC71) and volume (CC7) controllers are mapped to
+/// the filter registers, which are shared by all channels.
+///
+/// ```
+/// use resid::smf::{Smf, SmfRenderer};
+/// use resid::{ChipModel, Clock, Sid};
+///
+/// # fn render(data: &[u8]) -> Result<Vec<i16>, resid::Error> {
+/// let smf = Smf::parse(data)?;
+/// let mut renderer = SmfRenderer::new(Sid::new(ChipModel::Mos6581), Clock::Pal);
+/// Ok(renderer.render(&smf))
+/// # }
+/// ```
+pub struct SmfRenderer {
+    player: NotePlayer,
+    clock: Clock,
+    channels: [Channel; 16],
+    notes: [Option<u8>; 3],
+    pitch_bend_range: f64,
+    tail_cycles: u32,
+}
+
+impl SmfRenderer {
+    pub fn new(sid: Sid, clock: Clock) -> Self {
+        let mut channels = [Channel {
+            voice: None,
+            instrument: Instrument::default(),
+            filter: false,
+            pitch_bend: 0,
+        }; 16];
+        for (voice, channel) in channels.iter_mut().take(3).enumerate() {
+            channel.voice = Some(voice);
+        }
+        SmfRenderer {
+            player: NotePlayer::new(sid, clock),
+            clock,
+            channels,
+            notes: [None; 3],
+            pitch_bend_range: 2.0,
+            tail_cycles: clock.frequency(),
+        }
+    }
+
+    pub fn get_sid(&self) -> &Sid {
+        self.player.get_sid()
+    }
+
+    pub fn into_sid(self) -> Sid {
+        self.player.into_sid()
+    }
+
+    /// Map a MIDI channel to a voice, or mute the channel. Voice numbers
+    /// above 2 are rejected.
+    pub fn set_channel_voice(&mut self, channel: u8, voice: Option<usize>) -> Result<(), Error> {
+        match voice {
+            Some(voice) if voice >= 3 => Err(Error::InvalidVoice(voice)),
+            _ => {
+                self.channels[channel as usize & 0x0f].voice = voice;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_channel_instrument(&mut self, channel: u8, instrument: Instrument) {
+        self.channels[channel as usize & 0x0f].instrument = instrument;
+    }
+
+    /// Route the voice of the channel through the filter.
+    pub fn set_channel_filter(&mut self, channel: u8, enabled: bool) {
+        self.channels[channel as usize & 0x0f].filter = enabled;
+    }
+
+    /// Pitch bend range in semitones, 2 by default.
+    pub fn set_pitch_bend_range(&mut self, semitones: f64) {
+        self.pitch_bend_range = semitones;
+    }
+
+    /// Number of cycles rendered after the last event to let notes release,
+    /// one second by default.
+    pub fn set_tail_cycles(&mut self, cycles: u32) {
+        self.tail_cycles = cycles;
+    }
+
+    /// Render the file with the sampling parameters of the SID.
+    pub fn render(&mut self, smf: &Smf) -> Vec<i16> {
+        let mut output = Vec::new();
+        let mut res_filt = ResFilt::default();
+        for channel in self.channels.iter() {
+            match (channel.voice, channel.filter) {
+                (Some(0), true) => res_filt.filt1 = true,
+                (Some(1), true) => res_filt.filt2 = true,
+                (Some(2), true) => res_filt.filt3 = true,
+                _ => {}
+            }
+        }
+        let sid = self.player.get_sid_mut();
+        sid.set_res_filt(res_filt);
+        sid.set_mode_vol(ModeVol {
+            volume: 0x0f,
+            low_pass: true,
+            ..ModeVol::default()
+        });
+        sid.set_filter_cutoff(0x7ff);
+        let mut cycle = 0u64;
+        let timeline = smf.timeline(self.clock);
+        for (i, &(event_cycle, event)) in timeline.iter().enumerate() {
+            self.advance(event_cycle - cycle, &mut output);
+            cycle = event_cycle;
+            // A note released as the next note of the channel starts is
+            // played legato, as the SID cannot clear and set the gate in the
+            // same cycle.
+            let retriggered = timeline[i + 1..]
+                .iter()
+                .take_while(|&&(next_cycle, _)| next_cycle == event_cycle)
+                .any(|&(_, next)| match (event, next) {
+                    (
+                        Event::NoteOn { channel, .. } | Event::NoteOff { channel, .. },
+                        Event::NoteOn {
+                            channel: next_channel,
+                            velocity,
+                            ..
+                        },
+                    ) => channel == next_channel && velocity > 0,
+                    _ => false,
+                });
+            self.handle(event, retriggered);
+        }
+        self.advance(self.tail_cycles as u64, &mut output);
+        output
+    }
+
+    fn advance(&mut self, mut cycles: u64, output: &mut Vec<i16>) {
+        let mut buffer = [0i16; 1024];
+        while cycles > 0 {
+            let mut delta = cycles.min(0x10000) as u32;
+            cycles -= delta as u64;
+            while delta > 0 {
+                let (samples, next_delta) = self.player.sample(delta, &mut buffer, 1);
+                output.extend_from_slice(&buffer[..samples]);
+                delta = next_delta;
+            }
+        }
+    }
+
+    fn handle(&mut self, event: Event, retriggered: bool) {
+        match event {
+            Event::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity > 0 => self.note_on(channel, note),
+            Event::NoteOn { channel, note, .. } | Event::NoteOff { channel, note, .. } => {
+                self.note_off(channel, note, retriggered)
+            }
+            Event::ControlChange {
+                channel,
+                controller,
+                value,
+            } => self.control_change(channel, controller, value),
+            Event::PitchBend { channel, value } => {
+                self.channels[channel as usize].pitch_bend = value;
+                if let Some(voice) = self.channels[channel as usize].voice {
+                    if let Some(note) = self.notes[voice] {
+                        let frequency = self.frequency(channel, note);
+                        let _ = self.player.set_frequency_hz(voice, frequency);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn frequency(&self, channel: u8, note: u8) -> f64 {
+        let bend = self.channels[channel as usize].pitch_bend as f64 / 8192.0;
+        note_to_hz(note) * 2.0f64.powf(bend * self.pitch_bend_range / 12.0)
+    }
+
+    fn note_on(&mut self, channel: u8, note: u8) {
+        let Channel {
+            voice, instrument, ..
+        } = self.channels[channel as usize];
+        if let Some(voice) = voice {
+            let frequency = self.frequency(channel, note);
+            let _ = self.player.note_on_voice(voice, frequency, &instrument);
+            self.notes[voice] = Some(note);
+        }
+    }
+
+    fn note_off(&mut self, channel: u8, note: u8, retriggered: bool) {
+        if let Some(voice) = self.channels[channel as usize].voice {
+            if self.notes[voice] == Some(note) {
+                if !retriggered {
+                    let _ = self.player.note_off_voice(voice);
+                }
+                self.notes[voice] = None;
+            }
+        }
+    }
+
+    fn control_change(&mut self, _channel: u8, controller: u8, value: u8) {
+        let sid = self.player.get_sid_mut();
+        match controller {
+            CC_CUTOFF => {
+                // Scale 7 bits to the 11-bit cutoff register.
+                let fc = (value as u16) << 4 | (value as u16) >> 3;
+                sid.set_filter_cutoff(fc);
+            }
+            CC_RESONANCE => {
+                let mut res_filt = sid.get_res_filt();
+                res_filt.resonance = value >> 3;
+                sid.set_res_filt(res_filt);
+            }
+            CC_VOLUME => {
+                let mut mode_vol = sid.get_mode_vol();
+                mode_vol.volume = value >> 3;
+                sid.set_mode_vol(mode_vol);
+            }
+            _ => {}
+        }
+    }
+}
@@ -5,6 +5,7 @@
 
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
 
+use super::chip_profile::ChipProfile;
 use super::external_filter::ExternalFilter;
 use super::filter::Filter;
 use super::voice::Voice;
@@ -19,21 +20,37 @@ pub struct Synth {
     pub filter: Filter,
     pub voices: [Voice; 3],
     pub ext_in: i32,
+    pub ext_in_dc: i32,
 }
 
 impl Synth {
     pub fn new(chip_model: ChipModel) -> Self {
-        let mut voice1 = Voice::new(chip_model);
-        let mut voice2 = Voice::new(chip_model);
-        let mut voice3 = Voice::new(chip_model);
+        Synth::with_profile(&ChipProfile::new(chip_model))
+    }
+
+    pub fn with_profile(profile: &ChipProfile) -> Self {
+        let mut voice1 = Voice::with_profile(profile);
+        let mut voice2 = Voice::with_profile(profile);
+        let mut voice3 = Voice::with_profile(profile);
         voice1.set_sync_source(&mut voice3);
         voice2.set_sync_source(&mut voice1);
         voice3.set_sync_source(&mut voice2);
         Synth {
-            ext_filter: ExternalFilter::new(chip_model),
-            filter: Filter::new(chip_model),
+            ext_filter: ExternalFilter::with_profile(profile),
+            filter: Filter::with_profile(profile),
             voices: [voice1, voice2, voice3],
             ext_in: 0,
+            ext_in_dc: 0,
+        }
+    }
+
+    /// Switch the chip parameters in place, retaining the registers and the
+    /// runtime state.
+    pub fn set_profile(&mut self, profile: &ChipProfile) {
+        self.ext_filter.set_profile(profile);
+        self.filter.set_profile(profile);
+        for voice in self.voices.iter_mut() {
+            voice.set_profile(profile);
         }
     }
 
@@ -55,7 +72,7 @@ impl Synth {
             self.voices[0].output(),
             self.voices[1].output(),
             self.voices[2].output(),
-            self.ext_in,
+            self.ext_in + self.ext_in_dc,
         );
         // Clock external filter.
         self.ext_filter.clock(self.filter.output());
@@ -111,7 +128,7 @@ impl Synth {
             self.voices[0].output(),
             self.voices[1].output(),
             self.voices[2].output(),
-            self.ext_in,
+            self.ext_in + self.ext_in_dc,
         );
         // Clock external filter.
         self.ext_filter.clock_delta(delta, self.filter.output());
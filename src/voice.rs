@@ -5,6 +5,8 @@
 
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 #[cfg(not(feature = "std"))]
 use alloc::rc::Rc;
 #[cfg(feature = "std")]
@@ -12,6 +14,8 @@ use std::rc::Rc;
 
 use core::cell::RefCell;
 
+use super::chip_profile::ChipProfile;
+use super::dac::{build_dac_table, DacConfig};
 use super::envelope::EnvelopeGenerator;
 use super::wave::WaveformGenerator;
 use super::ChipModel;
@@ -20,7 +24,8 @@ use super::ChipModel;
 /// level should ideally have been 0x800. In the measured chip, the
 /// waveform output "zero" level was found to be 0x380 (i.e. $d41b
 /// = 0x38) at 5.94V.
-const WAVE_ZERO: i32 = 0x0380;
+pub(crate) const WAVE_ZERO_6581: i32 = 0x0380;
+pub(crate) const WAVE_ZERO_8580: i32 = 0x0800;
 
 /// The envelope multiplying D/A converter introduces another DC
 /// offset. This is isolated by the following measurements:
@@ -40,12 +45,14 @@ const WAVE_ZERO: i32 = 0x0380;
 ///     y < 0: (5.70V - 5.44V) - 0.50V = -0.24V
 /// The scaling of the voice amplitude is not symmetric about y = 0;
 /// this follows from the DC level in the waveform output.
-const VOICE_DC: i32 = 0x800 * 0xff;
+pub(crate) const VOICE_DC_6581: i32 = 0x800 * 0xff;
 
 pub struct Voice {
     // Configuration
     wave_zero: i32,
     voice_dc: i32,
+    wave_dac: Option<Vec<u16>>,
+    envelope_dac: Option<Vec<u16>>,
     // Generators
     pub envelope: EnvelopeGenerator,
     pub wave: Rc<RefCell<WaveformGenerator>>,
@@ -53,32 +60,67 @@ pub struct Voice {
 
 impl Voice {
     pub fn new(chip_model: ChipModel) -> Self {
-        match chip_model {
-            ChipModel::Mos6581 => Voice {
-                wave_zero: WAVE_ZERO,
-                voice_dc: VOICE_DC,
-                envelope: EnvelopeGenerator::default(),
-                wave: Rc::new(RefCell::new(WaveformGenerator::new(chip_model))),
-            },
-            ChipModel::Mos8580 => Voice {
-                // No DC offsets in the MOS8580.
-                wave_zero: 0x800,
-                voice_dc: 0,
-                envelope: EnvelopeGenerator::default(),
-                wave: Rc::new(RefCell::new(WaveformGenerator::new(chip_model))),
-            },
-        }
+        Voice::with_profile(&ChipProfile::new(chip_model))
+    }
+
+    pub fn with_profile(profile: &ChipProfile) -> Self {
+        let mut voice = Voice {
+            wave_zero: profile.wave_zero,
+            voice_dc: profile.voice_dc,
+            wave_dac: None,
+            envelope_dac: None,
+            envelope: EnvelopeGenerator::default(),
+            wave: Rc::new(RefCell::new(WaveformGenerator::with_profile(profile))),
+        };
+        voice.envelope.set_delays(profile.envelope_delays);
+        voice.set_dac(profile.dac);
+        voice
+    }
+
+    pub fn get_voice_dc(&self) -> i32 {
+        self.voice_dc
     }
 
     pub fn get_wave(&self) -> Rc<RefCell<WaveformGenerator>> {
         self.wave.clone()
     }
 
+    pub fn get_wave_zero(&self) -> i32 {
+        self.wave_zero
+    }
+
+    /// Model the waveform and envelope DACs with the given R-2R ladder
+    /// configuration, or as ideal linear DACs if none is given.
+    pub fn set_dac(&mut self, config: Option<DacConfig>) {
+        self.wave_dac = config.map(|config| build_dac_table(12, config, 1.0));
+        self.envelope_dac = config.map(|config| build_dac_table(8, config, 1.0));
+    }
+
     pub fn set_control(&mut self, value: u8) {
         self.envelope.set_control(value);
         self.wave.borrow_mut().set_control(value);
     }
 
+    /// Set the DC offset introduced by the envelope multiplying DAC.
+    pub fn set_voice_dc(&mut self, voice_dc: i32) {
+        self.voice_dc = voice_dc;
+    }
+
+    /// Set the waveform output "zero" level.
+    pub fn set_wave_zero(&mut self, wave_zero: i32) {
+        self.wave_zero = wave_zero;
+    }
+
+    /// Switch the chip parameters in place, retaining the registers and the
+    /// runtime state.
+    pub fn set_profile(&mut self, profile: &ChipProfile) {
+        self.wave_zero = profile.wave_zero;
+        self.voice_dc = profile.voice_dc;
+        self.envelope.set_delays(profile.envelope_delays);
+        self.set_dac(profile.dac);
+        self.wave.borrow_mut().set_profile(profile);
+    }
+
     pub fn set_sync_source(&mut self, source: &mut Voice) {
         self.wave.borrow_mut().set_sync_source(source.get_wave());
         let source_wave = source.get_wave();
@@ -89,9 +131,18 @@ impl Voice {
     /// Range [-2048*255, 2047*255].
     #[inline]
     pub fn output(&self) -> i32 {
+        let wave = self.wave.borrow().output();
+        let wave = match self.wave_dac {
+            Some(ref dac) => dac[wave as usize],
+            None => wave,
+        };
+        let envelope = self.envelope.output();
+        let envelope = match self.envelope_dac {
+            Some(ref dac) => dac[envelope as usize],
+            None => envelope as u16,
+        };
         // Multiply oscillator output with envelope output.
-        (self.wave.borrow().output() as i32 - self.wave_zero) * self.envelope.output() as i32
-            + self.voice_dc
+        (wave as i32 - self.wave_zero) * envelope as i32 + self.voice_dc
     }
 
     pub fn reset(&mut self) {
@@ -13,7 +13,8 @@ use std::rc::Rc;
 use bit_field::BitField;
 use core::cell::RefCell;
 
-use super::data;
+use super::chip_profile::ChipProfile;
+use super::combined_waveform::CombinedWaveforms;
 use super::ChipModel;
 
 const ACC_MASK: u32 = 0x00ff_ffff;
@@ -22,6 +23,29 @@ const ACC_MSB_MASK: u32 = 0x0080_0000;
 const SHIFT_MASK: u32 = 0x007f_ffff;
 const OUTPUT_MASK: u16 = 0x0fff;
 
+/// Number of cycles the test bit must be held for the shift register to be
+/// reset to all ones.
+pub(crate) const SHIFT_REGISTER_RESET_6581: u32 = 0x8000;
+pub(crate) const SHIFT_REGISTER_RESET_8580: u32 = 0x0095_0000;
+
+/// Number of cycles the noise shift register is clocked after accumulator
+/// bit 19 is set high, as in reSID 1.0.
+pub(crate) const SHIFT_DELAY: u8 = 2;
+
+/// Number of cycles the waveform DAC input holds its value after the waveform
+/// has been deselected, and the number of cycles between each subsequent bit
+/// fade.
+pub(crate) const FLOATING_OUTPUT_TTL_6581: u32 = 54_000;
+pub(crate) const FLOATING_OUTPUT_FADE_6581: u32 = 1_400;
+pub(crate) const FLOATING_OUTPUT_TTL_8580: u32 = 800_000;
+pub(crate) const FLOATING_OUTPUT_FADE_8580: u32 = 50_000;
+
+/// Maximum change of the waveform output per cycle when edge slew is enabled.
+/// A full scale pulse edge ramps over approximately 4 cycles on the MOS6581
+/// and 2 cycles on the MOS8580.
+pub(crate) const SLEW_RATE_6581: u16 = 0x0400;
+pub(crate) const SLEW_RATE_8580: u16 = 0x0800;
+
 /// A 24 bit accumulator is the basis for waveform generation. FREQ is added to
 /// the lower 16 bits of the accumulator each cycle.
 /// The accumulator is set to zero when TEST is set, and starts counting
@@ -35,6 +59,12 @@ pub struct WaveformGenerator {
     // Configuration
     frequency: u16,
     pulse_width: u16,
+    shift_delay: u8,
+    shift_register_reset_time: u32,
+    floating_output_ttl_time: u32,
+    floating_output_fade_time: u32,
+    slew_rate: u16,
+    slew_enabled: bool,
     // Control
     waveform: u8,
     ring: bool,
@@ -43,46 +73,46 @@ pub struct WaveformGenerator {
     // Runtime State
     pub acc: u32,
     pub shift: u32,
+    pub shift_pipeline: u8,
+    pub shift_register_reset: u32,
+    pub floating_output: u16,
+    pub floating_output_ttl: u32,
+    pub slew_output: u16,
     msb_rising: bool,
     // Static Data
-    wave_ps: &'static [u8; 4096],
-    wave_pst: &'static [u8; 4096],
-    wave_pt: &'static [u8; 4096],
-    wave_st: &'static [u8; 4096],
+    combined_waveforms: CombinedWaveforms,
 }
 
 impl WaveformGenerator {
     pub fn new(chip_model: ChipModel) -> Self {
-        let (wave_ps, wave_pst, wave_pt, wave_st) = match chip_model {
-            ChipModel::Mos6581 => (
-                &data::WAVE6581_PS,
-                &data::WAVE6581_PST,
-                &data::WAVE6581_PT,
-                &data::WAVE6581_ST,
-            ),
-            ChipModel::Mos8580 => (
-                &data::WAVE8580_PS,
-                &data::WAVE8580_PST,
-                &data::WAVE8580_PT,
-                &data::WAVE8580_ST,
-            ),
-        };
+        WaveformGenerator::with_profile(&ChipProfile::new(chip_model))
+    }
+
+    pub fn with_profile(profile: &ChipProfile) -> Self {
         let mut waveform = WaveformGenerator {
             sync_source: None,
             sync_dest: None,
             frequency: 0,
             pulse_width: 0,
+            shift_delay: profile.shift_delay,
+            shift_register_reset_time: profile.shift_register_reset,
+            floating_output_ttl_time: profile.floating_output_ttl,
+            floating_output_fade_time: profile.floating_output_fade,
+            slew_rate: profile.slew_rate,
+            slew_enabled: false,
             waveform: 0,
             ring: false,
             sync: false,
             test: false,
             acc: 0,
             shift: 0,
+            shift_pipeline: 0,
+            shift_register_reset: 0,
+            floating_output: 0,
+            floating_output_ttl: 0,
+            slew_output: 0,
             msb_rising: false,
-            wave_ps,
-            wave_pst,
-            wave_pt,
-            wave_st,
+            combined_waveforms: profile.combined_waveforms.clone(),
         };
         waveform.reset();
         waveform
@@ -92,6 +122,10 @@ impl WaveformGenerator {
         self.acc
     }
 
+    pub fn get_combined_waveforms(&self) -> &CombinedWaveforms {
+        &self.combined_waveforms
+    }
+
     pub fn get_control(&self) -> u8 {
         let mut value = 0u8;
         value.set_bit(1, self.sync);
@@ -124,6 +158,10 @@ impl WaveformGenerator {
         self.shift
     }
 
+    pub fn get_slew_rate(&self) -> u16 {
+        self.slew_rate
+    }
+
     pub fn get_sync_dest_sync(&self) -> bool {
         if let Some(ref sync_dest) = self.sync_dest {
             sync_dest.borrow().sync
@@ -144,34 +182,54 @@ impl WaveformGenerator {
         self.msb_rising
     }
 
+    pub fn is_slew_enabled(&self) -> bool {
+        self.slew_enabled
+    }
+
     pub fn set_acc(&mut self, value: u32) {
         self.acc = value;
     }
 
+    /// Replace the combined waveform lookup tables, e.g. with tables generated
+    /// from a CombinedWaveformModel.
+    pub fn set_combined_waveforms(&mut self, combined_waveforms: CombinedWaveforms) {
+        self.combined_waveforms = combined_waveforms;
+    }
+
     pub fn set_control(&mut self, value: u8) {
-        self.waveform = (value >> 4) & 0x0f;
+        let waveform = (value >> 4) & 0x0f;
+        if self.waveform != 0 && waveform == 0 {
+            // Waveform deselected.
+            // The waveform DAC input is left floating, holding the last output
+            // until it starts to fade, see clock().
+            self.floating_output = self.output_waveform();
+            self.floating_output_ttl = self.floating_output_ttl_time;
+        } else if waveform != 0 {
+            self.floating_output_ttl = 0;
+        }
+        self.waveform = waveform;
         self.sync = value.get_bit(1);
         self.ring = value.get_bit(2);
         let test = value.get_bit(3);
         if test {
             // Test bit set.
-            // The accumulator and the shift register are both cleared.
-            // NB! The shift register is not really cleared immediately. It seems like
-            // the individual bits in the shift register start to fade down towards
-            // zero when test is set. All bits reach zero within approximately
-            // $2000 - $4000 cycles.
-            // This is not modeled. There should fortunately be little audible output
-            // from this peculiar behavior.
+            // The accumulator is cleared and the shift pipeline is flushed.
+            // The shift register is retained, and is only reset to all ones
+            // once test has been held for a chip specific number of cycles,
+            // see clock().
             self.acc = 0;
-            self.shift = 0;
+            self.shift_pipeline = 0;
+            if !self.test {
+                self.shift_register_reset = self.shift_register_reset_time;
+            }
         } else if self.test {
             // Test bit cleared.
-            // The accumulator starts counting, and the shift register is reset to
-            // the value 0x7ffff8.
-            // NB! The shift register will not actually be set to this exact value if the
-            // shift register bits have not had time to fade to zero.
-            // This is not modeled.
-            self.shift = 0x007f_fff8;
+            // The accumulator starts counting, and the shift register is clocked
+            // once with the test bit still driving the feedback:
+            // bit0 = (bit22 | test) ^ bit17 = ~bit17
+            let bit0 = (!self.shift >> 17) & 0x01;
+            self.shift = ((self.shift << 1) & SHIFT_MASK) | bit0;
+            self.shift_register_reset = 0;
         }
         self.test = test;
     }
@@ -196,6 +254,30 @@ impl WaveformGenerator {
         self.pulse_width = result;
     }
 
+    /// Limit the rate of change of the waveform output, see clock_slew().
+    pub fn set_slew_enabled(&mut self, enabled: bool) {
+        if enabled && !self.slew_enabled {
+            self.slew_output = self.output_waveform();
+        }
+        self.slew_enabled = enabled;
+    }
+
+    /// Set the maximum change of the waveform output per cycle.
+    pub fn set_slew_rate(&mut self, rate: u16) {
+        self.slew_rate = rate.max(1);
+    }
+
+    /// Switch the chip parameters in place, retaining the registers and the
+    /// runtime state.
+    pub fn set_profile(&mut self, profile: &ChipProfile) {
+        self.shift_delay = profile.shift_delay;
+        self.shift_register_reset_time = profile.shift_register_reset;
+        self.floating_output_ttl_time = profile.floating_output_ttl;
+        self.floating_output_fade_time = profile.floating_output_fade;
+        self.slew_rate = profile.slew_rate;
+        self.combined_waveforms = profile.combined_waveforms.clone();
+    }
+
     pub fn set_sync_dest(&mut self, dest: Rc<RefCell<WaveformGenerator>>) {
         self.sync_dest = Some(dest);
     }
@@ -213,17 +295,52 @@ impl WaveformGenerator {
             self.acc = (self.acc + self.frequency as u32) & ACC_MASK;
             // Check whether the MSB is set high. This is used for synchronization.
             self.msb_rising = (acc_prev & ACC_MSB_MASK) == 0 && (self.acc & ACC_MSB_MASK) != 0;
+            // Shift noise register once for each time accumulator bit 19 is set high.
+            // The shift is delayed 2 cycles.
             if (acc_prev & ACC_BIT19_MASK) == 0 && (self.acc & ACC_BIT19_MASK) != 0 {
-                // Shift noise register once for each time accumulator bit 19 is set high.
-                let bit0 = ((self.shift >> 22) ^ (self.shift >> 17)) & 0x01;
-                self.shift = ((self.shift << 1) & SHIFT_MASK) | bit0;
+                // Pipeline: Detect rising bit, shift phase 1, shift phase 2.
+                if self.shift_delay != 0 {
+                    self.shift_pipeline = self.shift_delay;
+                } else {
+                    self.clock_shift_register();
+                }
+            } else if self.shift_pipeline != 0 {
+                self.shift_pipeline -= 1;
+                if self.shift_pipeline == 0 {
+                    self.clock_shift_register();
+                }
             }
+        } else if self.shift_register_reset != 0 {
+            // Count down time to fully reset shift register.
+            self.shift_register_reset -= 1;
+            if self.shift_register_reset == 0 {
+                self.shift = SHIFT_MASK;
+            }
+        }
+        if self.floating_output_ttl != 0 {
+            // Count down time to fade floating DAC input.
+            self.floating_output_ttl -= 1;
+            if self.floating_output_ttl == 0 {
+                self.fade_floating_output();
+            }
+        }
+        self.write_shift_register();
+        if self.slew_enabled {
+            self.clock_slew(1);
         }
     }
 
     #[inline]
     pub fn clock_delta(&mut self, delta: u32) {
         if !self.test {
+            if self.shift_pipeline != 0 {
+                if delta >= self.shift_pipeline as u32 {
+                    self.shift_pipeline = 0;
+                    self.clock_shift_register();
+                } else {
+                    self.shift_pipeline -= delta as u8;
+                }
+            }
             let acc_prev = self.acc;
             // Calculate new accumulator value;
             let mut delta_acc = delta * self.frequency as u32;
@@ -232,6 +349,20 @@ impl WaveformGenerator {
             self.msb_rising = (acc_prev & ACC_MSB_MASK) == 0 && (self.acc & ACC_MSB_MASK) != 0;
             // Shift noise register once for each time accumulator bit 19 is set high.
             // Bit 19 is set high each time 2^20 (0x100000) is added to the accumulator.
+            // The shift is delayed, so a shift for bit 19 set high on one of the
+            // last cycles is left in the pipeline. The accumulator is rewound to
+            // the cycle before to leave it out below.
+            let acc_next = self.acc;
+            if self.frequency != 0 {
+                let cycles = ((self.acc.wrapping_sub(ACC_BIT19_MASK) & 0x000f_ffff)
+                    / self.frequency as u32)
+                    + 1;
+                if cycles <= self.shift_delay as u32 && cycles <= delta {
+                    self.shift_pipeline = self.shift_delay + 1 - cycles as u8;
+                    self.acc = self.acc.wrapping_sub(cycles * self.frequency as u32) & ACC_MASK;
+                    delta_acc -= cycles * self.frequency as u32;
+                }
+            }
             let mut shift_period = 0x0010_0000;
             while delta_acc != 0 {
                 if delta_acc < shift_period {
@@ -253,18 +384,57 @@ impl WaveformGenerator {
                     }
                 }
                 // Shift the noise/random register.
-                let bit0 = ((self.shift >> 22) ^ (self.shift >> 17)) & 0x01;
-                self.shift = (self.shift << 1) & SHIFT_MASK | bit0;
+                self.write_shift_register();
+                self.clock_shift_register();
                 delta_acc -= shift_period;
             }
+            self.acc = acc_next;
+        } else if self.shift_register_reset != 0 {
+            // Count down time to fully reset shift register.
+            if self.shift_register_reset > delta {
+                self.shift_register_reset -= delta;
+            } else {
+                self.shift_register_reset = 0;
+                self.shift = SHIFT_MASK;
+            }
+        }
+        let mut delta_fade = delta;
+        while self.floating_output_ttl != 0 && delta_fade != 0 {
+            // Count down time to fade floating DAC input.
+            if self.floating_output_ttl > delta_fade {
+                self.floating_output_ttl -= delta_fade;
+                delta_fade = 0;
+            } else {
+                delta_fade -= self.floating_output_ttl;
+                self.floating_output_ttl = 0;
+                self.fade_floating_output();
+            }
+        }
+        self.write_shift_register();
+        if self.slew_enabled {
+            self.clock_slew(delta);
         }
     }
 
     /// 12-bit waveform output
     #[inline]
     pub fn output(&self) -> u16 {
+        if self.slew_enabled {
+            self.slew_output
+        } else {
+            self.output_waveform()
+        }
+    }
+
+    pub fn read_osc(&self) -> u8 {
+        (self.output_waveform() >> 4) as u8
+    }
+
+    /// 12-bit digital waveform output, i.e. the waveform DAC input.
+    #[inline]
+    fn output_waveform(&self) -> u16 {
         match self.waveform {
-            0x0 => 0,
+            0x0 => self.floating_output,
             0x1 => self.output_t(),
             0x2 => self.output_s(),
             0x3 => self.output_st(),
@@ -273,21 +443,17 @@ impl WaveformGenerator {
             0x6 => self.output_ps(),
             0x7 => self.output_pst(),
             0x8 => self.output_n(),
-            0x9 => 0,
-            0xa => 0,
-            0xb => 0,
-            0xc => 0,
-            0xd => 0,
-            0xe => 0,
-            0xf => 0,
+            0x9 => self.output_t() & self.output_n(),
+            0xa => self.output_s() & self.output_n(),
+            0xb => self.output_st() & self.output_n(),
+            0xc => self.output_p() & self.output_n(),
+            0xd => self.output_pt() & self.output_n(),
+            0xe => self.output_ps() & self.output_n(),
+            0xf => self.output_pst() & self.output_n(),
             _ => panic!("invalid waveform {}", self.waveform),
         }
     }
 
-    pub fn read_osc(&self) -> u8 {
-        (self.output() >> 4) as u8
-    }
-
     pub fn reset(&mut self) {
         self.frequency = 0;
         self.pulse_width = 0;
@@ -297,6 +463,11 @@ impl WaveformGenerator {
         self.test = false;
         self.acc = 0;
         self.shift = 0x007f_fff8;
+        self.shift_pipeline = 0;
+        self.shift_register_reset = 0;
+        self.floating_output = 0;
+        self.floating_output_ttl = 0;
+        self.slew_output = 0;
         self.msb_rising = false;
     }
 
@@ -331,13 +502,83 @@ impl WaveformGenerator {
         }
     }
 
+    /// Floating DAC input:
+    /// When the waveform is deselected, the waveform DAC input keeps its last
+    /// value for a while, since there is nothing to discharge it. The bits then
+    /// fade to zero, at each step clearing every bit whose lower neighbor is
+    /// cleared. The held value is also read back in OSC3.
+    fn fade_floating_output(&mut self) {
+        self.floating_output &= self.floating_output >> 1;
+        if self.floating_output != 0 {
+            self.floating_output_ttl = self.floating_output_fade_time;
+        }
+    }
+
+    /// Edge slew:
+    /// The waveform DAC output cannot follow the instantaneous edges of the
+    /// pulse and sawtooth waveforms, nor the noise steps, since the analog
+    /// output stage only charges at a limited rate. The edges thus ramp over a
+    /// few cycles. This is modeled as a limit on the change of the output per
+    /// cycle.
+    #[inline]
+    fn clock_slew(&mut self, delta: u32) {
+        let target = self.output_waveform() as u32;
+        let output = self.slew_output as u32;
+        let step = delta.saturating_mul(self.slew_rate as u32);
+        self.slew_output = if target > output {
+            output + step.min(target - output)
+        } else {
+            output - step.min(output - target)
+        } as u16;
+    }
+
+    #[inline]
+    fn clock_shift_register(&mut self) {
+        let bit0 = ((self.shift >> 22) ^ (self.shift >> 17)) & 0x01;
+        self.shift = ((self.shift << 1) & SHIFT_MASK) | bit0;
+    }
+
+    /// Noise combined with other waveforms:
+    /// The waveform outputs are connected, and the combined output is the AND
+    /// of the noise output and the other waveform output. Since the noise
+    /// output bits are connected directly to the shift register, output bits
+    /// pulled low by the other waveform are written back into the shift register
+    /// as zeros. A bit once cleared this way is shifted along and may eventually
+    /// clear the whole shift register, locking the noise output at zero until
+    /// the test bit is toggled.
+    ///
+    /// NB! In clock_delta() the output is written back only before each shift,
+    /// using the accumulator value at the end of the delta period.
+    #[inline]
+    fn write_shift_register(&mut self) {
+        if self.waveform > 0x8 {
+            let output = self.output_waveform() as u32;
+            self.shift &= !(0x0040_0000
+                | 0x0010_0000
+                | 0x0001_0000
+                | 0x0000_2000
+                | 0x0000_0800
+                | 0x0000_0080
+                | 0x0000_0010
+                | 0x0000_0004)
+                | ((output & 0x0800) << 11) // Bit 11 -> bit 22
+                | ((output & 0x0400) << 10) // Bit 10 -> bit 20
+                | ((output & 0x0200) << 7) // Bit 9 -> bit 16
+                | ((output & 0x0100) << 5) // Bit 8 -> bit 13
+                | ((output & 0x0080) << 4) // Bit 7 -> bit 11
+                | ((output & 0x0040) << 1) // Bit 6 -> bit 7
+                | ((output & 0x0020) >> 1) // Bit 5 -> bit 4
+                | ((output & 0x0010) >> 2); // Bit 4 -> bit 2
+        }
+    }
+
     // -- Output Functions
 
     /// Noise:
     /// The noise output is taken from intermediate bits of a 23-bit shift register
     /// which is clocked by bit 19 of the accumulator.
     /// NB! The output is actually delayed 2 cycles after bit 19 is set high.
-    /// This is not modeled.
+    /// This is only modeled for 1 cycle clocking, see clock().
     ///
     /// Operation: Calculate EOR result, shift register, set bit 0 = result.
     ///``` ignore,
@@ -410,21 +651,22 @@ impl WaveformGenerator {
 
     #[inline]
     fn output_ps(&self) -> u16 {
-        ((self.wave_ps[self.output_s() as usize] as u16) << 4) & self.output_p()
+        ((self.combined_waveforms.ps[self.output_s() as usize] as u16) << 4) & self.output_p()
     }
 
     #[inline]
     fn output_pst(&self) -> u16 {
-        ((self.wave_pst[self.output_s() as usize] as u16) << 4) & self.output_p()
+        ((self.combined_waveforms.pst[self.output_s() as usize] as u16) << 4) & self.output_p()
     }
 
     #[inline]
     fn output_pt(&self) -> u16 {
-        ((self.wave_pt[(self.output_t() >> 1) as usize] as u16) << 4) & self.output_p()
+        ((self.combined_waveforms.pt[(self.output_t() >> 1) as usize] as u16) << 4)
+            & self.output_p()
     }
 
     #[inline]
     fn output_st(&self) -> u16 {
-        (self.wave_st[self.output_s() as usize] as u16) << 4
+        (self.combined_waveforms.st[self.output_s() as usize] as u16) << 4
     }
 }
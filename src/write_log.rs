@@ -0,0 +1,427 @@
+// This file is part of resid-rs.
+// Copyright (c) 2017-2019 Sebastian Jastrzebski <sebby2k@gmail.com>. All rights reserved.
+// Portions (c) 2004 Dag Lem <resid@nimrod.no>
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
+use core::convert::TryFrom;
+use core::fmt;
+
+use super::builder::{Clock, SidBuilder};
+use super::error::Error;
+use super::sampler::SamplingMethod;
+use super::sid::{Reg, Sid};
+use super::ChipModel;
+
+const MAGIC: &[u8] = b"RSWL";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 31;
+
+/// Register write at an absolute cycle, see Sid::get_cycle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegisterWrite {
+    pub cycle: u64,
+    /// Register offset as passed to Sid::write, i.e. possibly mirrored.
+    pub reg: u8,
+    pub value: u8,
+}
+
+/// Log of the register writes to a SID, recorded by Sid::start_recording
+/// with the chip model and sampling parameters of the SID.
+///
+/// Replaying the log into a fresh SID with the same chip model and sampling
+/// parameters reproduces the audio output bit-exactly, provided the
+/// recording was started on a SID in the reset state, e.g. right after it was
+/// created. Other chip parameters and settings are not recorded, so recording
+/// is refused for a SID which does not have the defaults of Sid::new. Resets,
+/// the external input and changes of the chip model or settings during the
+/// recording are not recorded either.
+///
+/// The binary format starts with a 31-byte header: the magic "RSWL", the
+/// format version, the chip model, the sampling method, the clock and sample
+/// frequencies as 32-bit values, and the start and end cycles as 64-bit
+/// values. Each write follows as the cycles since the previous write, or the
+/// start, as a MIDI style variable length quantity, the register and the
+/// value. All values are big-endian.
+///
+/// For diffing, the log formats as a text listing of the writes, and
+/// frames formats the register values of each frame as a table in the style
+/// of siddump.
+///
+/// ```
+/// use resid::write_log::WriteLog;
+/// use resid::{ChipModel, Sid};
+///
+/// let mut sid = Sid::new(ChipModel::Mos6581);
+/// sid.start_recording().unwrap();
+/// sid.write(0x18, 0x0f);
+/// sid.clock_delta(1000);
+/// let log = sid.stop_recording().unwrap();
+///
+/// let log = WriteLog::parse(&log.to_bytes()).unwrap();
+/// assert_eq!(log.render().unwrap().len(), 44);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct WriteLog {
+    pub chip_model: ChipModel,
+    pub sampling_method: SamplingMethod,
+    pub clock_freq: u32,
+    pub sample_freq: u32,
+    /// Cycle at which the recording started.
+    pub start: u64,
+    /// Cycle at which the recording stopped.
+    pub end: u64,
+    pub writes: Vec<RegisterWrite>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.data.len() - self.pos {
+            return Err(Error::InvalidWriteLog("unexpected end of data"));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?;
+        Ok(bytes
+            .iter()
+            .fold(0, |value, &byte| value << 8 | byte as u32))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let bytes = self.bytes(8)?;
+        Ok(bytes
+            .iter()
+            .fold(0, |value, &byte| value << 8 | byte as u64))
+    }
+
+    /// Variable length quantity of at most 64 bits.
+    fn var_len(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for _i in 0..10 {
+            let byte = self.u8()?;
+            if value >> 57 != 0 {
+                break;
+            }
+            value = value << 7 | (byte & 0x7f) as u64;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::InvalidWriteLog("invalid variable length quantity"))
+    }
+}
+
+fn push_var_len(data: &mut Vec<u8>, value: u64) {
+    let bits = 64 - (value | 1).leading_zeros();
+    let mut shift = (bits - 1) / 7 * 7;
+    while shift > 0 {
+        data.push((value >> shift) as u8 & 0x7f | 0x80);
+        shift -= 7;
+    }
+    data.push(value as u8 & 0x7f);
+}
+
+impl WriteLog {
+    /// Create an empty log for a SID with the given chip model and sampling
+    /// parameters, see Sid::set_sampling_parameters.
+    pub fn new(
+        chip_model: ChipModel,
+        sampling_method: SamplingMethod,
+        clock_freq: u32,
+        sample_freq: u32,
+    ) -> Self {
+        WriteLog {
+            chip_model,
+            sampling_method,
+            clock_freq,
+            sample_freq,
+            start: 0,
+            end: 0,
+            writes: Vec::new(),
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<WriteLog, Error> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.bytes(4)? != MAGIC {
+            return Err(Error::InvalidWriteLog("missing header"));
+        }
+        if reader.u8()? != VERSION {
+            return Err(Error::InvalidWriteLog("unsupported version"));
+        }
+        let chip_model = match reader.u8()? {
+            0 => ChipModel::Mos6581,
+            1 => ChipModel::Mos8580,
+            2 => ChipModel::Clean,
+            _ => return Err(Error::InvalidWriteLog("invalid chip model")),
+        };
+        let sampling_method = match reader.u8()? {
+            0 => SamplingMethod::Fast,
+            1 => SamplingMethod::Interpolate,
+            2 => SamplingMethod::Resample,
+            3 => SamplingMethod::ResampleFast,
+            _ => return Err(Error::InvalidWriteLog("invalid sampling method")),
+        };
+        let clock_freq = reader.u32()?;
+        let sample_freq = reader.u32()?;
+        let start = reader.u64()?;
+        let end = reader.u64()?;
+        if end < start {
+            return Err(Error::InvalidWriteLog("end before start"));
+        }
+        let mut writes = Vec::new();
+        let mut cycle = start;
+        while !reader.is_empty() {
+            let delta = reader.var_len()?;
+            if delta > end - cycle {
+                return Err(Error::InvalidWriteLog("write after end"));
+            }
+            cycle += delta;
+            let reg = reader.u8()?;
+            let value = reader.u8()?;
+            writes.push(RegisterWrite { cycle, reg, value });
+        }
+        Ok(WriteLog {
+            chip_model,
+            sampling_method,
+            clock_freq,
+            sample_freq,
+            start,
+            end,
+            writes,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_LEN + self.writes.len() * 3);
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        data.push(match self.chip_model {
+            ChipModel::Mos6581 => 0,
+            ChipModel::Mos8580 => 1,
+            ChipModel::Clean => 2,
+        });
+        data.push(match self.sampling_method {
+            SamplingMethod::Fast => 0,
+            SamplingMethod::Interpolate => 1,
+            SamplingMethod::Resample => 2,
+            SamplingMethod::ResampleFast => 3,
+        });
+        data.extend_from_slice(&self.clock_freq.to_be_bytes());
+        data.extend_from_slice(&self.sample_freq.to_be_bytes());
+        data.extend_from_slice(&self.start.to_be_bytes());
+        data.extend_from_slice(&self.end.to_be_bytes());
+        let mut cycle = self.start;
+        for write in self.writes.iter() {
+            push_var_len(&mut data, write.cycle - cycle);
+            data.push(write.reg);
+            data.push(write.value);
+            cycle = write.cycle;
+        }
+        data
+    }
+
+    /// Register table with one row per frame of the given number of cycles,
+    /// e.g. 19656 for a PAL C64, in the style of siddump. Each row shows the
+    /// registers at the end of the frame, with the values unchanged since
+    /// the previous row as dots.
+    pub fn frames(&self, frame_cycles: u32) -> FrameTable<'_> {
+        FrameTable {
+            log: self,
+            frame_cycles: frame_cycles.max(1),
+        }
+    }
+
+    /// Create a SID with the chip model and sampling parameters of the log.
+    pub fn create_sid(&self) -> Result<Sid, Error> {
+        SidBuilder::new(self.chip_model)
+            .clock(Clock::Custom(self.clock_freq))
+            .sample_freq(self.sample_freq)
+            .sampling_method(self.sampling_method)
+            .build()
+    }
+
+    /// Replay the writes into a SID in the reset state, returning the audio
+    /// output from the start to the end of the recording.
+    pub fn replay(&self, sid: &mut Sid) -> Vec<i16> {
+        let mut output = Vec::new();
+        let mut cycle = self.start;
+        for write in self.writes.iter() {
+            advance(sid, write.cycle - cycle, &mut output);
+            cycle = write.cycle;
+            sid.write(write.reg, write.value);
+        }
+        advance(sid, self.end - cycle, &mut output);
+        output
+    }
+
+    /// Replay the writes into a fresh SID, see replay.
+    pub fn render(&self) -> Result<Vec<i16>, Error> {
+        let mut sid = self.create_sid()?;
+        Ok(self.replay(&mut sid))
+    }
+}
+
+fn advance(sid: &mut Sid, mut cycles: u64, output: &mut Vec<i16>) {
+    let mut buffer = [0i16; 1024];
+    while cycles > 0 {
+        let mut delta = cycles.min(0x10000) as u32;
+        cycles -= delta as u64;
+        while delta > 0 {
+            let (samples, next_delta) = sid.sample(delta, &mut buffer, 1);
+            output.extend_from_slice(&buffer[..samples]);
+            delta = next_delta;
+        }
+    }
+}
+
+/// Text dump for diffing, with one line per write giving the cycle, the
+/// cycles since the previous write, the register and the value.
+impl fmt::Display for WriteLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "# {:?}, {:?} sampling, clock {}Hz, sample {}Hz",
+            self.chip_model, self.sampling_method, self.clock_freq, self.sample_freq
+        )?;
+        writeln!(f, "# cycles {} to {}", self.start, self.end)?;
+        writeln!(f, "#      cycle    delta reg          value")?;
+        let mut cycle = self.start;
+        for write in self.writes.iter() {
+            let name = match Reg::try_from(write.reg & 0x1f) {
+                Ok(reg) => format!("{:?}", reg),
+                Err(_) => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "{:12} {:8} ${:02x} {:<8} ${:02x}",
+                write.cycle,
+                write.cycle - cycle,
+                write.reg,
+                name,
+                write.value
+            )?;
+            cycle = write.cycle;
+        }
+        Ok(())
+    }
+}
+
+/// Per-frame register table of a write log, see WriteLog::frames.
+pub struct FrameTable<'a> {
+    log: &'a WriteLog,
+    frame_cycles: u32,
+}
+
+const FILTER_TYPES: [&str; 8] = ["Off", "Low", "Bnd", "L+B", "Hi ", "L+H", "B+H", "LBH"];
+
+/// Columns of a voice: frequency, waveform, ADSR and pulse width.
+fn voice_columns(regs: &[u8]) -> [String; 4] {
+    [
+        format!("{:04x}", (regs[1] as u16) << 8 | regs[0] as u16),
+        format!("{:02x}", regs[4]),
+        format!("{:02x}{:02x}", regs[5], regs[6]),
+        format!("{:03x}", ((regs[3] & 0x0f) as u16) << 8 | regs[2] as u16),
+    ]
+}
+
+/// Columns of the filter: cutoff, resonance and routing, type and volume.
+fn filter_columns(regs: &[u8]) -> [String; 4] {
+    [
+        format!(
+            "{:04x}",
+            (regs[0x16] as u16) << 3 | (regs[0x15] & 0x07) as u16
+        ),
+        format!("{:02x}", regs[0x17]),
+        FILTER_TYPES[(regs[0x18] >> 4 & 0x07) as usize].to_string(),
+        format!("{:x}", regs[0x18] & 0x0f),
+    ]
+}
+
+fn columns(regs: &[u8]) -> Vec<[String; 4]> {
+    vec![
+        voice_columns(&regs[0x00..0x07]),
+        voice_columns(&regs[0x07..0x0e]),
+        voice_columns(&regs[0x0e..0x15]),
+        filter_columns(regs),
+    ]
+}
+
+impl<'a> fmt::Display for FrameTable<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let voice_header = " Freq WF ADSR Pul |";
+        writeln!(
+            f,
+            "| Frame |{}{}{} FCut RC Typ V |",
+            voice_header, voice_header, voice_header
+        )?;
+        let voice_rule = "------------------+";
+        writeln!(
+            f,
+            "+-------+{}{}{}---------------+",
+            voice_rule, voice_rule, voice_rule
+        )?;
+        let log = self.log;
+        let frame_cycles = self.frame_cycles as u64;
+        let mut regs = [0u8; 0x19];
+        let mut previous: Option<Vec<[String; 4]>> = None;
+        let mut writes = log.writes.iter().peekable();
+        let mut frame = 0;
+        let mut frame_start = log.start;
+        while frame_start < log.end {
+            let frame_end = frame_start + frame_cycles;
+            while let Some(write) = writes.peek() {
+                if write.cycle >= frame_end {
+                    break;
+                }
+                let reg = (write.reg & 0x1f) as usize;
+                if reg < regs.len() {
+                    regs[reg] = write.value;
+                }
+                writes.next();
+            }
+            let current = columns(&regs);
+            write!(f, "| {:5} |", frame)?;
+            for (i, group) in current.iter().enumerate() {
+                for (j, column) in group.iter().enumerate() {
+                    let unchanged = match previous {
+                        Some(ref previous) => previous[i][j] == *column,
+                        None => false,
+                    };
+                    if unchanged {
+                        write!(f, " {}", ".".repeat(column.len()))?;
+                    } else {
+                        write!(f, " {}", column)?;
+                    }
+                }
+                write!(f, " |")?;
+            }
+            writeln!(f)?;
+            previous = Some(current);
+            frame += 1;
+            frame_start = frame_end;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use resid::asid::{AsidDecoder, AsidEvent, AsidPlayer, TimedEvent};
+use resid::{ChipModel, Sid};
+
+const START: &[u8] = &[0xf0, 0x2d, 0x4c, 0xf7];
+const STOP: &[u8] = &[0xf0, 0x2d, 0x4d, 0xf7];
+
+// Update of FREQLO1 = 0x8d, FREQHI1 = 0x1c, MODVOL = 0x0f and the control
+// register of voice 1, first written as 0x40 and then as 0x41.
+const UPDATE: &[u8] = &[
+    0xf0, 0x2d, 0x4e, //
+    0x03, 0x00, 0x00, 0x13, // mask: regs 0, 1, 21, 22, 25
+    0x01, 0x00, 0x00, 0x00, // msb: reg 0
+    0x0d, 0x1c, 0x0f, 0x40, 0x41, //
+    0xf7,
+];
+
+fn decode(bytes: &[u8]) -> Vec<AsidEvent> {
+    let mut decoder = AsidDecoder::default();
+    let mut events = Vec::new();
+    decoder.decode(bytes, |event| events.push(event));
+    events
+}
+
+fn write(sid: usize, reg: u8, value: u8) -> AsidEvent {
+    AsidEvent::Write { sid, reg, value }
+}
+
+#[test]
+fn start_stop() {
+    let mut decoder = AsidDecoder::default();
+    let mut events = Vec::new();
+    decoder.decode(START, |event| events.push(event));
+    assert!(decoder.is_playing());
+    decoder.decode(STOP, |event| events.push(event));
+    assert!(!decoder.is_playing());
+    assert_eq!(events, vec![AsidEvent::Start, AsidEvent::Stop]);
+}
+
+#[test]
+fn register_update() {
+    assert_eq!(
+        decode(UPDATE),
+        vec![
+            write(0, 0x00, 0x8d),
+            write(0, 0x01, 0x1c),
+            write(0, 0x18, 0x0f),
+            write(0, 0x04, 0x40),
+            write(0, 0x04, 0x41),
+        ]
+    );
+}
+
+#[test]
+fn register_update_msb() {
+    // All registers with the MSB set, the value is the ASID index.
+    let mut update = vec![
+        0xf0, 0x2d, 0x4e, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f,
+    ];
+    update.extend(0..28);
+    update.push(0xf7);
+    let events = decode(&update);
+    assert_eq!(events.len(), 28);
+    assert_eq!(events[0], write(0, 0x00, 0x80));
+    assert_eq!(events[4], write(0, 0x05, 0x84));
+    assert_eq!(events[21], write(0, 0x18, 0x95));
+    assert_eq!(events[24], write(0, 0x12, 0x98));
+    assert_eq!(events[27], write(0, 0x12, 0x9b));
+}
+
+#[test]
+fn multi_sid() {
+    let mut update = UPDATE.to_vec();
+    update[2] = 0x50;
+    assert_eq!(decode(&update)[0], write(1, 0x00, 0x8d));
+    update[2] = 0x51;
+    assert_eq!(decode(&update)[0], write(2, 0x00, 0x8d));
+}
+
+#[test]
+fn stream() {
+    // A dump with a display message, a message for another manufacturer,
+    // real-time clock bytes within a message and a note on between messages.
+    let mut dump = Vec::new();
+    dump.extend_from_slice(START);
+    dump.extend_from_slice(&[0xf0, 0x2d, 0x4f, b'H', b'I', 0xf7]);
+    dump.extend_from_slice(&[0xf0, 0x43, 0x4e, 0x00, 0xf7]);
+    dump.extend_from_slice(&UPDATE[..6]);
+    dump.push(0xf8);
+    dump.extend_from_slice(&UPDATE[6..]);
+    dump.extend_from_slice(&[0x90, 0x3c, 0x40]);
+    dump.extend_from_slice(STOP);
+    let expected = decode(&dump);
+    assert_eq!(expected.len(), 7);
+    assert_eq!(expected[0], AsidEvent::Start);
+    assert_eq!(expected[6], AsidEvent::Stop);
+    // Fed in chunks of any size.
+    for &chunk_size in [1, 3, 7].iter() {
+        let mut decoder = AsidDecoder::default();
+        let mut events = Vec::new();
+        for chunk in dump.chunks(chunk_size) {
+            decoder.decode(chunk, |event| events.push(event));
+        }
+        assert_eq!(events, expected);
+    }
+}
+
+#[test]
+fn malformed() {
+    // Truncated by a status byte.
+    let mut update = UPDATE[..8].to_vec();
+    update.push(0x90);
+    update.extend_from_slice(&UPDATE[8..]);
+    assert_eq!(decode(&update), vec![]);
+    // Data byte count not matching the mask.
+    let mut update = UPDATE.to_vec();
+    update.remove(12);
+    assert_eq!(decode(&update), vec![]);
+    // Too short.
+    assert_eq!(decode(&[0xf0, 0x2d, 0x4e, 0x01, 0xf7]), vec![]);
+}
+
+#[test]
+fn timed() {
+    let mut decoder = AsidDecoder::default();
+    let mut events = Vec::new();
+    decoder.decode_timed(100, START, &mut events);
+    decoder.decode_timed(200, &UPDATE[..5], &mut events);
+    decoder.decode_timed(300, &UPDATE[5..], &mut events);
+    assert_eq!(events.len(), 6);
+    assert_eq!(
+        events[0],
+        TimedEvent {
+            time: 100,
+            event: AsidEvent::Start
+        }
+    );
+    // Events are stamped with the time of the end of their message.
+    assert_eq!(
+        events[1],
+        TimedEvent {
+            time: 300,
+            event: write(0, 0x00, 0x8d)
+        }
+    );
+}
+
+#[test]
+fn player() {
+    let mut sids = [Sid::new(ChipModel::Mos6581), Sid::new(ChipModel::Mos6581)];
+    let mut player = AsidPlayer::default();
+    player.play(&mut sids, START);
+    player.play(&mut sids, UPDATE);
+    let mut update = UPDATE.to_vec();
+    update[2] = 0x51;
+    player.play(&mut sids, &update);
+    assert!(player.get_decoder().is_playing());
+    let registers = sids[0].read_state().sid_register;
+    assert_eq!(registers[0x00], 0x8d);
+    assert_eq!(registers[0x01], 0x1c);
+    assert_eq!(registers[0x04], 0x41);
+    assert_eq!(registers[0x18], 0x0f);
+    assert_eq!(sids[1].read_state().sid_register[0x00], 0x00);
+    player.play(&mut sids, STOP);
+    assert_eq!(sids[0].read_state().sid_register[0x18], 0x00);
+}
+
+/// Synthetic session in the layout of tracker ASID output: start, a display
+/// text message, 64 frames of updates with only the changed registers and
+/// hard restarts of the control registers, each followed by a MIDI clock
+/// byte, and stop. Not a capture of a real tracker, see
+/// data/asid_stream_synthetic.py.
+static SYNTHETIC_STREAM: &[u8] = include_bytes!("data/asid_stream_synthetic.syx");
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+static SYNTHETIC_STREAM_REGISTERS: [u8; 0x19] = [
+    0x68, 0x10, 0xf0, 0x08, 0x40, 0x09, 0xa8,
+    0x67, 0x11, 0x30, 0x08, 0x21, 0x09, 0xa8,
+    0x0e, 0x0d, 0x70, 0x08, 0x11, 0x09, 0xa8,
+    0x07, 0x7f, 0xf1, 0x1f,
+];
+
+#[test]
+fn synthetic_stream_file() {
+    let events = decode(SYNTHETIC_STREAM);
+    assert_eq!(events.len(), 576 + 2);
+    assert_eq!(events[0], AsidEvent::Start);
+    assert_eq!(events[events.len() - 1], AsidEvent::Stop);
+    // Play the frames at 50Hz, up to the stop message.
+    let mut sids = [Sid::new(ChipModel::Mos6581)];
+    let mut player = AsidPlayer::default();
+    let mut buffer = [0i16; 1024];
+    let mut peak = 0;
+    let (frames, stop) = SYNTHETIC_STREAM.split_at(SYNTHETIC_STREAM.len() - STOP.len());
+    assert_eq!(stop, STOP);
+    for frame in frames.split(|&byte| byte == 0xf8) {
+        player.play(&mut sids, frame);
+        let mut delta = 19656;
+        while delta > 0 {
+            let (samples, next_delta) = sids[0].sample(delta, &mut buffer, 1);
+            for &sample in buffer[..samples].iter() {
+                peak = peak.max((sample as i32).abs());
+            }
+            delta = next_delta;
+        }
+    }
+    assert!(peak > 1000);
+    let registers = sids[0].read_state().sid_register;
+    assert_eq!(registers[..0x19], SYNTHETIC_STREAM_REGISTERS[..]);
+    player.play(&mut sids, stop);
+    assert!(!player.get_decoder().is_playing());
+}
@@ -0,0 +1,97 @@
+use resid::{ChipModel, Clock, Error, SamplingMethod, Sid, SidBuilder};
+
+fn play(sid: &mut Sid) -> Vec<i16> {
+    let mut buffer = [0i16; 4096];
+    let (samples, _next_delta) = sid.sample(20000, &mut buffer, 1);
+    buffer[..samples].to_vec()
+}
+
+fn registers() -> [u8; 0x19] {
+    let mut registers = [0u8; 0x19];
+    registers[0x01] = 0x19; // FREQHI1
+    registers[0x05] = 0x09; // AD1
+    registers[0x06] = 0xf0; // SR1
+    registers[0x17] = 0xf1; // RESFILT
+    registers[0x18] = 0x1f; // MODVOL
+    registers[0x04] = 0x21; // CR1
+    registers
+}
+
+#[test]
+fn clock_presets() {
+    assert_eq!(Clock::Pal.frequency(), 985_248);
+    assert_eq!(Clock::Ntsc.frequency(), 1_022_727);
+    assert_eq!(Clock::NtscOld.frequency(), 1_022_727);
+    assert_eq!(Clock::Drean.frequency(), 1_023_440);
+    assert_eq!(Clock::Custom(1_000_000).frequency(), 1_000_000);
+}
+
+#[test]
+fn build_defaults() {
+    let mut sid = SidBuilder::new(ChipModel::Mos6581).build().unwrap();
+    let mut sid_new = Sid::new(ChipModel::Mos6581);
+    assert_eq!(play(&mut sid), play(&mut sid_new));
+}
+
+#[test]
+fn build_options() {
+    let mut sid = SidBuilder::new(ChipModel::Mos8580)
+        .clock(Clock::Ntsc)
+        .sample_freq(48000)
+        .sampling_method(SamplingMethod::Interpolate)
+        .filter(false)
+        .external_filter(false)
+        .registers(registers())
+        .build()
+        .unwrap();
+    let mut sid_manual = Sid::new(ChipModel::Mos8580);
+    sid_manual
+        .set_sampling_parameters(SamplingMethod::Interpolate, 1_022_727, 48000)
+        .unwrap();
+    sid_manual.enable_filter(false);
+    sid_manual.enable_external_filter(false);
+    for (reg, &value) in registers().iter().enumerate() {
+        sid_manual.write(reg as u8, value);
+    }
+    assert_eq!(sid.read(0x1c), sid_manual.read(0x1c));
+    assert_eq!(play(&mut sid), play(&mut sid_manual));
+}
+
+#[test]
+fn build_registers_gate() {
+    // The gate opens with the attack rate of the registers.
+    let mut registers = [0u8; 0x19];
+    registers[0x12] = 0x11; // CR3
+    registers[0x13] = 0x49; // AD3
+    registers[0x14] = 0xf0; // SR3
+    let mut sid = SidBuilder::new(ChipModel::Mos6581)
+        .registers(registers)
+        .build()
+        .unwrap();
+    let mut sid_manual = Sid::new(ChipModel::Mos6581);
+    sid_manual.write(0x13, 0x49); // AD3
+    sid_manual.write(0x14, 0xf0); // SR3
+    sid_manual.write(0x12, 0x11); // CR3
+    for _i in 0..100 {
+        sid.clock_delta(1000);
+        sid_manual.clock_delta(1000);
+        assert_eq!(sid.read(0x1c), sid_manual.read(0x1c));
+    }
+    assert_eq!(sid.read(0x1c), 0xff);
+}
+
+#[test]
+fn build_invalid() {
+    let result = SidBuilder::new(ChipModel::Mos6581)
+        .clock(Clock::Custom(0))
+        .build();
+    assert_eq!(
+        result.err(),
+        Some(Error::SampleFrequencyAboveClock {
+            clock_freq: 0,
+            sample_freq: 44100
+        })
+    );
+    let result = SidBuilder::new(ChipModel::Mos6581).sample_freq(0).build();
+    assert_eq!(result.err(), Some(Error::InvalidSampleFrequency(0)));
+}
@@ -0,0 +1,132 @@
+use std::f64;
+
+use resid::filter::Filter;
+use resid::{ChipModel, ChipProfile, Sid};
+
+fn play(sid: &mut Sid) -> Vec<i16> {
+    sid.write(0x05, 0x09); // AD1
+    sid.write(0x06, 0x00); // SR1
+    sid.write(0x18, 0x1f); // MODVOL
+    sid.write(0x17, 0xf1); // RESFILT
+    sid.write(0x16, 0x40); // FCHI
+    sid.write(0x01, 25); // FREQHI1
+    sid.write(0x00, 177); // FREQLO1
+    sid.write(0x04, 0x41); // CR1
+    sid.write(0x03, 0x08); // PWHI1
+    (0..2000)
+        .map(|_i| {
+            sid.clock_delta(22);
+            sid.output()
+        })
+        .collect()
+}
+
+// Output of play_note recorded with the presets. The MOS8580 has no DC
+// offsets, so its output is centered around zero.
+static PRESET_OUTPUT_6581: [i16; 48] = [
+    15839, 15578, 15310, 15053, 14800, 14549, 14300, 14054, 13811, 13570, 13331, 13085, 19078,
+    19260, 19441, 19619, 19796, 19971, 20145, 20316, 20520, 20688, 20854, 20646, 20441, 9209, 9118,
+    9028, 8938, 8850, 8762, 8675, 8599, 8514, 8430, 8346, 8263, 8182, 19086, 18897, 18709, 18523,
+    18339, 18157, 17976, 17798, 17621, 17446,
+];
+static PRESET_OUTPUT_8580: [i16; 48] = [
+    -216, -451, -706, -936, -1164, -1389, -1612, -1833, -2052, -2269, -2484, -2718, 3298, 3503,
+    3707, 3909, 4108, 4306, 4502, 4695, 4909, 5098, 5286, 5234, 5182, -5897, -5837, -5778, -5720,
+    -5663, -5606, -5549, -5472, -5417, -5362, -5308, -5254, -5201, 5836, 5778, 5721, 5665, 5609,
+    5554, 5499, 5444, 5391, 5338,
+];
+
+/// A pulse note with the filter off, released after 4000 cycles.
+fn play_note(sid: &mut Sid) -> Vec<i16> {
+    sid.write(0x05, 0x09); // AD1
+    sid.write(0x06, 0x00); // SR1
+    sid.write(0x18, 0x0f); // MODVOL
+    sid.write(0x01, 25); // FREQHI1
+    sid.write(0x00, 177); // FREQLO1
+    sid.write(0x03, 0x08); // PWHI1
+    sid.write(0x04, 0x41); // CR1
+    let mut output = Vec::new();
+    for i in 0..48 {
+        if i == 40 {
+            sid.write(0x04, 0x40); // CR1
+        }
+        sid.clock_delta(100);
+        output.push(sid.output());
+    }
+    output
+}
+
+#[test]
+fn presets() {
+    let mut sid = Sid::with_profile(&ChipProfile::new(ChipModel::Mos6581));
+    assert_eq!(play_note(&mut sid)[..], PRESET_OUTPUT_6581[..]);
+    let mut sid = Sid::with_profile(&ChipProfile::new(ChipModel::Mos8580));
+    assert_eq!(play_note(&mut sid)[..], PRESET_OUTPUT_8580[..]);
+}
+
+#[test]
+fn custom_profile() {
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    let output = play(&mut sid);
+    // A chip with the filter cutoff frequency curve of the MOS8580.
+    let mut profile = ChipProfile::new(ChipModel::Mos6581);
+    profile.f0_points = ChipProfile::new(ChipModel::Mos8580).f0_points;
+    let mut sid = Sid::with_profile(&profile);
+    assert_ne!(play(&mut sid), output);
+    // A chip without DC offsets.
+    let mut profile = ChipProfile::new(ChipModel::Mos6581);
+    profile.wave_zero = 0x800;
+    profile.voice_dc = 0;
+    profile.mixer_dc = 0;
+    profile.ext_filter_dc = 0;
+    let mut sid = Sid::with_profile(&profile);
+    assert_ne!(play(&mut sid), output);
+}
+
+#[test]
+fn custom_f0_points() {
+    // Linear FC to cutoff frequency mapping.
+    let mut profile = ChipProfile::new(ChipModel::Mos8580);
+    profile.f0_points = vec![(0, 0), (0, 0), (2047, 10235), (2047, 10235)].into();
+    let filter = Filter::with_profile(&profile);
+    assert_eq!(filter.cutoff_hz(0), 0.0);
+    assert_eq!(filter.cutoff_hz(1000), 5000.0);
+}
+
+#[test]
+fn clean_profile() {
+    let profile = ChipProfile::clean();
+    assert_eq!(profile.chip_model, ChipModel::Clean);
+    assert_eq!(
+        play(&mut Sid::new(ChipModel::Clean)),
+        play(&mut Sid::with_profile(&profile))
+    );
+    assert_eq!(profile.wave_zero, 0x800);
+    assert_eq!(profile.voice_dc, 0);
+    assert_eq!(profile.mixer_dc, 0);
+    assert_eq!(profile.ext_filter_dc, 0);
+    // Linear FC mapping.
+    let filter = Filter::with_profile(&profile);
+    for fc in 0..2048 {
+        let f0 = 30.0 + (12000.0 - 30.0) * fc as f64 / 2047.0;
+        assert!((filter.cutoff_hz(fc) - f0).abs() <= 1.0);
+    }
+    // Linear Q, starting at the Butterworth response.
+    for res in 0..16 {
+        let q = f64::consts::FRAC_1_SQRT_2 + res as f64 / 15.0;
+        assert!((filter.q(res) - q).abs() < 1e-9);
+    }
+    // No DC offsets, so silent voices and volume writes give no output.
+    let mut sid = Sid::with_profile(&profile);
+    sid.enable_external_filter(false);
+    sid.write(0x04, 0x40); // CR1
+    sid.write(0x03, 0x08); // PWHI1
+    sid.write(0x01, 0x10); // FREQHI1
+    for i in 0..10000 {
+        if i % 100 == 0 {
+            sid.write(0x18, if i % 200 == 0 { 0x0f } else { 0x00 }); // MODVOL
+        }
+        sid.clock();
+        assert_eq!(sid.output(), 0);
+    }
+}
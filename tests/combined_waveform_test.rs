@@ -0,0 +1,54 @@
+use resid::combined_waveform::{CombinedWaveformModel, CombinedWaveforms};
+use resid::wave::WaveformGenerator;
+use resid::ChipModel;
+
+fn bit_errors(a: &[u8], b: &[u8]) -> u32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x ^ y).count_ones())
+        .sum()
+}
+
+#[test]
+fn model_approximates_sampled_tables() {
+    for chip_model in [ChipModel::Mos6581, ChipModel::Mos8580].iter() {
+        let sampled = CombinedWaveforms::new(*chip_model);
+        let model = CombinedWaveforms::from_model(&CombinedWaveformModel::new(*chip_model));
+        assert_eq!(model.st.len(), 4096);
+        assert_eq!(model.pt.len(), 4096);
+        assert_eq!(model.ps.len(), 4096);
+        assert_eq!(model.pst.len(), 4096);
+        // The model is fitted to other chips than the sampled tables, allow
+        // for up to 1 in 8 differing bits (1 in 4 for PS).
+        assert!(bit_errors(&model.st, &sampled.st) < 4096);
+        assert!(bit_errors(&model.pt, &sampled.pt) < 4096);
+        assert!(bit_errors(&model.ps, &sampled.ps) < 4096 * 2);
+        assert!(bit_errors(&model.pst, &sampled.pst) < 4096);
+    }
+}
+
+#[test]
+fn model_output() {
+    let model = CombinedWaveformModel::new(ChipModel::Mos8580);
+    // Non-combined waveforms are not modeled.
+    assert_eq!(model.output(0x1, 0x800), 0);
+    assert_eq!(model.output(0x4, 0x800), 0);
+    // Combined waveforms are silent at zero and loud near the top.
+    assert_eq!(model.output(0x3, 0x000), 0);
+    assert_eq!(model.output(0x7, 0x000), 0);
+    assert_eq!(model.output(0x6, 0xfff) & 0xf00, 0xf00);
+}
+
+#[test]
+fn waveform_generator_tables() {
+    let model = CombinedWaveformModel::new(ChipModel::Mos6581);
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    wave.set_combined_waveforms(CombinedWaveforms::from_model(&model));
+    wave.set_control(0x30);
+    wave.set_frequency_hi(0x10);
+    for _i in 0..1000 {
+        wave.clock();
+        let acc = (wave.get_acc() >> 12) as u16;
+        assert_eq!(wave.output(), model.output(0x3, acc) & 0xff0);
+    }
+}
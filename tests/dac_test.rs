@@ -0,0 +1,67 @@
+use resid::dac::{build_dac_table, DacConfig};
+use resid::voice::Voice;
+use resid::{ChipModel, Sid};
+
+#[test]
+fn dac_8580_linear() {
+    let config = DacConfig::from_chip_model(ChipModel::Mos8580);
+    let table = build_dac_table(12, config, 1.0);
+    assert_eq!(table.len(), 4096);
+    for (i, &value) in table.iter().enumerate() {
+        assert_eq!(value as usize, i);
+    }
+    let table = build_dac_table(4, config, 256.0);
+    for (i, &value) in table.iter().enumerate() {
+        assert_eq!(value as usize, i << 8);
+    }
+}
+
+#[test]
+fn dac_6581_non_linear() {
+    let config = DacConfig::from_chip_model(ChipModel::Mos6581);
+    let table = build_dac_table(8, config, 1.0);
+    assert_eq!(table[0], 0);
+    // The missing termination makes bit 0 weigh more than it should.
+    assert!(table[1] > 1);
+    assert!(table.iter().enumerate().any(|(i, &value)| value as usize != i));
+    assert!((table[255] as i32 - 255).abs() < 16);
+}
+
+#[test]
+fn voice_dac() {
+    let mut voice = Voice::new(ChipModel::Mos6581);
+    voice.envelope.set_attack_decay(0x00);
+    voice.envelope.set_sustain_release(0xf0);
+    voice.wave.borrow_mut().set_frequency_hi(0x10);
+    voice.set_control(0x21);
+    for _i in 0..1000 {
+        voice.envelope.clock();
+        voice.wave.borrow_mut().clock();
+    }
+    let linear = voice.output();
+    voice.set_dac(Some(DacConfig::from_chip_model(ChipModel::Mos8580)));
+    assert_eq!(voice.output(), linear);
+    voice.set_dac(Some(DacConfig::from_chip_model(ChipModel::Mos6581)));
+    assert_ne!(voice.output(), linear);
+    voice.set_dac(None);
+    assert_eq!(voice.output(), linear);
+}
+
+#[test]
+fn sid_dac_linear() {
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    let mut sid_dac = Sid::new(ChipModel::Mos8580);
+    sid_dac.set_dac(Some(DacConfig::from_chip_model(ChipModel::Mos8580)));
+    for sid in [&mut sid, &mut sid_dac].iter_mut() {
+        sid.write(0x05, 0x09);
+        sid.write(0x06, 0xf0);
+        sid.write(0x18, 0x0a);
+        sid.write(0x01, 0x19);
+        sid.write(0x04, 0x21);
+    }
+    for _i in 0..1000 {
+        sid.clock_delta(22);
+        sid_dac.clock_delta(22);
+        assert_eq!(sid.output(), sid_dac.output());
+    }
+}
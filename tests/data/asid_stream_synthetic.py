@@ -0,0 +1,93 @@
+#!/usr/bin/env python3
+# Generates asid_stream_synthetic.syx:
+#     python3 tests/data/asid_stream_synthetic.py > tests/data/asid_stream_synthetic.syx
+#
+# A synthetic ASID session in the layout sent by tracker ASID output: a start
+# message, a display text message, one update per 50Hz frame with only the
+# changed registers (all of them in the first frame) and hard restarts of the
+# control registers, each followed by a MIDI clock byte, and a stop message.
+#
+# NB! This is not a capture of a real tracker. It only exercises the decoder
+# with the message layout, and should be replaced with a recorded session.
+import sys
+
+# SID register of each ASID register index. The control registers are sent
+# twice, for a second write within the frame.
+ASID_REGISTERS = [
+    0x00, 0x01, 0x02, 0x03, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0c, 0x0d,
+    0x0e, 0x0f, 0x10, 0x11, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18,
+    0x04, 0x0b, 0x12, 0x04, 0x0b, 0x12,
+]
+NOTES = [0x1167, 0x1389, 0x15ed, 0x1751, 0x1a1c, 0x1d45, 0x20d0, 0x22d0]
+
+
+def update(writes):
+    """SID update message for a dict of ASID register index -> value."""
+    mask = [0] * 4
+    msb = [0] * 4
+    data = []
+    for i in range(28):
+        if i in writes:
+            value = writes[i]
+            mask[i // 7] |= 1 << (i % 7)
+            if value & 0x80:
+                msb[i // 7] |= 1 << (i % 7)
+            data.append(value & 0x7f)
+    return bytes([0xf0, 0x2d, 0x4e] + mask + msb + data + [0xf7])
+
+
+def session():
+    out = bytearray([0xf0, 0x2d, 0x4c, 0xf7])
+    out += bytes([0xf0, 0x2d, 0x4f]) + b"RESID TEST" + bytes([0xf7])
+    regs = [0] * 0x19
+    for frame in range(64):
+        new = list(regs)
+        new[0x18] = 0x1f
+        new[0x17] = 0xf1
+        new[0x16] = 0x40 + frame
+        new[0x15] = frame & 7
+        first = {}
+        second = {}
+        for v in range(3):
+            base = v * 7
+            new[base + 5] = 0x09
+            new[base + 6] = 0xa8
+            new[base + 2] = (frame * 16 + v * 64) & 0xff
+            new[base + 3] = 0x08
+            waveform = [0x40, 0x20, 0x10][v]
+            step = (frame + v * 3) // 4
+            first[v] = None
+            second[v] = None
+            if (frame + v * 3) % 4 == 0:
+                f = NOTES[(step * (v + 2)) % 8] >> (1 - v % 2)
+                new[base] = f & 0xff
+                new[base + 1] = f >> 8
+                # Hard restart: gate off, then on within the frame.
+                first[v] = waveform
+                second[v] = waveform | 1
+            elif (frame + v * 3) % 4 == 3:
+                first[v] = waveform
+            if first[v] is None:
+                new[base + 4] = regs[base + 4] if frame else waveform | 1
+                if frame == 0:
+                    first[v] = waveform | 1
+            else:
+                new[base + 4] = second[v] if second[v] is not None else first[v]
+        writes = {}
+        for i, reg in enumerate(ASID_REGISTERS[:22]):
+            if frame == 0 or new[reg] != regs[reg]:
+                writes[i] = new[reg]
+        for v in range(3):
+            if first[v] is not None:
+                writes[22 + v] = first[v]
+            if second[v] is not None:
+                writes[25 + v] = second[v]
+        out += update(writes)
+        regs = new
+        # MIDI clock between frames.
+        out += bytes([0xf8])
+    out += bytes([0xf0, 0x2d, 0x4d, 0xf7])
+    return bytes(out)
+
+
+sys.stdout.buffer.write(session())
@@ -1,4 +1,6 @@
 pub mod sid_output;
+pub mod sid_output_8580;
 pub mod spline_output;
 pub mod wave_delta_output;
+pub mod wave_noise_output;
 pub mod wave_output;
@@ -0,0 +1,80 @@
+#!/usr/bin/env python3
+# Generates wave_noise_output.rs:
+#     python3 tests/data/wave_noise_output.py > tests/data/wave_noise_output.rs
+#
+# A separate model of the SID noise LFSR as described for reSID: 23-bit LFSR,
+# feedback bit22 ^ bit17, output bits 22,20,16,13,11,7,4,2, shift 2 cycles
+# after accumulator bit 19 rises, and combined waveforms ANDed onto the noise
+# output bits and written back into the LFSR.
+#
+# NB! This is not a capture from a chip. It only guards the emulation against
+# drifting from this model, and should be replaced with OSC3 captures.
+OUT_BITS = [22, 20, 16, 13, 11, 7, 4, 2]  # -> output bits 11..4
+
+class Osc:
+    def __init__(s, waveform, freq, pw):
+        s.acc = 0; s.shift = 0x7ffff8; s.pipe = 0
+        s.waveform, s.freq, s.pw = waveform, freq, pw
+    def noise(s):
+        o = 0
+        for i, b in enumerate(OUT_BITS):
+            o |= ((s.shift >> b) & 1) << (11 - i)
+        return o
+    def tri(s):
+        a = s.acc
+        o = (~a if a & 0x800000 else a) >> 11
+        return o & 0xfff
+    def pulse(s):
+        return 0xfff if (s.acc >> 12) >= s.pw else 0
+    def output(s):
+        o = s.noise()
+        if s.waveform & 1: o &= s.tri()
+        if s.waveform & 4: o &= s.pulse()
+        return o
+    def clock(s):
+        prev = s.acc
+        s.acc = (s.acc + s.freq) & 0xffffff
+        if not prev & 0x80000 and s.acc & 0x80000:
+            s.pipe = 2
+        elif s.pipe:
+            s.pipe -= 1
+            if s.pipe == 0:
+                bit0 = ((s.shift >> 22) ^ (s.shift >> 17)) & 1
+                s.shift = ((s.shift << 1) & 0x7fffff) | bit0
+        if s.waveform > 8:
+            o = s.output()
+            for i, b in enumerate(OUT_BITS):
+                if not (o >> (11 - i)) & 1:
+                    s.shift &= ~(1 << b)
+
+def seq(waveform, freq, pw, step, n):
+    osc = Osc(waveform, freq, pw)
+    out = []
+    for _ in range(n):
+        for _ in range(step):
+            osc.clock()
+        out.append(osc.output() >> 4)
+    return out
+
+def emit(name, values):
+    lines = ["#[cfg_attr(rustfmt, rustfmt_skip)]",
+             "pub static %s: [u8; %d] = [" % (name, len(values))]
+    for i in range(0, len(values), 8):
+        lines.append("    " + ", ".join("0x%02x" % v for v in values[i:i + 8]) + ",")
+    lines.append("];")
+    return "\n".join(lines)
+
+print("""// OSC3 readback sequences of the noise waveform, alone and combined with
+// triangle and with pulse at a pulse width of 0x100. OSC3 is read every 32
+// cycles at a frequency of 0x8000, i.e. once per shift of the noise
+// register, starting from the shift register value after reset.
+//
+// NB! These are not captures from a chip. They are generated by
+// wave_noise_output.py from a separate model of the noise LFSR, and should be
+// replaced with OSC3 captures.
+""")
+print(emit("NOISE_OSC3", seq(0x8, 0x8000, 0, 32, 256)))
+print()
+print(emit("NOISE_TRIANGLE_OSC3", seq(0x9, 0x8000, 0, 32, 32)))
+print()
+print(emit("NOISE_PULSE_OSC3", seq(0xc, 0x8000, 0x100, 32, 32)))
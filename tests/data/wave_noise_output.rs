@@ -0,0 +1,60 @@
+// OSC3 readback sequences of the noise waveform, alone and combined with
+// triangle and with pulse at a pulse width of 0x100. OSC3 is read every 32
+// cycles at a frequency of 0x8000, i.e. once per shift of the noise
+// register, starting from the shift register value after reset.
+//
+// NB! These are not captures from a chip. They are generated by
+// wave_noise_output.py from a separate model of the noise LFSR, and should be
+// replaced with OSC3 captures.
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+pub static NOISE_OSC3: [u8; 256] = [
+    0xfe, 0xfc, 0xfc, 0xfc, 0xf8, 0xf8, 0xf8, 0xf8,
+    0xf0, 0xf0, 0xe0, 0xe0, 0xe0, 0xc0, 0xc0, 0xc0,
+    0xc0, 0x81, 0x81, 0x03, 0x03, 0x03, 0x06, 0x06,
+    0x04, 0x04, 0x0c, 0x08, 0x18, 0x18, 0x18, 0x30,
+    0x30, 0x20, 0x20, 0x61, 0x41, 0xc3, 0xc3, 0xc3,
+    0x87, 0x87, 0x07, 0x07, 0x0f, 0x0e, 0x1e, 0x1c,
+    0x1c, 0x3c, 0x38, 0x38, 0x38, 0x79, 0x71, 0xf3,
+    0xe3, 0xe3, 0xe6, 0xc6, 0xc4, 0xc4, 0xcc, 0x89,
+    0x99, 0x1b, 0x1b, 0x33, 0x36, 0x26, 0x24, 0x65,
+    0x4d, 0xcb, 0xdb, 0xdb, 0x9f, 0xb7, 0x37, 0x27,
+    0x2f, 0x6f, 0x5f, 0xdf, 0xdf, 0xff, 0xbf, 0xbf,
+    0x3f, 0x7e, 0x7e, 0xfd, 0xfd, 0xff, 0xfa, 0xfa,
+    0xfc, 0xfc, 0xf4, 0xf0, 0xe8, 0xe8, 0xf8, 0xd0,
+    0xd0, 0xe0, 0xe0, 0xa1, 0x81, 0x42, 0x42, 0xc0,
+    0x84, 0x84, 0x01, 0x01, 0x0b, 0x0a, 0x12, 0x14,
+    0x04, 0x24, 0x20, 0x08, 0x08, 0x59, 0x51, 0x92,
+    0xa2, 0x20, 0x25, 0x05, 0x43, 0x43, 0xcb, 0x8e,
+    0x96, 0x15, 0x05, 0x2f, 0x2a, 0x1a, 0x1c, 0x5d,
+    0x75, 0xb2, 0xaa, 0x28, 0x7c, 0x54, 0xd1, 0xe1,
+    0xeb, 0xab, 0x93, 0x56, 0x46, 0xe4, 0xa4, 0x8c,
+    0x09, 0x58, 0x5a, 0x98, 0xb0, 0x34, 0x21, 0x21,
+    0x63, 0x4b, 0xc3, 0xd7, 0xc7, 0x87, 0xa7, 0x0f,
+    0x0f, 0x1f, 0x5f, 0x1e, 0xbe, 0x3c, 0x3c, 0x3d,
+    0x79, 0x7b, 0xfb, 0xfb, 0xf7, 0xf6, 0xe6, 0xe4,
+    0xec, 0xcc, 0xd8, 0xd8, 0xd8, 0xb9, 0xb1, 0x33,
+    0x23, 0x62, 0x67, 0xc5, 0xc7, 0xc7, 0xca, 0x8f,
+    0x9d, 0x1f, 0x17, 0x3b, 0x2e, 0x3e, 0x3c, 0x55,
+    0x7d, 0xeb, 0xfb, 0xba, 0xde, 0x74, 0xf4, 0xe4,
+    0xa8, 0xe8, 0x58, 0xd8, 0xd0, 0xf1, 0xa1, 0xa3,
+    0x23, 0x42, 0x46, 0xc5, 0xc5, 0x86, 0x8b, 0x09,
+    0x1f, 0x1f, 0x12, 0x36, 0x2c, 0x2c, 0x34, 0x59,
+    0x49, 0xfb, 0xfb, 0x92, 0xb7, 0x64, 0x66, 0xa5,
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+pub static NOISE_TRIANGLE_OSC3: [u8; 32] = [
+    0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+pub static NOISE_PULSE_OSC3: [u8; 32] = [
+    0x00, 0x6c, 0x49, 0x90, 0x92, 0x49, 0x21, 0xb7,
+    0x02, 0x02, 0x64, 0x4d, 0x84, 0x92, 0x49, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
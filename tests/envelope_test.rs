@@ -1,10 +1,18 @@
-use resid::envelope::EnvelopeGenerator;
+use resid::envelope::{EnvelopeGenerator, State};
 
 static RESID_OUTPUT: &'static [u8] = include_bytes!("data/envelope_output.dat");
 
+// The reference output is from reSID 0.16, which steps the envelope without
+// pipeline delays.
+fn resid_0_16() -> EnvelopeGenerator {
+    let mut envelope = EnvelopeGenerator::default();
+    envelope.set_delays(false);
+    envelope
+}
+
 #[test]
 fn clock() {
-    let mut envelope = EnvelopeGenerator::default();
+    let mut envelope = resid_0_16();
     let mut cycles = 0u32;
     // setup
     envelope.set_attack_decay(0x02 << 4 | 0x00);
@@ -109,11 +117,11 @@ fn clock() {
 }
 
 fn clock_delta() {
-    let mut envelope = EnvelopeGenerator::default();
+    let mut envelope = resid_0_16();
     envelope.set_attack_decay(0x02 << 4 | 0x00);
     envelope.set_sustain_release(0x02 << 4 | 0x01);
     envelope.set_control(0x01);
-    let mut envelope2 = EnvelopeGenerator::default();
+    let mut envelope2 = resid_0_16();
     envelope2.set_attack_decay(0x02 << 4 | 0x00);
     envelope2.set_sustain_release(0x02 << 4 | 0x01);
     envelope2.set_control(0x01);
@@ -132,7 +140,7 @@ fn clock_delta() {
 
 #[test]
 fn resid_output() {
-    let mut envelope = EnvelopeGenerator::default();
+    let mut envelope = resid_0_16();
     envelope.reset();
     // setup
     envelope.set_attack_decay(0x02 << 4 | 0x00);
@@ -156,3 +164,179 @@ fn resid_output() {
     assert_eq!(buffer.len(), RESID_OUTPUT.len());
     assert_eq!(&*buffer, RESID_OUTPUT);
 }
+
+#[test]
+fn clock_delta_delays() {
+    // The pipeline delays are the same for 1 cycle and delta cycle clocking,
+    // also with gate changes while they are in progress.
+    let mut envelope = EnvelopeGenerator::default();
+    let mut envelope2 = EnvelopeGenerator::default();
+    for envelope in [&mut envelope, &mut envelope2].iter_mut() {
+        envelope.set_attack_decay(0x21);
+        envelope.set_sustain_release(0x81);
+        envelope.set_control(0x01);
+    }
+    let mut delta = 1;
+    for i in 0..2000 {
+        for _j in 0..delta {
+            envelope.clock();
+        }
+        envelope2.clock_delta(delta);
+        assert_eq!(envelope2.output(), envelope.output(), "step {}", i);
+        assert_eq!(envelope2.read_env(), envelope.read_env(), "step {}", i);
+        if i % 97 == 0 {
+            let control = envelope.get_control() ^ 0x01;
+            envelope.set_control(control);
+            envelope2.set_control(control);
+        }
+        delta = delta % 61 + 1;
+    }
+}
+
+#[test]
+fn gate_on_decay_rate() {
+    // The decay rate is enabled during the first cycle after gate on, and the
+    // attack state on the second. The envelope is stepped two cycles after
+    // the rate counter has been reset.
+    let mut envelope = EnvelopeGenerator::default();
+    envelope.set_attack_decay(0x10);
+    envelope.rate_counter = 8;
+    envelope.set_control(0x01);
+    envelope.clock();
+    assert_eq!(envelope.state, State::Release);
+    assert!(envelope.reset_rate_counter);
+    envelope.clock();
+    assert_eq!(envelope.state, State::Attack);
+    assert_eq!(envelope.rate_counter, 1);
+    envelope.clock();
+    assert_eq!(envelope.output(), 0x00);
+    envelope.clock();
+    assert_eq!(envelope.output(), 0x01);
+}
+
+#[test]
+fn gate_off_delay() {
+    let mut envelope = EnvelopeGenerator::default();
+    envelope.set_attack_decay(0x00);
+    envelope.set_sustain_release(0xf0);
+    envelope.set_control(0x01);
+    for _i in 0..100 {
+        envelope.clock();
+    }
+    while envelope.envelope_pipeline != 0 || envelope.reset_rate_counter {
+        envelope.clock();
+    }
+    // Attack to release takes two cycles.
+    envelope.set_control(0x00);
+    envelope.clock();
+    assert_eq!(envelope.state, State::Attack);
+    envelope.clock();
+    assert_eq!(envelope.state, State::Release);
+    // A pending envelope step delays the release by one more cycle.
+    envelope.set_control(0x01);
+    while envelope.envelope_pipeline == 0 {
+        envelope.clock();
+    }
+    let output = envelope.output();
+    envelope.set_control(0x00);
+    envelope.clock();
+    envelope.clock();
+    assert_eq!(envelope.state, State::Attack);
+    envelope.clock();
+    assert_eq!(envelope.state, State::Release);
+    assert_eq!(envelope.output(), output + 1);
+    // Decay/sustain to release takes one cycle.
+    envelope.set_control(0x01);
+    for _i in 0..3000 {
+        envelope.clock();
+    }
+    assert_eq!(envelope.state, State::DecaySustain);
+    envelope.set_control(0x00);
+    envelope.clock();
+    assert_eq!(envelope.state, State::Release);
+}
+
+#[test]
+fn attack_to_decay() {
+    // The decay state is entered on the envelope step to 0xff.
+    let mut envelope = EnvelopeGenerator::default();
+    envelope.set_attack_decay(0x00);
+    envelope.set_sustain_release(0xf0);
+    envelope.set_control(0x01);
+    envelope.clock();
+    envelope.clock();
+    while envelope.output() != 0xff {
+        assert_eq!(envelope.state, State::Attack);
+        envelope.clock();
+    }
+    assert_eq!(envelope.state, State::DecaySustain);
+}
+
+#[test]
+fn decrement_delay() {
+    let mut envelope = EnvelopeGenerator::default();
+    envelope.set_attack_decay(0x00);
+    envelope.set_sustain_release(0x00);
+    envelope.set_control(0x01);
+    while envelope.output() != 0xff {
+        envelope.clock();
+    }
+    // Decay down to the exponential counter period 2 threshold.
+    while envelope.output() != 0x5d {
+        envelope.clock();
+    }
+    // The exponential counter period changes one cycle later.
+    assert_eq!(envelope.exponential_counter_period, 1);
+    envelope.clock();
+    assert_eq!(envelope.exponential_counter_period, 2);
+    // The exponential counter is reset two cycles after the rate counter, and
+    // the envelope is decremented one cycle after that.
+    while envelope.envelope_pipeline == 0 {
+        envelope.clock();
+    }
+    assert_eq!(envelope.output(), 0x5d);
+    envelope.clock();
+    assert_eq!(envelope.output(), 0x5c);
+}
+
+#[test]
+fn env3_delay() {
+    // ENV3 is sampled one cycle before the envelope counter is updated.
+    let mut envelope = EnvelopeGenerator::default();
+    envelope.set_attack_decay(0x00);
+    envelope.set_control(0x01);
+    let mut last_output = envelope.output();
+    for _i in 0..1000 {
+        envelope.clock();
+        assert_eq!(envelope.read_env(), last_output);
+        last_output = envelope.output();
+    }
+}
+
+#[test]
+fn attack_counter_flip() {
+    // Retriggering the attack with the envelope counter at 0xff flips the
+    // counter to zero, where it is frozen.
+    for &single_cycle in [true, false].iter() {
+        let mut envelope = EnvelopeGenerator::default();
+        envelope.set_attack_decay(0x00);
+        envelope.set_sustain_release(0xf0);
+        envelope.set_control(0x01);
+        for _i in 0..3000 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.state, State::DecaySustain);
+        assert_eq!(envelope.output(), 0xff);
+        envelope.set_control(0x00);
+        envelope.set_control(0x01);
+        if single_cycle {
+            for _i in 0..1000 {
+                envelope.clock();
+            }
+        } else {
+            envelope.clock_delta(1000);
+        }
+        assert_eq!(envelope.state, State::Attack);
+        assert_eq!(envelope.output(), 0x00);
+    }
+}
@@ -1,4 +1,4 @@
-use resid::external_filter::ExternalFilter;
+use resid::external_filter::{ExternalFilter, OutputStage, RcNetwork};
 use resid::ChipModel;
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
@@ -48,3 +48,88 @@ fn clock_delta() {
         index += 1;
     }
 }
+
+#[test]
+fn output_stage_c64() {
+    let mut ext_filter = ExternalFilter::new(ChipModel::Mos6581);
+    ext_filter.set_output_stage(OutputStage::c64());
+    let mut index = 0usize;
+    let mut vi = -1000;
+    while vi <= 1000 {
+        ext_filter.clock(vi);
+        assert_eq!(ext_filter.output(), RESID_OUTPUT[index]);
+        vi += 50;
+        index += 1;
+    }
+    let stage = OutputStage::c64();
+    assert!((stage.low_pass.unwrap().cutoff_hz() - 15915.5).abs() < 0.1);
+    assert!((stage.high_pass.cutoff_hz() - 15.9155).abs() < 0.001);
+}
+
+#[test]
+fn output_stage_dc_only() {
+    let mut ext_filter = ExternalFilter::new(ChipModel::Mos6581);
+    ext_filter.set_output_stage(OutputStage::dc_only());
+    // Without the low-pass stage the input is passed through after one cycle.
+    ext_filter.clock(100_000);
+    ext_filter.clock(100_000);
+    assert_eq!(ext_filter.output(), 100_000);
+    // The DC level is removed.
+    ext_filter.clock_delta(1_000_000, 100_000);
+    assert!(ext_filter.output().abs() < 10_000);
+}
+
+#[test]
+fn output_stage_switch() {
+    let mut ext_filter = ExternalFilter::new(ChipModel::Mos6581);
+    ext_filter.clock_delta(100, 1000);
+    let output = ext_filter.output();
+    // Switching the output stage retains the filter state.
+    ext_filter.set_output_stage(OutputStage::new(
+        Some(RcNetwork::new(10e3, 470e-12)),
+        RcNetwork::new(1e3, 10e-6),
+    ));
+    assert_eq!(ext_filter.output(), output);
+    assert_eq!(
+        ext_filter.get_output_stage().low_pass,
+        Some(RcNetwork::new(10e3, 470e-12))
+    );
+}
+
+#[test]
+fn output_stage_presets() {
+    let stage = OutputStage::sid_card();
+    assert_eq!(stage.low_pass, None);
+    assert!((stage.high_pass.cutoff_hz() - 15.9155).abs() < 0.001);
+}
+
+#[test]
+fn output_stage_high_pass_limit() {
+    // A 1.6kHz high-pass stage is limited to approximately 40Hz, and must
+    // not overflow at full scale.
+    let mut ext_filter = ExternalFilter::new(ChipModel::Mos6581);
+    ext_filter.set_output_stage(OutputStage::new(None, RcNetwork::new(100.0, 1e-6)));
+    for i in 0..10_000 {
+        let vi = if i & 0x100 != 0 { 1 << 19 } else { -(1 << 19) };
+        ext_filter.clock_delta(8, vi);
+    }
+    ext_filter.clock_delta(1_000_000, 1 << 19);
+    assert!(ext_filter.output().abs() < 1 << 10);
+}
+
+#[test]
+fn output_stage_low_pass_limit() {
+    // A 20kHz low-pass stage must not overflow at full scale.
+    let mut ext_filter = ExternalFilter::new(ChipModel::Mos6581);
+    ext_filter.set_output_stage(OutputStage::new(
+        Some(RcNetwork::new(1e3, 7.96e-9)),
+        RcNetwork::new(1e3, 10e-6),
+    ));
+    let mut max = 0;
+    for i in 0..10_000 {
+        let vi = if i & 0x10 != 0 { 1 << 19 } else { -(1 << 19) };
+        ext_filter.clock_delta(8, vi);
+        max = max.max(ext_filter.output().abs());
+    }
+    assert!(max <= 1 << 20);
+}
@@ -0,0 +1,111 @@
+use resid::filter_effect::{FilterEffect, MODE_BP, MODE_HP, MODE_LP};
+use resid::{ChipModel, Error};
+
+const SAMPLE_FREQ: u32 = 44100;
+
+fn sine(freq: f64, samples: usize) -> Vec<i16> {
+    (0..samples)
+        .map(|i| {
+            let t = i as f64 / SAMPLE_FREQ as f64;
+            (8000.0 * (2.0 * std::f64::consts::PI * freq * t).sin()) as i16
+        })
+        .collect()
+}
+
+fn peak(buffer: &[i16]) -> i32 {
+    buffer.iter().map(|&x| (x as i32).abs()).max().unwrap()
+}
+
+#[test]
+fn lowpass_dc() {
+    let mut effect = FilterEffect::new(ChipModel::Mos8580, SAMPLE_FREQ).unwrap();
+    effect.set_cutoff(0x400);
+    effect.set_mode(MODE_LP);
+    let mut buffer = [4000i16; 2048];
+    effect.process_buffer(&mut buffer);
+    assert!((buffer[2047] as i32 - 4000).abs() < 40);
+}
+
+#[test]
+fn highpass_dc() {
+    let mut effect = FilterEffect::new(ChipModel::Mos8580, SAMPLE_FREQ).unwrap();
+    effect.set_cutoff(0x400);
+    effect.set_mode(MODE_HP);
+    let mut buffer = [4000i16; 2048];
+    effect.process_buffer(&mut buffer);
+    assert!((buffer[2047] as i32).abs() < 40);
+}
+
+#[test]
+fn lowpass_attenuation() {
+    let mut effect = FilterEffect::new(ChipModel::Mos8580, SAMPLE_FREQ).unwrap();
+    effect.set_cutoff_hz(500.0);
+    effect.set_mode(MODE_LP);
+    let mut low = sine(100.0, 4410);
+    let mut high = sine(5000.0, 4410);
+    effect.process_buffer(&mut low);
+    effect.reset();
+    effect.process_buffer(&mut high);
+    assert!(peak(&low[2205..]) > 7000);
+    assert!(peak(&high[2205..]) < 400);
+}
+
+#[test]
+fn bandpass_resonance() {
+    let mut effect = FilterEffect::new(ChipModel::Mos6581, 48000).unwrap();
+    effect.set_cutoff_hz(1000.0);
+    effect.set_mode(MODE_BP);
+    let mut buffer = vec![0i16; 4800];
+    effect.set_resonance(0);
+    let input = (0..4800)
+        .map(|i| {
+            (8000.0
+                * (2.0 * std::f64::consts::PI * effect.get_cutoff_hz() * i as f64 / 48000.0).sin())
+                as i16
+        })
+        .collect::<Vec<i16>>();
+    buffer.copy_from_slice(&input);
+    effect.process_buffer(&mut buffer);
+    let peak_res_0 = peak(&buffer[2400..]);
+    effect.reset();
+    effect.set_resonance(15);
+    buffer.copy_from_slice(&input);
+    effect.process_buffer(&mut buffer);
+    let peak_res_15 = peak(&buffer[2400..]);
+    assert!(peak_res_15 > peak_res_0 * 2);
+}
+
+#[test]
+fn cutoff_hz() {
+    let mut effect = FilterEffect::new(ChipModel::Mos8580, SAMPLE_FREQ).unwrap();
+    effect.set_cutoff_hz(6500.0);
+    assert_eq!(effect.get_cutoff(), 1024);
+    effect.set_cutoff_hz(100_000.0);
+    assert_eq!(effect.get_cutoff(), 2047);
+}
+
+#[test]
+fn sample_freq() {
+    assert_eq!(
+        FilterEffect::new(ChipModel::Mos8580, 0).err(),
+        Some(Error::InvalidSampleFrequency(0))
+    );
+    assert_eq!(
+        FilterEffect::new(ChipModel::Mos8580, 15).err(),
+        Some(Error::InvalidSampleFrequency(15))
+    );
+    assert_eq!(
+        FilterEffect::new(ChipModel::Mos8580, 1_000_001).err(),
+        Some(Error::SampleFrequencyAboveClock {
+            clock_freq: 1_000_000,
+            sample_freq: 1_000_001,
+        })
+    );
+    let mut effect = FilterEffect::new(ChipModel::Mos8580, 16).unwrap();
+    assert!(effect.set_sample_freq(0).is_err());
+    for _i in 0..100 {
+        effect.process(1000);
+    }
+    effect.set_sample_freq(1_000_000).unwrap();
+    effect.process(1000);
+}
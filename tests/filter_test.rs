@@ -1,10 +1,20 @@
-use resid::{ChipModel, Sid};
+use resid::filter::Filter;
+use resid::{ChipModel, ChipProfile, Sid};
 
 const CPU_FREQ: u32 = 985248;
 const SAMPLE_FREQ: u32 = 44100;
 const SAMPLE_COUNT: usize = 128;
 const CYCLES_PER_SAMPLE: u32 = CPU_FREQ / SAMPLE_FREQ;
 
+// The reference output is from reSID 0.16, which steps the envelope without
+// pipeline delays.
+fn resid_0_16() -> Sid {
+    Sid::with_profile(&ChipProfile {
+        envelope_delays: false,
+        ..ChipProfile::new(ChipModel::Mos6581)
+    })
+}
+
 fn dump(sid: &mut Sid, _name: &str, samples: usize) -> Vec<i16> {
     let mut buffer = vec![0; samples];
     let _ = sid.sample(samples as u32 * CYCLES_PER_SAMPLE, &mut buffer, 1);
@@ -40,7 +50,7 @@ fn filter_off() {
         -14222, -14222, -14222, -14222, -17188, -17188, -17188, -17188, -17188, -17188, -17188,
         -17188, -17188, -17188, -17188, -17188, 0, 0,
     ];
-    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid = resid_0_16();
     sid.enable_external_filter(false);
     sid.enable_filter(false);
     setup(&mut sid, 0, 4, 0x19b1, 0x0200, 4);
@@ -66,7 +76,7 @@ fn filter_on() {
         -14222, -14222, -14222, -14222, -17188, -17188, -17188, -17188, -17188, -17188, -17188,
         -17188, -17188, -17188, -17188, -17188, 0, 0,
     ];
-    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid = resid_0_16();
     sid.enable_external_filter(false);
     sid.enable_filter(true);
     setup(&mut sid, 0, 4, 0x19b1, 0x0200, 4);
@@ -91,7 +101,7 @@ fn filter_vol_8() {
         -2984, -2984, -2984, -2984, -2984, -2984, -2984, -8917, -8917, -8917, -8917, -8917, -8917,
         -8917, -8917, -8917, -8917, -8917, -8917, 0, 0,
     ];
-    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid = resid_0_16();
     sid.enable_external_filter(false);
     sid.enable_filter(true);
     setup(&mut sid, 0, 4, 0x19b1, 0x0200, 4);
@@ -118,7 +128,7 @@ fn filter_voice3_off() {
         -18023, -18023, -18023, -18023, -20989, -20989, -20989, -20989, -20989, -20989, -20989,
         -20989, -20989, -20989, -20989, -20989, 0, 0,
     ];
-    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid = resid_0_16();
     sid.enable_external_filter(false);
     sid.enable_filter(true);
     setup(&mut sid, 0, 4, 0x19b1, 0x0200, 4);
@@ -145,7 +155,7 @@ fn filter_hp_lp_lp() {
         -14222, -14222, -14222, -14222, -17188, -17188, -17188, -17188, -17188, -17188, -17188,
         -17188, -17188, -17188, -17188, -17188, 0, 0,
     ];
-    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid = resid_0_16();
     sid.enable_external_filter(false);
     sid.enable_filter(true);
     setup(&mut sid, 0, 4, 0x19b1, 0x0200, 4);
@@ -172,7 +182,7 @@ fn filter_filt_15() {
         -30952, -31001, -31056, -31112, -28342, -28625, -28888, -29144, -29407, -29651, -29888,
         -30130, -30355, -30574, -30796, -31003, 0, 0,
     ];
-    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid = resid_0_16();
     sid.enable_external_filter(false);
     sid.enable_filter(true);
     setup(&mut sid, 0, 4, 0x19b1, 0x0200, 4);
@@ -200,7 +210,7 @@ fn filter_res_15() {
         -14222, -14222, -14222, -14222, -17188, -17188, -17188, -17188, -17188, -17188, -17188,
         -17188, -17188, -17188, -17188, -17188, 0, 0,
     ];
-    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid = resid_0_16();
     sid.enable_external_filter(false);
     sid.enable_filter(true);
     setup(&mut sid, 0, 4, 0x19b1, 0x0200, 4);
@@ -228,7 +238,7 @@ fn filter_fc_255() {
         -14222, -14222, -14222, -14222, -17188, -17188, -17188, -17188, -17188, -17188, -17188,
         -17188, -17188, -17188, -17188, -17188, 0, 0,
     ];
-    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid = resid_0_16();
     sid.enable_external_filter(false);
     sid.enable_filter(true);
     setup(&mut sid, 0, 4, 0x19b1, 0x0200, 4);
@@ -256,7 +266,7 @@ fn filter_fc_res_filt() {
         -32708, -32768, -32768, -32768, -30234, -30490, -30733, -30975, -31226, -31465, -31702,
         -31948, -32181, -32411, -32650, -32768, 0, 0,
     ];
-    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid = resid_0_16();
     sid.enable_external_filter(false);
     sid.enable_filter(true);
     setup(&mut sid, 0, 4, 0x19b1, 0x0200, 4);
@@ -268,3 +278,48 @@ fn filter_fc_res_filt() {
     let res = dump(&mut sid, "filter_fc_res_filt", SAMPLE_COUNT);
     assert_eq!(res, expected);
 }
+
+#[test]
+fn cutoff_hz() {
+    let filter = Filter::new(ChipModel::Mos6581);
+    assert_eq!(filter.cutoff_hz(0), 220.0);
+    assert_eq!(filter.cutoff_hz(1024), 4600.0);
+    assert_eq!(filter.cutoff_hz(1023), 6000.0);
+    let filter = Filter::new(ChipModel::Mos8580);
+    assert_eq!(filter.cutoff_hz(0), 0.0);
+    assert_eq!(filter.cutoff_hz(1024), 6500.0);
+    assert_eq!(filter.cutoff_hz(128), 800.0);
+}
+
+#[test]
+fn q() {
+    let filter = Filter::new(ChipModel::Mos6581);
+    assert!((filter.q(0) - 0.707).abs() < 1e-9);
+    assert!((filter.q(15) - 1.707).abs() < 1e-9);
+}
+
+#[test]
+fn frequency_response() {
+    let filter = Filter::new(ChipModel::Mos8580);
+    let fc = 1024;
+    let f0 = filter.cutoff_hz(fc);
+    let freqs = [1.0, f0, 400_000.0];
+    // Lowpass passes DC (inverted) and attenuates high frequencies.
+    let lp = filter.frequency_response(fc, 0, 0x1, &freqs);
+    assert!((lp[0].0 - 1.0).abs() < 1e-3);
+    assert!((lp[0].1.abs() - core::f64::consts::PI).abs() < 1e-2);
+    assert!(lp[2].0 < 0.01);
+    // Highpass blocks DC and passes high frequencies.
+    let hp = filter.frequency_response(fc, 0, 0x4, &freqs);
+    assert!(hp[0].0 < 1e-3);
+    assert!((hp[2].0 - 1.0).abs() < 0.05);
+    // Bandpass peaks at approximately Q at the cutoff frequency.
+    for res in [0u8, 8, 15].iter() {
+        let bp = filter.frequency_response(fc, *res, 0x2, &freqs);
+        assert!((bp[1].0 / filter.q(*res) - 1.0).abs() < 0.1);
+        assert!(bp[1].1.abs() < 1e-2);
+    }
+    // No mode selected yields silence.
+    let none = filter.frequency_response(fc, 0, 0x0, &freqs);
+    assert!(none.iter().all(|&(mag, _)| mag == 0.0));
+}
@@ -0,0 +1,254 @@
+use resid::instrument::{note_to_hz, Instrument, NotePlayer};
+use resid::registers::{AttackDecay, Control, SustainRelease, Waveform};
+use resid::{ChipModel, Clock, Error, Sid};
+
+fn player() -> NotePlayer {
+    NotePlayer::new(Sid::new(ChipModel::Mos6581), Clock::Pal)
+}
+
+#[test]
+fn note_frequencies() {
+    assert_eq!(note_to_hz(69), 440.0);
+    assert_eq!(note_to_hz(81), 880.0);
+    assert!((note_to_hz(60) - 261.626).abs() < 0.001);
+    let mut player = player();
+    player.note_on(69, &Instrument::default());
+    assert_eq!(
+        player.get_sid_mut().voice(0).unwrap().get_frequency(),
+        0x1d45
+    );
+}
+
+#[test]
+fn envelope_rates() {
+    let instrument = Instrument {
+        attack_ms: 0.0,
+        decay_ms: 750.0,
+        sustain: 0x0a,
+        release_ms: 24000.0,
+        ..Instrument::default()
+    };
+    assert_eq!(
+        instrument.attack_decay(Clock::Pal),
+        AttackDecay {
+            attack: 0,
+            decay: 9
+        }
+    );
+    assert_eq!(
+        instrument.sustain_release(Clock::Pal),
+        SustainRelease {
+            sustain: 0x0a,
+            release: 15
+        }
+    );
+    // The nearest rate for a time between 250ms and 500ms.
+    let instrument = Instrument {
+        attack_ms: 300.0,
+        ..Instrument::default()
+    };
+    assert_eq!(instrument.attack_decay(Clock::Pal).attack, 9);
+    let instrument = Instrument {
+        attack_ms: 450.0,
+        ..Instrument::default()
+    };
+    assert_eq!(instrument.attack_decay(Clock::Pal).attack, 10);
+}
+
+#[test]
+fn voice_allocation() {
+    let mut player = player();
+    let instrument = Instrument::default();
+    assert_eq!(player.note_on(60, &instrument), 0);
+    assert_eq!(player.note_on(64, &instrument), 1);
+    assert_eq!(player.note_on(67, &instrument), 2);
+    // Retrigger the voice playing the note.
+    assert_eq!(player.note_on(64, &instrument), 1);
+    // Steal the oldest note.
+    assert_eq!(player.note_on(72, &instrument), 0);
+    // Reuse the voice released the longest ago.
+    player.note_off(67);
+    player.note_off(64);
+    assert_eq!(player.note_on(76, &instrument), 2);
+    assert_eq!(player.note_on(79, &instrument), 1);
+    assert!(player.get_sid_mut().voice(1).unwrap().get_control().gate);
+    player.all_notes_off();
+    for voice in 0..3 {
+        assert!(
+            !player
+                .get_sid_mut()
+                .voice(voice)
+                .unwrap()
+                .get_control()
+                .gate
+        );
+    }
+}
+
+#[test]
+fn gate() {
+    let mut player = player();
+    player.set_volume(0x0f);
+    let instrument = Instrument {
+        waveform: Waveform::Sawtooth,
+        ..Instrument::default()
+    };
+    let voice = player.note_on(60, &instrument);
+    let control = player.get_sid_mut().voice(voice).unwrap().get_control();
+    assert_eq!(u8::from(control), 0x21);
+    player.clock_delta(10000);
+    assert_eq!(player.get_sid().read_state().envelope_counter[voice], 0xff);
+    player.note_off(60);
+    let control = player.get_sid_mut().voice(voice).unwrap().get_control();
+    assert_eq!(u8::from(control), 0x20);
+}
+
+#[test]
+fn hard_restart() {
+    let mut player = player();
+    let instrument = Instrument {
+        hard_restart: true,
+        ..Instrument::default()
+    };
+    player.note_on(60, &instrument);
+    let control = player.get_sid_mut().voice(0).unwrap().get_control();
+    assert!(control.test);
+    assert!(!control.gate);
+    assert_eq!(
+        player.get_sid_mut().voice(0).unwrap().get_attack_decay(),
+        AttackDecay::default()
+    );
+    // The gate is set once the hard restart is complete.
+    let mut buffer = [0i16; 4096];
+    let (samples, delta) = player.sample(0x8000 - 1, &mut buffer, 1);
+    assert_eq!(delta, 0);
+    assert!(samples > 0);
+    assert!(!player.get_sid_mut().voice(0).unwrap().get_control().gate);
+    player.sample(1, &mut buffer, 1);
+    let control: Control = player.get_sid_mut().voice(0).unwrap().get_control();
+    assert!(control.gate);
+    assert!(!control.test);
+    // The attack and decay rates are written once the state pipeline has
+    // passed the decay rate.
+    assert_eq!(
+        player.get_sid_mut().voice(0).unwrap().get_attack_decay(),
+        AttackDecay::default()
+    );
+    player.sample(2, &mut buffer, 1);
+    assert_eq!(
+        player.get_sid_mut().voice(0).unwrap().get_attack_decay(),
+        instrument.attack_decay(Clock::Pal)
+    );
+    // The attack starts without the ADSR delay bug.
+    player.sample(100, &mut buffer, 1);
+    assert!(player.get_sid().read_state().envelope_counter[0] > 0);
+}
+
+#[test]
+fn hard_restart_cycles() {
+    let mut player = player();
+    player.set_hard_restart_cycles(7000);
+    let instrument = Instrument {
+        hard_restart: true,
+        ..Instrument::default()
+    };
+    player.note_on(60, &instrument);
+    player.clock_delta(6999);
+    assert!(!player.get_sid_mut().voice(0).unwrap().get_control().gate);
+    player.clock_delta(1);
+    assert!(player.get_sid_mut().voice(0).unwrap().get_control().gate);
+}
+
+#[test]
+fn hard_restart_short_note() {
+    let mut player = player();
+    let instrument = Instrument {
+        hard_restart: true,
+        ..Instrument::default()
+    };
+    player.note_on(60, &instrument);
+    player.clock_delta(1000);
+    // Released during the hard restart, the note still plays for as long as
+    // it was held.
+    player.note_off(60);
+    player.clock_delta(0x8000 - 1000);
+    assert!(player.get_sid_mut().voice(0).unwrap().get_control().gate);
+    player.clock_delta(999);
+    assert!(player.get_sid_mut().voice(0).unwrap().get_control().gate);
+    assert!(player.get_sid().read_state().envelope_counter[0] > 0);
+    player.clock_delta(1);
+    assert!(!player.get_sid_mut().voice(0).unwrap().get_control().gate);
+    // The voice is not reused until the note has been released.
+    let mut player = self::player();
+    player.note_on(60, &instrument);
+    player.note_off(60);
+    assert_eq!(player.note_on(64, &Instrument::default()), 1);
+    assert_eq!(player.note_on(67, &Instrument::default()), 2);
+}
+
+#[test]
+fn retrigger() {
+    let mut player = player();
+    let instrument = Instrument::default();
+    assert_eq!(player.note_on(60, &instrument), 0);
+    player.clock_delta(20000);
+    assert_eq!(player.get_sid().read_state().envelope_counter[0], 0xff);
+    // A note on the playing voice is played legato.
+    assert_eq!(player.note_on(60, &instrument), 0);
+    assert!(player.get_sid_mut().voice(0).unwrap().get_control().gate);
+    for _i in 0..10 {
+        player.clock_delta(100);
+        assert_eq!(player.get_sid().read_state().envelope_counter[0], 0xff);
+    }
+}
+
+#[test]
+fn steal_voice() {
+    let mut player = player();
+    let instrument = Instrument::default();
+    for &note in [60, 64, 67].iter() {
+        player.note_on(note, &instrument);
+    }
+    player.clock_delta(20000);
+    // The oldest note is replaced right away.
+    let frequency = player.get_sid_mut().voice(0).unwrap().get_frequency();
+    assert_eq!(player.note_on(72, &instrument), 0);
+    let octave = player.get_sid_mut().voice(0).unwrap().get_frequency();
+    assert!((octave as i32 - frequency as i32 * 2).abs() <= 1);
+    player.clock_delta(20000);
+    assert_eq!(player.get_sid().read_state().envelope_counter[0], 0xff);
+}
+
+#[test]
+fn note_after_release() {
+    let mut player = player();
+    let instrument = Instrument::default();
+    for &note in [60, 64, 67].iter() {
+        player.note_on(note, &instrument);
+    }
+    player.clock_delta(20000);
+    // The voice is released and played again in the same cycle.
+    player.note_off(60);
+    assert_eq!(player.note_on(72, &instrument), 0);
+    assert!(player.get_sid_mut().voice(0).unwrap().get_control().gate);
+    player.clock_delta(20000);
+    assert_eq!(player.get_sid().read_state().envelope_counter[0], 0xff);
+}
+
+#[test]
+fn invalid_voice() {
+    let mut player = player();
+    let instrument = Instrument::default();
+    assert_eq!(
+        player.note_on_voice(3, 440.0, &instrument),
+        Err(Error::InvalidVoice(3))
+    );
+    assert_eq!(
+        player.set_frequency_hz(3, 440.0),
+        Err(Error::InvalidVoice(3))
+    );
+    assert_eq!(player.note_off_voice(3), Err(Error::InvalidVoice(3)));
+    assert_eq!(player.note_on_voice(2, 440.0, &instrument), Ok(()));
+    assert_eq!(player.note_off_voice(2), Ok(()));
+    assert!(player.get_sid_mut().voice(3).is_none());
+}
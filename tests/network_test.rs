@@ -0,0 +1,185 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use resid::network::NetworkSidDevice;
+use resid::{ChipModel, Error, Sid};
+
+const TRY_SET_SID_COUNT: u8 = 1;
+const TRY_RESET: u8 = 3;
+const TRY_DELAY: u8 = 4;
+const TRY_WRITE: u8 = 5;
+const TRY_READ: u8 = 6;
+const GET_VERSION: u8 = 7;
+const SET_CLOCKING: u8 = 9;
+const GET_CONFIG_COUNT: u8 = 10;
+const GET_CONFIG_INFO: u8 = 11;
+const TRY_SET_SID_MODEL: u8 = 14;
+
+const OK: u8 = 0;
+const BUSY: u8 = 1;
+const ERR: u8 = 2;
+const READ: u8 = 3;
+
+fn request(command: u8, sid: u8, data: &[u8]) -> Vec<u8> {
+    let mut request = vec![command, sid, (data.len() >> 8) as u8, data.len() as u8];
+    request.extend_from_slice(data);
+    request
+}
+
+fn process(device: &mut NetworkSidDevice, command: u8, sid: u8, data: &[u8]) -> Vec<u8> {
+    let mut response = Vec::new();
+    device.process(command, sid, data, &mut response);
+    response
+}
+
+#[test]
+fn config() {
+    let mut device = NetworkSidDevice::new(44100, 0x10000).unwrap();
+    assert_eq!(process(&mut device, GET_VERSION, 0, &[]), vec![4, 4]);
+    assert_eq!(process(&mut device, GET_CONFIG_COUNT, 0, &[]), vec![5, 2]);
+    let info = process(&mut device, GET_CONFIG_INFO, 1, &[]);
+    assert_eq!(&info[..2], &[6, 1]);
+    assert_eq!(&info[2..], b"resid-rs MOS8580\0");
+    assert_eq!(process(&mut device, GET_CONFIG_INFO, 2, &[]), vec![ERR]);
+    assert_eq!(process(&mut device, TRY_SET_SID_COUNT, 3, &[]), vec![OK]);
+    assert_eq!(device.get_sids().len(), 3);
+    assert_eq!(process(&mut device, TRY_SET_SID_COUNT, 9, &[]), vec![ERR]);
+    assert_eq!(device.get_sids().len(), 3);
+    assert_eq!(process(&mut device, TRY_SET_SID_MODEL, 2, &[1]), vec![OK]);
+    assert_eq!(process(&mut device, TRY_SET_SID_MODEL, 3, &[1]), vec![ERR]);
+    assert_eq!(process(&mut device, SET_CLOCKING, 0, &[1]), vec![OK]);
+    assert_eq!(process(&mut device, 0x7f, 0, &[]), vec![ERR]);
+}
+
+#[test]
+fn invalid_sample_freq() {
+    assert_eq!(
+        NetworkSidDevice::new(0, 0x10000).err(),
+        Some(Error::InvalidSampleFrequency(0))
+    );
+}
+
+#[test]
+fn cycle_exact_writes() {
+    // Writes with cycle deltas produce the same output as clocking a SID
+    // directly.
+    let mut device = NetworkSidDevice::new(44100, 0x10000).unwrap();
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    let writes: [(u16, u8, u8); 4] = [
+        (10, 0x18, 0x0f),
+        (100, 0x01, 0x20),
+        (7, 0x05, 0x09),
+        (1000, 0x04, 0x11),
+    ];
+    let mut data = Vec::new();
+    let mut expected = Vec::new();
+    let mut buffer = [0i16; 1024];
+    for &(cycles, reg, value) in writes.iter() {
+        data.extend_from_slice(&[(cycles >> 8) as u8, cycles as u8, reg, value]);
+        let (samples, _next_delta) = sid.sample(cycles as u32, &mut buffer, 1);
+        expected.extend_from_slice(&buffer[..samples]);
+        sid.write(reg, value);
+    }
+    assert_eq!(process(&mut device, TRY_WRITE, 0, &data), vec![OK]);
+    assert_eq!(process(&mut device, TRY_DELAY, 0, &[0x4e, 0x20]), vec![OK]);
+    let (samples, _next_delta) = sid.sample(20000, &mut buffer, 1);
+    expected.extend_from_slice(&buffer[..samples]);
+    assert_eq!(device.take_samples(), expected);
+    assert_eq!(device.get_sids()[0].read_state().sid_register[0x04], 0x11);
+}
+
+#[test]
+fn multi_sid_writes() {
+    let mut device = NetworkSidDevice::new(44100, 0x10000).unwrap();
+    assert_eq!(process(&mut device, TRY_SET_SID_COUNT, 2, &[]), vec![OK]);
+    // The chip is selected by the upper bits of the register.
+    let data = [0x00, 0x01, 0x18, 0x0f, 0x00, 0x01, 0x20 | 0x18, 0x05];
+    assert_eq!(process(&mut device, TRY_WRITE, 0, &data), vec![OK]);
+    assert_eq!(device.get_sids()[0].read_state().sid_register[0x18], 0x0f);
+    assert_eq!(device.get_sids()[1].read_state().sid_register[0x18], 0x05);
+    let data = [0x00, 0x01, 0x40 | 0x18, 0x05];
+    assert_eq!(process(&mut device, TRY_WRITE, 0, &data), vec![ERR]);
+    assert_eq!(process(&mut device, TRY_WRITE, 0, &data[..3]), vec![ERR]);
+}
+
+#[test]
+fn read_and_reset() {
+    let mut device = NetworkSidDevice::new(44100, 0x10000).unwrap();
+    // Start voice 3 noise, then read OSC3.
+    let data = [
+        0x00, 0x01, 0x0f, 0xff, 0x00, 0x01, 0x12, 0x80, 0x01, 0x00, 0x1b,
+    ];
+    let response = process(&mut device, TRY_READ, 0, &data);
+    assert_eq!(response[0], READ);
+    assert_eq!(response[1], device.get_sids()[0].read(0x1b));
+    assert_eq!(process(&mut device, TRY_RESET, 0, &[0x0a]), vec![OK]);
+    let registers = device.get_sids()[0].read_state().sid_register;
+    assert_eq!(registers[0x12], 0x00);
+    assert_eq!(registers[0x18], 0x0a);
+}
+
+#[test]
+fn busy() {
+    let mut device = NetworkSidDevice::new(44100, 100).unwrap();
+    assert_eq!(process(&mut device, TRY_DELAY, 0, &[0x27, 0x10]), vec![OK]);
+    assert_eq!(
+        process(&mut device, TRY_DELAY, 0, &[0x27, 0x10]),
+        vec![BUSY]
+    );
+    assert_eq!(
+        process(&mut device, TRY_WRITE, 0, &[0, 1, 0x18, 0x0f]),
+        vec![BUSY]
+    );
+    assert!(device.take_samples().len() >= 100);
+    assert_eq!(
+        process(&mut device, TRY_WRITE, 0, &[0, 1, 0x18, 0x0f]),
+        vec![OK]
+    );
+}
+
+#[test]
+fn loopback() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        // A buffer smaller than the output of one delay.
+        let mut device = NetworkSidDevice::new(44100, 100).unwrap();
+        let (stream, _addr) = listener.accept().unwrap();
+        let mut samples = 0;
+        device
+            .serve(stream, |output| samples += output.len())
+            .unwrap();
+        // The SID is not Send.
+        let registers = device.get_sids()[0].read_state().sid_register;
+        (registers, samples)
+    });
+    let mut client = TcpStream::connect(addr).unwrap();
+    let mut response = [0u8; 2];
+    client.write_all(&request(GET_VERSION, 0, &[])).unwrap();
+    client.read_exact(&mut response).unwrap();
+    assert_eq!(response, [4, 4]);
+    client
+        .write_all(&request(TRY_SET_SID_MODEL, 0, &[1]))
+        .unwrap();
+    client.read_exact(&mut response[..1]).unwrap();
+    assert_eq!(response[0], OK);
+    let writes = [0x00, 0x10, 0x18, 0x0f, 0x03, 0xe8, 0x01, 0x10];
+    client.write_all(&request(TRY_WRITE, 0, &writes)).unwrap();
+    client.read_exact(&mut response[..1]).unwrap();
+    assert_eq!(response[0], OK);
+    // The output is passed to the sink, so the buffer never fills up.
+    for _i in 0..10 {
+        client
+            .write_all(&request(TRY_DELAY, 0, &[0x27, 0x10]))
+            .unwrap();
+        client.read_exact(&mut response[..1]).unwrap();
+        assert_eq!(response[0], OK);
+    }
+    drop(client);
+    let (registers, samples) = server.join().unwrap();
+    assert_eq!(registers[0x18], 0x0f);
+    assert_eq!(registers[0x01], 0x10);
+    // 101016 cycles at 44.1kHz.
+    assert_eq!(samples, 4521);
+}
@@ -0,0 +1,84 @@
+use resid::pot::Potentiometer;
+use resid::{ChipModel, Sid};
+
+#[test]
+fn open_input() {
+    let mut pot = Potentiometer::default();
+    for _i in 0..2000 {
+        pot.clock();
+        assert_eq!(pot.read(), 0xff);
+    }
+}
+
+#[test]
+fn measurement_cycle() {
+    let mut pot = Potentiometer::default();
+    pot.set_value(0x40);
+    // The capacitor is discharged for 256 cycles, then the count is latched
+    // when the threshold is crossed.
+    for _i in 0..(256 + 0x40 - 1) {
+        pot.clock();
+        assert_eq!(pot.read(), 0xff);
+    }
+    pot.clock();
+    assert_eq!(pot.read(), 0x40);
+    // A new value is latched on the next measurement.
+    pot.set_value(0x20);
+    for _i in 0..(512 - 0x20 - 1) {
+        pot.clock();
+        assert_eq!(pot.read(), 0x40);
+    }
+    pot.clock();
+    assert_eq!(pot.read(), 0x20);
+}
+
+#[test]
+fn clock_delta() {
+    let mut pot = Potentiometer::default();
+    let mut pot_delta = Potentiometer::default();
+    for i in 0..20000u32 {
+        if i % 1000 == 0 {
+            pot.set_value((i * 7 / 100) as u8);
+            pot_delta.set_value((i * 7 / 100) as u8);
+        }
+        pot.clock();
+        if i % 10 == 9 {
+            pot_delta.clock_delta(10);
+            assert_eq!(pot_delta.read(), pot.read());
+        }
+    }
+}
+
+#[test]
+fn resistance() {
+    let mut pot = Potentiometer::default();
+    pot.set_resistance(0.0);
+    assert_eq!(pot.get_value(), 0);
+    pot.set_resistance(235_000.0);
+    assert_eq!(pot.get_value(), 128);
+    pot.set_resistance(470_000.0);
+    assert_eq!(pot.get_value(), 255);
+    pot.set_resistance(1e9);
+    assert_eq!(pot.get_value(), 255);
+}
+
+#[test]
+fn sid_read() {
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    assert_eq!(sid.read(0x19), 0xff);
+    assert_eq!(sid.read(0x1a), 0xff);
+    sid.set_pot(0x12, 0x34);
+    sid.clock_delta(512);
+    assert_eq!(sid.read(0x19), 0x12);
+    assert_eq!(sid.read(0x1a), 0x34);
+    // Sampling clocks the pot measurement too.
+    sid.set_pot_resistance(0.0, 470_000.0);
+    let mut buffer = [0i16; 64];
+    let mut delta = 512;
+    while delta > 0 {
+        let (_samples, next_delta) = sid.sample(delta, &mut buffer[..], 1);
+        delta = next_delta;
+    }
+    assert_eq!(sid.read(0x19), 0x00);
+    assert_eq!(sid.read(0x1a), 0xff);
+}
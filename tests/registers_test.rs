@@ -0,0 +1,134 @@
+use resid::registers::{AttackDecay, Control, ModeVol, ResFilt, SustainRelease, Waveform};
+use resid::{ChipModel, Clock, Sid};
+
+#[test]
+fn register_round_trip() {
+    for value in 0..=255u8 {
+        assert_eq!(u8::from(Control::from(value)), value);
+        assert_eq!(u8::from(AttackDecay::from(value)), value);
+        assert_eq!(u8::from(SustainRelease::from(value)), value);
+        assert_eq!(u8::from(ResFilt::from(value)), value);
+        assert_eq!(u8::from(ModeVol::from(value)), value);
+    }
+}
+
+#[test]
+fn register_fields() {
+    let control = Control {
+        gate: true,
+        pulse: true,
+        ..Control::default()
+    };
+    assert_eq!(u8::from(control), 0x41);
+    let attack_decay = AttackDecay {
+        attack: 0x0a,
+        decay: 0x05,
+    };
+    assert_eq!(u8::from(attack_decay), 0xa5);
+    let res_filt = ResFilt {
+        resonance: 0x0f,
+        filt1: true,
+        ..ResFilt::default()
+    };
+    assert_eq!(u8::from(res_filt), 0xf1);
+    let mode_vol = ModeVol {
+        volume: 0x0f,
+        low_pass: true,
+        voice3_off: true,
+        ..ModeVol::default()
+    };
+    assert_eq!(u8::from(mode_vol), 0x9f);
+    let mut control = Control::from(0xff);
+    control.set_waveform(Waveform::Sawtooth);
+    assert_eq!(u8::from(control), 0x2f);
+}
+
+#[test]
+fn voice_control() {
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid_raw = Sid::new(ChipModel::Mos6581);
+    sid.voice(1)
+        .unwrap()
+        .set_attack_decay(AttackDecay {
+            attack: 0,
+            decay: 9,
+        })
+        .set_sustain_release(SustainRelease {
+            sustain: 0x0f,
+            release: 0,
+        })
+        .set_pulse_width(0x0800)
+        .set_frequency(0x1cd6)
+        .set_waveform(Waveform::Pulse)
+        .set_gate(true);
+    sid.set_mode_vol(ModeVol {
+        volume: 0x0f,
+        ..ModeVol::default()
+    });
+    sid_raw.write(0x0c, 0x09); // AD2
+    sid_raw.write(0x0d, 0xf0); // SR2
+    sid_raw.write(0x09, 0x00); // PWLO2
+    sid_raw.write(0x0a, 0x08); // PWHI2
+    sid_raw.write(0x07, 0xd6); // FREQLO2
+    sid_raw.write(0x08, 0x1c); // FREQHI2
+    sid_raw.write(0x0b, 0x40); // CR2
+    sid_raw.write(0x0b, 0x41); // CR2
+    sid_raw.write(0x18, 0x0f); // MODVOL
+    for reg in 0..0x19 {
+        assert_eq!(
+            sid.read_state().sid_register[reg],
+            sid_raw.read_state().sid_register[reg]
+        );
+    }
+    assert_eq!(sid.voice(1).unwrap().get_frequency(), 0x1cd6);
+    assert_eq!(sid.voice(1).unwrap().get_pulse_width(), 0x0800);
+    assert_eq!(sid.voice(1).unwrap().get_control(), Control::from(0x41));
+    assert_eq!(sid.get_mode_vol(), ModeVol::from(0x0f));
+    for _i in 0..1000 {
+        sid.clock_delta(22);
+        sid_raw.clock_delta(22);
+        assert_eq!(sid.output(), sid_raw.output());
+    }
+    // Register writes set the bus value.
+    sid.voice(2).unwrap().set_gate(false);
+    assert_eq!(sid.read(0x00), 0x00);
+    sid.voice(2).unwrap().set_frequency(0x1234);
+    assert_eq!(sid.read(0x00), 0x12);
+}
+
+#[test]
+fn voice_frequency_hz() {
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    // A4 at 440Hz on a PAL clock.
+    sid.voice(0).unwrap().set_frequency_hz(440.0, Clock::Pal);
+    assert_eq!(sid.voice(0).unwrap().get_frequency(), 0x1d45);
+    sid.voice(0).unwrap().set_frequency_hz(440.0, Clock::Ntsc);
+    assert_eq!(sid.voice(0).unwrap().get_frequency(), 0x1c32);
+    sid.voice(0).unwrap().set_frequency_hz(1.0e6, Clock::Pal);
+    assert_eq!(sid.voice(0).unwrap().get_frequency(), 0xffff);
+    sid.voice(0).unwrap().set_frequency_hz(-1.0, Clock::Pal);
+    assert_eq!(sid.voice(0).unwrap().get_frequency(), 0);
+}
+
+#[test]
+fn filter_registers() {
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    sid.set_filter_cutoff(0x5a5);
+    assert_eq!(sid.read_state().sid_register[0x15], 0x05);
+    assert_eq!(sid.read_state().sid_register[0x16], 0xb4);
+    let res_filt = ResFilt {
+        resonance: 0x08,
+        filt3: true,
+        filt_ext: true,
+        ..ResFilt::default()
+    };
+    sid.set_res_filt(res_filt);
+    assert_eq!(sid.get_res_filt(), res_filt);
+}
+
+#[test]
+fn voice_invalid() {
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    assert!(sid.voice(2).is_some());
+    assert!(sid.voice(3).is_none());
+}
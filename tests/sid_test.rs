@@ -1,6 +1,8 @@
 mod data;
 
-use resid::{ChipModel, Sid};
+use core::convert::TryFrom;
+
+use resid::{ChipModel, ChipProfile, Error, Reg, SamplingMethod, Sid};
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
 static SID_DATA: [u16; 51] = [
@@ -15,9 +17,8 @@ static SID_DATA: [u16; 51] = [
     19, 63, 250,
 ];
 
-#[test]
-fn clock_delta() {
-    let mut sid = Sid::new(ChipModel::Mos6581);
+fn clock_delta_output(chip_model: ChipModel, expected: &[i16]) {
+    let mut sid = Sid::new(chip_model);
     sid.write(0x05, 0x09); // AD1
     sid.write(0x06, 0x00); // SR1
     sid.write(0x18, 0x0f); // MODVOL
@@ -29,15 +30,279 @@ fn clock_delta() {
         sid.write(0x00, 0x21); // CR1
         for _j in 0..SID_DATA[i + 2] {
             sid.clock_delta(22);
-            assert_eq!(sid.output(), data::sid_output::RESID_OUTPUT[index]);
+            assert_eq!(sid.output(), expected[index]);
             index += 1;
         }
         sid.write(0x00, 0x20); // CR1
         for _j in 0..50 {
             sid.clock_delta(22);
-            assert_eq!(sid.output(), data::sid_output::RESID_OUTPUT[index]);
+            assert_eq!(sid.output(), expected[index]);
             index += 1;
         }
         i += 3;
     }
 }
+
+#[test]
+fn clock_delta() {
+    clock_delta_output(ChipModel::Mos6581, &data::sid_output::RESID_OUTPUT);
+}
+
+#[test]
+fn clock_delta_8580() {
+    // The MOS8580 has no mixer DC offset.
+    clock_delta_output(
+        ChipModel::Mos8580,
+        &data::sid_output_8580::RESID_OUTPUT_8580,
+    );
+}
+
+/// Peak to peak output of a 4-bit sample played by writing the volume register.
+fn volume_sample_amplitude(sid: &mut Sid) -> i32 {
+    let mut min = i32::MAX;
+    let mut max = i32::MIN;
+    for i in 0..2000 {
+        if i % 100 == 0 {
+            sid.write(0x18, if i % 200 == 0 { 0x0f } else { 0x00 }); // MODVOL
+        }
+        sid.clock();
+        if i >= 1000 {
+            min = min.min(sid.output() as i32);
+            max = max.max(sid.output() as i32);
+        }
+    }
+    max - min
+}
+
+#[test]
+fn volume_samples() {
+    // The MOS6581 mixer DC offset makes volume register samples audible.
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    let amplitude_6581 = volume_sample_amplitude(&mut sid);
+    assert!(amplitude_6581 > 0);
+    // The MOS8580 has no DC offsets, and is silent.
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    assert_eq!(volume_sample_amplitude(&mut sid), 0);
+    // The mixer DC offset is adjustable.
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    sid.set_mixer_dc((-0xfff * 0xff / 18) >> 7);
+    let amplitude_mixer_dc = volume_sample_amplitude(&mut sid);
+    assert!(amplitude_mixer_dc > 0 && amplitude_mixer_dc < amplitude_6581);
+    // The voice DC offsets add to the mixer DC offset.
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    sid.set_mixer_dc((-0xfff * 0xff / 18) >> 7);
+    sid.set_voice_dc(0x0380, 0x800 * 0xff);
+    assert_eq!(volume_sample_amplitude(&mut sid), amplitude_6581);
+    // Digi boost makes the samples louder than on the MOS6581.
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    sid.enable_digi_boost(true);
+    assert!(volume_sample_amplitude(&mut sid) > amplitude_6581);
+}
+
+#[test]
+fn digi_boost_external_filter_disabled() {
+    // The digi boost DC level is removed along with the mixer DC.
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    sid.enable_digi_boost(true);
+    sid.enable_external_filter(false);
+    sid.write(0x18, 0x0f); // MODVOL
+    sid.clock_delta(100);
+    assert_eq!(sid.output(), 0);
+    sid.enable_digi_boost(false);
+    sid.clock_delta(100);
+    assert_eq!(sid.output(), 0);
+}
+
+#[test]
+fn set_chip_model() {
+    let setup = |sid: &mut Sid| {
+        sid.write(0x13, 0x29); // AD3
+        sid.write(0x14, 0x8a); // SR3
+        sid.write(0x18, 0x0f); // MODVOL
+        sid.write(0x0f, 0x19); // FREQHI3
+        sid.write(0x12, 0x21); // CR3
+    };
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid_6581 = Sid::new(ChipModel::Mos6581);
+    setup(&mut sid);
+    setup(&mut sid_6581);
+    for _i in 0..100 {
+        sid.clock_delta(22);
+        sid_6581.clock_delta(22);
+    }
+    // Registers, oscillators and envelopes are retained.
+    let state = sid.read_state();
+    sid.set_chip_model(ChipModel::Mos8580);
+    let state_8580 = sid.read_state();
+    assert_eq!(state_8580.sid_register, state.sid_register);
+    assert_eq!(state_8580.accumulator, state.accumulator);
+    assert_eq!(state_8580.envelope_counter, state.envelope_counter);
+    assert_eq!(state_8580.rate_counter, state.rate_counter);
+    let mut diff = false;
+    for _i in 0..1000 {
+        sid.clock_delta(22);
+        sid_6581.clock_delta(22);
+        assert_eq!(sid.read(0x1b), sid_6581.read(0x1b)); // OSC3
+        assert_eq!(sid.read(0x1c), sid_6581.read(0x1c)); // ENV3
+        diff |= sid.output() != sid_6581.output();
+    }
+    // The output is that of the MOS8580.
+    assert!(diff);
+    sid.set_chip_model(ChipModel::Mos6581);
+    assert_eq!(sid.read_state().accumulator, sid_6581.read_state().accumulator);
+}
+
+#[test]
+fn bus_value_decay() {
+    let chip_models = [(ChipModel::Mos6581, 0x1d00), (ChipModel::Mos8580, 0x000a_2000)];
+    for &(chip_model, ttl) in chip_models.iter() {
+        let mut sid = Sid::new(chip_model);
+        sid.write(0x00, 0xa5); // FREQLO1
+        assert_eq!(sid.read(0x00), 0xa5);
+        sid.clock_delta(ttl - 1);
+        assert_eq!(sid.read(0x00), 0xa5);
+        // All bits fade at once.
+        sid.clock();
+        assert_eq!(sid.read(0x00), 0x00);
+    }
+}
+
+#[test]
+fn bus_value_decay_per_bit() {
+    let mut profile = ChipProfile::new(ChipModel::Mos6581);
+    profile.bus_value_ttl = [100, 200, 300, 400, 500, 600, 700, 800];
+    let mut sid = Sid::with_profile(&profile);
+    sid.write(0x00, 0xff); // FREQLO1
+    let mut expected = 0xffu8;
+    for bit in 0..8 {
+        for _i in 0..99 {
+            sid.clock();
+        }
+        assert_eq!(sid.read(0x00), expected);
+        sid.clock();
+        expected &= !(1 << bit);
+        assert_eq!(sid.read(0x00), expected);
+    }
+}
+
+#[test]
+fn bus_value_state() {
+    let mut profile = ChipProfile::new(ChipModel::Mos6581);
+    profile.bus_value_ttl = [100, 200, 300, 400, 500, 600, 700, 800];
+    let mut sid = Sid::with_profile(&profile);
+    sid.write(0x00, 0x0f); // FREQLO1
+    sid.clock_delta(150);
+    let state = sid.read_state();
+    let mut sid2 = Sid::with_profile(&profile);
+    sid2.write_state(&state);
+    assert_eq!(sid2.read(0x00), 0x0e);
+    sid.clock_delta(200);
+    sid2.clock_delta(200);
+    assert_eq!(sid.read(0x00), 0x08);
+    assert_eq!(sid2.read(0x00), 0x08);
+}
+
+#[test]
+fn reg_try_from() {
+    assert_eq!(Reg::try_from(0x00), Ok(Reg::FREQLO1));
+    assert_eq!(Reg::try_from(0x1c), Ok(Reg::ENV3));
+    assert_eq!(Reg::try_from(0x1d), Err(Error::InvalidRegister(0x1d)));
+    assert_eq!(Reg::try_from(0xff), Err(Error::InvalidRegister(0xff)));
+}
+
+#[test]
+#[allow(deprecated)]
+fn reg_from() {
+    assert_eq!(Reg::from(0x1c), Reg::ENV3);
+}
+
+#[test]
+#[should_panic]
+#[allow(deprecated)]
+fn reg_from_invalid() {
+    Reg::from(0x1d);
+}
+
+#[test]
+fn register_mirroring() {
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    let mut sid_mirror = Sid::new(ChipModel::Mos6581);
+    sid.write(0x18, 0x0f); // MODVOL
+    sid_mirror.write(0xf8, 0x0f); // MODVOL mirror at $D4F8
+    sid.write(0x01, 0x40); // FREQHI1
+    sid_mirror.write(0x21, 0x40); // FREQHI1 mirror at $D421
+    sid.write(0x04, 0x21); // CR1
+    sid_mirror.write(0xe4, 0x21); // CR1 mirror at $D4E4
+    sid.write(0x0e, 0xff); // FREQLO3
+    sid_mirror.write(0x2e, 0xff); // FREQLO3 mirror at $D42E
+    sid.write(0x0f, 0x10); // FREQHI3
+    sid_mirror.write(0x4f, 0x10); // FREQHI3 mirror at $D44F
+    sid.write(0x12, 0x21); // CR3
+    sid_mirror.write(0x72, 0x21); // CR3 mirror at $D472
+    for _i in 0..1000 {
+        sid.clock_delta(22);
+        sid_mirror.clock_delta(22);
+        assert_eq!(sid_mirror.output(), sid.output());
+        assert_eq!(sid_mirror.read(0x3b), sid.read(0x1b)); // OSC3
+        assert_eq!(sid_mirror.read(0xfc), sid.read(0x1c)); // ENV3
+    }
+}
+
+#[test]
+fn unused_registers() {
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    for reg in 0x1d..0x20 {
+        sid.write(reg, 0x5a);
+        assert_eq!(sid.read(reg), 0x5a);
+        assert_eq!(sid.read(reg + 0x20), 0x5a);
+    }
+    // Writes to the read-only registers only set the bus value.
+    sid.write(0x19, 0x12); // POTX
+    assert_eq!(sid.read(0x19), 0xff);
+    assert_eq!(sid.read(0x00), 0x12);
+}
+
+#[test]
+fn sampling_parameters() {
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    for &method in [
+        SamplingMethod::Fast,
+        SamplingMethod::Interpolate,
+        SamplingMethod::Resample,
+        SamplingMethod::ResampleFast,
+    ]
+    .iter()
+    {
+        assert_eq!(sid.set_sampling_parameters(method, 985_248, 44100), Ok(()));
+        assert_eq!(sid.set_sampling_parameters(method, 1_022_730, 8000), Ok(()));
+        assert_eq!(
+            sid.set_sampling_parameters(method, 985_248, 0),
+            Err(Error::InvalidSampleFrequency(0))
+        );
+        assert_eq!(
+            sid.set_sampling_parameters(method, 985_248, 1_000_000),
+            Err(Error::SampleFrequencyAboveClock {
+                clock_freq: 985_248,
+                sample_freq: 1_000_000
+            })
+        );
+    }
+    assert_eq!(
+        sid.set_sampling_parameters(SamplingMethod::Fast, 985_248, 100),
+        Ok(())
+    );
+    assert_eq!(
+        sid.set_sampling_parameters(SamplingMethod::Resample, 985_248, 100),
+        Err(Error::SampleFrequencyTooLow {
+            method: SamplingMethod::Resample,
+            clock_freq: 985_248,
+            sample_freq: 100
+        })
+    );
+    assert!(sid
+        .set_sampling_parameters(SamplingMethod::Fast, 985_248, 60)
+        .is_err());
+    // The previous parameters are kept on error.
+    let mut buffer = [0i16; 16];
+    assert_eq!(sid.sample(985_248 / 100, &mut buffer, 1).0, 1);
+}
@@ -0,0 +1,293 @@
+use resid::registers::Waveform;
+use resid::smf::{Event, Smf, SmfRenderer, TrackEvent};
+use resid::{ChipModel, Clock, Error, Sid};
+
+fn chunk(chunk_type: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chunk = chunk_type.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(data);
+    chunk
+}
+
+fn smf(format: u16, ticks_per_quarter: u16, tracks: &[&[u8]]) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&format.to_be_bytes());
+    header.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    header.extend_from_slice(&ticks_per_quarter.to_be_bytes());
+    let mut data = chunk(b"MThd", &header);
+    for track in tracks {
+        data.extend(chunk(b"MTrk", track));
+    }
+    data
+}
+
+// Middle C for one quarter note, then A4 for one quarter note using running
+// status and a note on with zero velocity as note off.
+const TRACK: &[u8] = &[
+    0x00, 0x90, 60, 100, // note on
+    0x60, 0x80, 60, 0, // note off
+    0x00, 0x90, 69, 100, // note on
+    0x60, 69, 0, // note off, running status
+    0x00, 0xff, 0x2f, 0x00, // end of track
+];
+
+#[test]
+fn parse_format_0() {
+    let smf = Smf::parse(&smf(0, 96, &[TRACK])).unwrap();
+    assert_eq!(smf.format, 0);
+    assert_eq!(smf.ticks_per_quarter, 96);
+    assert_eq!(smf.tracks.len(), 1);
+    assert_eq!(
+        smf.tracks[0],
+        vec![
+            TrackEvent {
+                delta: 0,
+                event: Event::NoteOn {
+                    channel: 0,
+                    note: 60,
+                    velocity: 100
+                }
+            },
+            TrackEvent {
+                delta: 96,
+                event: Event::NoteOff {
+                    channel: 0,
+                    note: 60,
+                    velocity: 0
+                }
+            },
+            TrackEvent {
+                delta: 0,
+                event: Event::NoteOn {
+                    channel: 0,
+                    note: 69,
+                    velocity: 100
+                }
+            },
+            TrackEvent {
+                delta: 96,
+                event: Event::NoteOn {
+                    channel: 0,
+                    note: 69,
+                    velocity: 0
+                }
+            },
+            TrackEvent {
+                delta: 0,
+                event: Event::EndOfTrack
+            },
+        ]
+    );
+}
+
+#[test]
+fn parse_skipped_events() {
+    let track: &[u8] = &[
+        0x10, 0xff, 0x03, 0x02, b'h', b'i', // track name
+        0x10, 0xf0, 0x02, 0x7e, 0xf7, // system exclusive
+        0x10, 0xe1, 0x00, 0x60, // pitch bend
+        0x00, 0xff, 0x2f, 0x00,
+    ];
+    let smf = Smf::parse(&smf(0, 96, &[track])).unwrap();
+    assert_eq!(
+        smf.tracks[0][0],
+        TrackEvent {
+            delta: 0x30,
+            event: Event::PitchBend {
+                channel: 1,
+                value: 0x1000
+            }
+        }
+    );
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(
+        Smf::parse(b"RIFF").err(),
+        Some(Error::InvalidMidiFile("missing header chunk"))
+    );
+    assert_eq!(
+        Smf::parse(&smf(2, 96, &[TRACK])).err(),
+        Some(Error::InvalidMidiFile("unsupported format"))
+    );
+    assert_eq!(
+        Smf::parse(&smf(0, 0xe728, &[TRACK])).err(),
+        Some(Error::InvalidMidiFile("unsupported time division"))
+    );
+    let data = smf(0, 96, &[TRACK]);
+    assert_eq!(
+        Smf::parse(&data[..data.len() - 2]).err(),
+        Some(Error::InvalidMidiFile("unexpected end of data"))
+    );
+    assert_eq!(
+        Smf::parse(&smf(0, 96, &[&[0x00, 60, 100]])).err(),
+        Some(Error::InvalidMidiFile("data byte without status"))
+    );
+}
+
+#[test]
+fn timeline_tempo() {
+    // Format 1 with the tempo map in the first track, doubling the tempo
+    // after one quarter note.
+    let tempo_track: &[u8] = &[
+        0x60, 0xff, 0x51, 0x03, 0x03, 0xd0, 0x90, // 250000us per quarter
+        0x00, 0xff, 0x2f, 0x00,
+    ];
+    let smf = Smf::parse(&smf(1, 96, &[tempo_track, TRACK])).unwrap();
+    let cycles: Vec<u64> = smf
+        .timeline(Clock::Pal)
+        .iter()
+        .map(|&(cycle, _event)| cycle)
+        .collect();
+    // Half a second, then a quarter second per quarter note.
+    assert_eq!(
+        cycles,
+        vec![0, 492_624, 492_624, 492_624, 492_624, 738_936, 738_936]
+    );
+}
+
+#[test]
+fn render() {
+    let smf = Smf::parse(&smf(0, 96, &[TRACK])).unwrap();
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    sid.set_sampling_parameters(resid::SamplingMethod::Fast, 985_248, 44100)
+        .unwrap();
+    let mut renderer = SmfRenderer::new(sid, Clock::Pal);
+    let mut instrument = renderer_instrument();
+    instrument.waveform = Waveform::Sawtooth;
+    renderer.set_channel_instrument(0, instrument);
+    renderer.set_tail_cycles(0);
+    let output = renderer.render(&smf);
+    // One second of audio.
+    assert!((output.len() as i32 - 44100).abs() <= 1);
+    assert!(output.iter().any(|&sample| sample > 1000));
+    assert!(output.iter().any(|&sample| sample < -1000));
+    // The last note is released at the end.
+    let sid = renderer.into_sid();
+    assert_eq!(sid.read_state().sid_register[0x04], 0x20);
+    assert_eq!(sid.read_state().sid_register[0x01], 0x1d);
+}
+
+fn render_track(hard_restart: bool) -> Vec<i16> {
+    let smf = Smf::parse(&smf(0, 96, &[TRACK])).unwrap();
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    sid.set_sampling_parameters(resid::SamplingMethod::Fast, 985_248, 44100)
+        .unwrap();
+    let mut renderer = SmfRenderer::new(sid, Clock::Pal);
+    let mut instrument = renderer_instrument();
+    instrument.waveform = Waveform::Sawtooth;
+    instrument.hard_restart = hard_restart;
+    renderer.set_channel_instrument(0, instrument);
+    renderer.set_tail_cycles(0);
+    renderer.render(&smf)
+}
+
+fn peak_to_peak(samples: &[i16]) -> i32 {
+    *samples.iter().max().unwrap() as i32 - *samples.iter().min().unwrap() as i32
+}
+
+#[test]
+fn render_retrigger() {
+    // The A4 note starts on the voice as the middle C is released, and is
+    // played legato right away.
+    let output = render_track(false);
+    let start = output.len() / 2;
+    let c4 = peak_to_peak(&output[start - 500..start]);
+    assert!(c4 > 1000);
+    assert!(peak_to_peak(&output[start..start + 100]) > c4 / 2);
+}
+
+#[test]
+fn render_retrigger_hard_restart() {
+    // The A4 note is silent during the hard restart, and starts once it is
+    // complete.
+    let output = render_track(true);
+    let start = output.len() / 2;
+    let hard_restart = (0x8000u64 * 44100 / 985_248) as usize;
+    let c4 = peak_to_peak(&output[start - 500..start]);
+    assert!(c4 > 1000);
+    let silent = start + hard_restart - 300..start + hard_restart - 50;
+    assert!(peak_to_peak(&output[silent]) < c4 / 10);
+    let a4 = start + hard_restart + 50..start + hard_restart + 500;
+    assert!(peak_to_peak(&output[a4]) > c4 / 2);
+}
+
+#[test]
+fn render_hard_restart() {
+    let track: &[u8] = &[
+        0x00, 0x90, 69, 100, // note on
+        0x00, 0xff, 0x2f, 0x00,
+    ];
+    let smf = Smf::parse(&smf(0, 96, &[track])).unwrap();
+    let mut instrument = renderer_instrument();
+    instrument.hard_restart = true;
+    for &(tail_cycles, control) in [(1000, 0x48), (0x8000, 0x41)].iter() {
+        let mut renderer = SmfRenderer::new(Sid::new(ChipModel::Mos6581), Clock::Pal);
+        renderer.set_channel_instrument(0, instrument);
+        renderer.set_tail_cycles(tail_cycles);
+        renderer.render(&smf);
+        // 1000 cycles into the hard restart the gate is clear and the test
+        // bit is set. Once the hard restart is complete the gate is set.
+        assert_eq!(renderer.get_sid().read_state().sid_register[0x04], control);
+    }
+}
+
+#[test]
+fn render_controllers() {
+    let track: &[u8] = &[
+        0x00, 0xb0, 74, 0x40, // filter cutoff
+        0x00, 0xb0, 71, 0x7f, // resonance
+        0x00, 0xb0, 7, 0x40, // volume
+        0x00, 0x90, 69, 100, // note on
+        0x00, 0xe0, 0x7f, 0x7f, // pitch bend up
+        0x00, 0xff, 0x2f, 0x00,
+    ];
+    let smf = Smf::parse(&smf(0, 96, &[track])).unwrap();
+    let mut renderer = SmfRenderer::new(Sid::new(ChipModel::Mos6581), Clock::Pal);
+    renderer.set_channel_filter(0, true);
+    renderer.set_tail_cycles(1000);
+    renderer.render(&smf);
+    let registers = renderer.get_sid().read_state().sid_register;
+    assert_eq!(registers[0x15], 0x00); // FCLO
+    assert_eq!(registers[0x16], 0x81); // FCHI
+    assert_eq!(registers[0x17], 0xf1); // RESFILT
+    assert_eq!(registers[0x18], 0x18); // MODVOL
+
+    // A4 bent up by almost two semitones.
+    let frequency = (registers[0x01] as u16) << 8 | registers[0x00] as u16;
+    let expected = 493.883 * 16_777_216.0 / 985_248.0;
+    assert!((frequency as f64 - expected).abs() < 2.0);
+}
+
+#[test]
+fn render_channel_mapping() {
+    let track: &[u8] = &[
+        0x00, 0x95, 69, 100, // note on channel 5
+        0x00, 0x90, 60, 100, // note on channel 0
+        0x00, 0xff, 0x2f, 0x00,
+    ];
+    let smf = Smf::parse(&smf(0, 96, &[track])).unwrap();
+    let mut renderer = SmfRenderer::new(Sid::new(ChipModel::Mos6581), Clock::Pal);
+    renderer.set_channel_voice(0, None).unwrap();
+    renderer.set_channel_voice(5, Some(2)).unwrap();
+    assert_eq!(
+        renderer.set_channel_voice(1, Some(3)),
+        Err(Error::InvalidVoice(3))
+    );
+    renderer.set_tail_cycles(0);
+    renderer.render(&smf);
+    let registers = renderer.get_sid().read_state().sid_register;
+    assert_eq!(registers[0x04], 0x00); // CR1
+    assert_eq!(registers[0x12], 0x41); // CR3
+}
+
+fn renderer_instrument() -> resid::instrument::Instrument {
+    resid::instrument::Instrument {
+        attack_ms: 2.0,
+        decay_ms: 6.0,
+        sustain: 0x0f,
+        release_ms: 6.0,
+        ..Default::default()
+    }
+}
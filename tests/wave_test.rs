@@ -1,7 +1,7 @@
 mod data;
 
 use resid::wave::WaveformGenerator;
-use resid::ChipModel;
+use resid::{ChipModel, ChipProfile};
 
 fn setup(wave: &mut WaveformGenerator, waveform: u8, freq: u16, pw: u16) {
     wave.set_control((waveform & 0x0f) << 4 | 0x00);
@@ -11,6 +11,15 @@ fn setup(wave: &mut WaveformGenerator, waveform: u8, freq: u16, pw: u16) {
     wave.set_pulse_width_lo((pw & 0xff) as u8);
 }
 
+// The reference output is from reSID 0.16, which clocks the noise shift
+// register without delay.
+fn resid_0_16() -> ChipProfile {
+    ChipProfile {
+        shift_delay: 0,
+        ..ChipProfile::new(ChipModel::Mos6581)
+    }
+}
+
 #[test]
 fn waveform_1() {
     let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
@@ -83,7 +92,7 @@ fn waveform_7() {
 
 #[test]
 fn waveform_8() {
-    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    let mut wave = WaveformGenerator::with_profile(&resid_0_16());
     setup(&mut wave, 8, 16000, 1000);
     for i in 0..1500 {
         wave.clock();
@@ -91,6 +100,50 @@ fn waveform_8() {
     }
 }
 
+#[test]
+fn waveform_noise_delay() {
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    // Bit 19 is set high on the first cycle.
+    setup(&mut wave, 8, 0, 0);
+    wave.set_acc(0x0007_ffff);
+    wave.set_frequency_lo(1);
+    let shift = wave.get_shift();
+    wave.clock();
+    assert_eq!(wave.get_shift(), shift);
+    wave.clock();
+    assert_eq!(wave.get_shift(), shift);
+    wave.clock();
+    assert_ne!(wave.get_shift(), shift);
+}
+
+#[test]
+fn waveform_test_bit_reset() {
+    for &(chip_model, reset_time) in [
+        (ChipModel::Mos6581, 0x8000u32),
+        (ChipModel::Mos8580, 0x0095_0000u32),
+    ]
+    .iter()
+    {
+        let mut wave = WaveformGenerator::new(chip_model);
+        setup(&mut wave, 8, 16000, 0);
+        for _i in 0..5000 {
+            wave.clock();
+        }
+        let shift = wave.get_shift();
+        // The shift register is retained while test has not been held long enough.
+        wave.set_control(0x88);
+        wave.clock_delta(reset_time - 2);
+        wave.clock();
+        assert_eq!(wave.get_shift(), shift);
+        // The shift register is reset to all ones.
+        wave.clock();
+        assert_eq!(wave.get_shift(), 0x007f_ffff);
+        // Clearing test clocks the shift register with bit0 = ~bit17.
+        wave.set_control(0x80);
+        assert_eq!(wave.get_shift(), 0x007f_fffe);
+    }
+}
+
 #[test]
 fn waveform_delta_1() {
     let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
@@ -184,7 +237,7 @@ fn waveform_delta_7() {
 
 #[test]
 fn waveform_delta_8() {
-    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    let mut wave = WaveformGenerator::with_profile(&resid_0_16());
     setup(&mut wave, 8, 16000, 1000);
     for i in 0..1500 {
         wave.clock_delta(25);
@@ -194,3 +247,218 @@ fn waveform_delta_8() {
         );
     }
 }
+
+#[test]
+fn waveform_delta_noise_delay() {
+    // The delayed shift must be the same for 1 cycle and delta cycle clocking.
+    for &delta in [1u32, 2, 3, 7, 25, 100].iter() {
+        let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+        let mut wave_delta = WaveformGenerator::new(ChipModel::Mos6581);
+        setup(&mut wave, 8, 16000, 0);
+        setup(&mut wave_delta, 8, 16000, 0);
+        for i in 0..1000 {
+            for _j in 0..delta {
+                wave.clock();
+            }
+            wave_delta.clock_delta(delta);
+            assert_eq!(
+                wave_delta.output(),
+                wave.output(),
+                "delta {} step {}",
+                delta,
+                i
+            );
+            assert_eq!(wave_delta.shift_pipeline, wave.shift_pipeline);
+        }
+    }
+}
+
+// Compares OSC3 reads with sequences generated from a separate model of the
+// noise LFSR, see data/wave_noise_output.py.
+fn read_osc3_sequence(waveform: u8, pw: u16, expected: &[u8]) {
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    setup(&mut wave, waveform, 0x8000, pw);
+    for (i, &osc3) in expected.iter().enumerate() {
+        for _j in 0..32 {
+            wave.clock();
+        }
+        assert_eq!(wave.read_osc(), osc3, "OSC3 read {}", i);
+    }
+}
+
+#[test]
+fn waveform_noise_osc3_model() {
+    read_osc3_sequence(0x8, 0, &data::wave_noise_output::NOISE_OSC3);
+}
+
+#[test]
+fn waveform_noise_triangle_osc3_model() {
+    read_osc3_sequence(0x9, 0, &data::wave_noise_output::NOISE_TRIANGLE_OSC3);
+}
+
+#[test]
+fn waveform_noise_pulse_osc3_model() {
+    read_osc3_sequence(0xc, 0x100, &data::wave_noise_output::NOISE_PULSE_OSC3);
+}
+
+#[test]
+fn waveform_noise_pulse_high() {
+    // With the pulse output held high, pulse + noise equals noise.
+    let mut noise = WaveformGenerator::new(ChipModel::Mos6581);
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    setup(&mut noise, 8, 16000, 0);
+    setup(&mut wave, 12, 16000, 0);
+    for _i in 0..1500 {
+        noise.clock();
+        wave.clock();
+        assert_eq!(wave.output(), noise.output());
+        assert_eq!(wave.get_shift(), noise.get_shift());
+    }
+}
+
+#[test]
+fn waveform_noise_combined() {
+    // Combined output is the AND of the noise and the other waveform output.
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    setup(&mut wave, 9, 16000, 0);
+    for _i in 0..1500 {
+        wave.clock();
+        let triangle = {
+            let acc = wave.get_acc();
+            let output = if acc & 0x0080_0000 != 0 { !acc } else { acc };
+            (output >> 11) as u16 & 0x0fff
+        };
+        assert_eq!(wave.output() & !triangle, 0);
+    }
+}
+
+#[test]
+fn waveform_noise_lock() {
+    // Pulse + noise with the pulse output low writes zeros back into the
+    // shift register, which eventually locks the noise output at zero.
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    setup(&mut wave, 12, 16000, 0x0fff);
+    for _i in 0..20000 {
+        wave.clock();
+    }
+    assert_eq!(wave.get_shift(), 0);
+    // The noise stays locked after deselecting the pulse waveform.
+    setup(&mut wave, 8, 16000, 0x0fff);
+    for _i in 0..20000 {
+        wave.clock();
+        assert_eq!(wave.output(), 0);
+    }
+    // Holding the test bit resets the shift register.
+    wave.set_control(0x88);
+    wave.clock_delta(0x8000);
+    wave.set_control(0x80);
+    assert_eq!(wave.get_shift(), 0x007f_fffe);
+}
+
+#[test]
+fn waveform_delta_noise_lock() {
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    setup(&mut wave, 12, 16000, 0x0fff);
+    for _i in 0..1000 {
+        wave.clock_delta(25);
+    }
+    assert_eq!(wave.get_shift(), 0);
+}
+
+#[test]
+fn waveform_floating_output() {
+    for &(chip_model, ttl, fade) in [
+        (ChipModel::Mos6581, 54_000u32, 1_400u32),
+        (ChipModel::Mos8580, 800_000u32, 50_000u32),
+    ]
+    .iter()
+    {
+        let mut wave = WaveformGenerator::new(chip_model);
+        setup(&mut wave, 2, 0, 0);
+        wave.set_acc(0x00ab_c000);
+        let output = wave.output();
+        assert_eq!(output, 0x0abc);
+        // The last output is held when the waveform is deselected.
+        wave.set_control(0x00);
+        wave.clock_delta(ttl - 1);
+        assert_eq!(wave.output(), output);
+        assert_eq!(wave.read_osc(), 0xab);
+        // The bits then fade one step at a time.
+        wave.clock();
+        assert_eq!(wave.output(), 0x001c);
+        wave.clock_delta(fade);
+        assert_eq!(wave.output(), 0x000c);
+        wave.clock_delta(fade * 2);
+        assert_eq!(wave.output(), 0x0000);
+    }
+}
+
+#[test]
+fn waveform_floating_output_reselect() {
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    setup(&mut wave, 1, 0, 0);
+    wave.set_acc(0x0040_0000);
+    wave.set_control(0x00);
+    assert_eq!(wave.output(), 0x0800);
+    // Selecting a waveform drives the DAC again.
+    wave.set_control(0x20);
+    assert_eq!(wave.output(), 0x0400);
+}
+
+#[test]
+fn waveform_slew_pulse() {
+    for &(chip_model, rate) in [
+        (ChipModel::Mos6581, 0x400u16),
+        (ChipModel::Mos8580, 0x800u16),
+    ]
+    .iter()
+    {
+        let mut wave = WaveformGenerator::new(chip_model);
+        setup(&mut wave, 4, 0, 0x0800);
+        wave.set_slew_enabled(true);
+        assert_eq!(wave.get_slew_rate(), rate);
+        assert_eq!(wave.output(), 0x0000);
+        // The rising edge ramps up at the slew rate.
+        wave.set_acc(0x0080_0000);
+        let mut output = wave.output();
+        while output != 0x0fff {
+            wave.clock();
+            assert_eq!(wave.output() - output, rate.min(0x0fff - output));
+            output = wave.output();
+        }
+        // OSC3 reads the digital output.
+        wave.set_acc(0x0000_0000);
+        assert_eq!(wave.read_osc(), 0x00);
+        assert_eq!(wave.output(), 0x0fff);
+    }
+}
+
+#[test]
+fn waveform_slew_sawtooth() {
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    setup(&mut wave, 2, 0xffff, 0);
+    wave.set_acc(0x00ff_0000);
+    wave.set_slew_enabled(true);
+    // The sawtooth ramp is slow enough to be followed exactly, whereas the
+    // falling edge ramps down over several cycles.
+    wave.clock();
+    assert_eq!(wave.output(), 0x0fff);
+    wave.clock();
+    assert_eq!(wave.output(), 0x0bff);
+    wave.clock_delta(2);
+    assert_eq!(wave.output(), 0x03ff);
+    wave.clock();
+    assert_eq!(wave.output(), 0x003f);
+}
+
+#[test]
+fn waveform_slew_disabled() {
+    let mut wave = WaveformGenerator::new(ChipModel::Mos6581);
+    setup(&mut wave, 4, 16000, 1000);
+    wave.set_slew_enabled(true);
+    wave.set_slew_enabled(false);
+    for i in 0..1500 {
+        wave.clock();
+        assert_eq!(wave.output(), data::wave_output::RESID_WAVE4_OUTPUT[i]);
+    }
+}
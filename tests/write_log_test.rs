@@ -0,0 +1,250 @@
+use resid::dac::DacConfig;
+use resid::write_log::{RegisterWrite, WriteLog};
+use resid::{ChipModel, ChipProfile, Clock, Error, SamplingMethod, Sid, SidBuilder};
+
+fn sample(sid: &mut Sid, mut delta: u32, output: &mut Vec<i16>) {
+    // Deliberately small buffer, so sampling is split at odd cycles.
+    let mut buffer = [0i16; 37];
+    while delta > 0 {
+        let (samples, next_delta) = sid.sample(delta, &mut buffer, 1);
+        output.extend_from_slice(&buffer[..samples]);
+        delta = next_delta;
+    }
+}
+
+#[test]
+fn cycle_counter() {
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    assert_eq!(sid.get_cycle(), 0);
+    sid.clock();
+    sid.clock_delta(100);
+    assert_eq!(sid.get_cycle(), 101);
+    let mut buffer = [0i16; 4];
+    let (_samples, next_delta) = sid.sample(1000, &mut buffer, 1);
+    assert!(next_delta > 0);
+    assert_eq!(sid.get_cycle(), 1101 - next_delta as u64);
+    sid.reset();
+    assert_eq!(sid.get_cycle(), 1101 - next_delta as u64);
+    let state = sid.read_state();
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    sid.write_state(&state);
+    assert_eq!(sid.get_cycle(), 1101 - next_delta as u64);
+}
+
+#[test]
+fn recording() {
+    let mut sid = Sid::new(ChipModel::Mos8580);
+    sid.write(0x18, 0x0f);
+    sid.clock_delta(500);
+    sid.start_recording().unwrap();
+    sid.clock_delta(10);
+    sid.write(0x04, 0x11);
+    sid.voice(1).unwrap().set_frequency(0x1234);
+    sid.clock_delta(100_000);
+    sid.write(0x38, 0x0f);
+    sid.clock_delta(20);
+    let log = sid.stop_recording().unwrap();
+    assert_eq!(sid.stop_recording(), None);
+    sid.write(0x18, 0x00);
+    // The header is filled in from the SID.
+    assert_eq!(log.chip_model, ChipModel::Mos8580);
+    assert_eq!(log.sampling_method, SamplingMethod::Fast);
+    assert_eq!(log.clock_freq, 985_248);
+    assert_eq!(log.sample_freq, 44100);
+    assert_eq!(log.start, 500);
+    assert_eq!(log.end, 100_530);
+    assert_eq!(
+        log.writes,
+        vec![
+            RegisterWrite {
+                cycle: 510,
+                reg: 0x04,
+                value: 0x11,
+            },
+            RegisterWrite {
+                cycle: 510,
+                reg: 0x07,
+                value: 0x34,
+            },
+            RegisterWrite {
+                cycle: 510,
+                reg: 0x08,
+                value: 0x12,
+            },
+            RegisterWrite {
+                cycle: 100_510,
+                reg: 0x38,
+                value: 0x0f,
+            },
+        ]
+    );
+    let data = log.to_bytes();
+    // 31 byte header, 4 writes of 2 bytes plus 1, 1, 1 and 3 bytes of
+    // cycles.
+    assert_eq!(data.len(), 31 + 8 + 6);
+    assert_eq!(&data[..7], b"RSWL\x01\x01\x00");
+    assert_eq!(WriteLog::parse(&data), Ok(log));
+}
+
+#[test]
+fn replay() {
+    for &(chip_model, sampling_method) in &[
+        (ChipModel::Mos6581, SamplingMethod::Fast),
+        (ChipModel::Mos8580, SamplingMethod::Interpolate),
+        (ChipModel::Mos6581, SamplingMethod::ResampleFast),
+    ] {
+        let mut sid = SidBuilder::new(chip_model)
+            .clock(Clock::Ntsc)
+            .sample_freq(48000)
+            .sampling_method(sampling_method)
+            .build()
+            .unwrap();
+        sid.start_recording().unwrap();
+        let mut output = Vec::new();
+        sid.write(0x18, 0x1f);
+        sid.write(0x17, 0xf1);
+        sid.write(0x16, 0x40);
+        for (i, &note) in [0x1cd6u16, 0x2454, 0x2b34, 0x3398].iter().enumerate() {
+            sid.voice(i % 3)
+                .unwrap()
+                .set_frequency(note)
+                .set_pulse_width(0x400 + i as u16 * 0x100)
+                .set_attack_decay(0x09u8.into())
+                .set_sustain_release(0xa8u8.into())
+                .set_control(0x41u8.into());
+            sample(&mut sid, 20_011 + i as u32 * 1_237, &mut output);
+            sid.voice(i % 3).unwrap().set_gate(false);
+            sample(&mut sid, 3_001, &mut output);
+        }
+        let log = sid.stop_recording().unwrap();
+        assert_eq!(log.chip_model, chip_model);
+        assert_eq!(log.sampling_method, sampling_method);
+        assert_eq!(log.clock_freq, 1_022_727);
+        assert_eq!(log.sample_freq, 48000);
+        let log = WriteLog::parse(&log.to_bytes()).unwrap();
+        assert_eq!(log.render().unwrap(), output);
+    }
+}
+
+#[test]
+fn custom_settings() {
+    // Settings not stored in the log are refused.
+    let custom: [fn(&mut Sid); 7] = [
+        |sid| sid.enable_filter(false),
+        |sid| sid.enable_external_filter(false),
+        |sid| sid.enable_digi_boost(true),
+        |sid| sid.enable_edge_slew(true),
+        |sid| sid.set_dac(Some(DacConfig::from_chip_model(ChipModel::Mos6581))),
+        |sid| sid.set_mixer_dc(0),
+        |sid| sid.set_profile(&ChipProfile::clean()),
+    ];
+    for setting in custom.iter() {
+        let mut sid = Sid::new(ChipModel::Mos6581);
+        setting(&mut sid);
+        assert_eq!(sid.start_recording(), Err(Error::UnsupportedRecording));
+        assert_eq!(sid.stop_recording(), None);
+    }
+    let mut sid = Sid::with_profile(&ChipProfile::new(ChipModel::Mos6581));
+    assert_eq!(sid.start_recording(), Err(Error::UnsupportedRecording));
+    let mut sid = SidBuilder::new(ChipModel::Mos6581)
+        .filter(false)
+        .build()
+        .unwrap();
+    assert_eq!(sid.start_recording(), Err(Error::UnsupportedRecording));
+    // The defaults and the chip model presets can be recorded.
+    let mut sid = Sid::new(ChipModel::Mos6581);
+    sid.enable_filter(true);
+    sid.set_chip_model(ChipModel::Clean);
+    assert_eq!(sid.start_recording(), Ok(()));
+    let mut sid = SidBuilder::new(ChipModel::Mos8580).build().unwrap();
+    assert_eq!(sid.start_recording(), Ok(()));
+}
+
+#[test]
+fn invalid_log() {
+    let log = WriteLog::new(ChipModel::Mos6581, SamplingMethod::Fast, 985_248, 44100);
+    let mut data = log.to_bytes();
+    assert_eq!(
+        WriteLog::parse(&data[..30]),
+        Err(Error::InvalidWriteLog("unexpected end of data"))
+    );
+    assert_eq!(
+        WriteLog::parse(b"MThd"),
+        Err(Error::InvalidWriteLog("missing header"))
+    );
+    data[5] = 2;
+    assert_eq!(WriteLog::parse(&data).unwrap().chip_model, ChipModel::Clean);
+    data[5] = 3;
+    assert_eq!(
+        WriteLog::parse(&data),
+        Err(Error::InvalidWriteLog("invalid chip model"))
+    );
+    data[5] = 0;
+    // A write one cycle after the end.
+    data.extend_from_slice(&[0x01, 0x18, 0x0f]);
+    assert_eq!(
+        WriteLog::parse(&data),
+        Err(Error::InvalidWriteLog("write after end"))
+    );
+    data[30] = 1;
+    assert!(WriteLog::parse(&data).is_ok());
+    data.pop();
+    assert_eq!(
+        WriteLog::parse(&data),
+        Err(Error::InvalidWriteLog("unexpected end of data"))
+    );
+}
+
+#[test]
+fn dump() {
+    let mut log = WriteLog::new(ChipModel::Mos6581, SamplingMethod::Fast, 985_248, 44100);
+    log.start = 100;
+    log.end = 2000;
+    log.writes.push(RegisterWrite {
+        cycle: 150,
+        reg: 0x04,
+        value: 0x41,
+    });
+    log.writes.push(RegisterWrite {
+        cycle: 1150,
+        reg: 0x3f,
+        value: 0x00,
+    });
+    assert_eq!(
+        log.to_string(),
+        "# Mos6581, Fast sampling, clock 985248Hz, sample 44100Hz\n\
+         # cycles 100 to 2000\n\
+         #      cycle    delta reg          value\n\
+         \x20        150       50 $04 CR1      $41\n\
+         \x20       1150     1000 $3f -        $00\n"
+    );
+}
+
+#[test]
+fn frames() {
+    let mut log = WriteLog::new(ChipModel::Mos6581, SamplingMethod::Fast, 985_248, 44100);
+    log.start = 1000;
+    log.end = 1000 + 3 * 19656;
+    for &(cycle, reg, value) in [
+        (1000, 0x18, 0x1f),
+        (1010, 0x00, 0xd6),
+        (1020, 0x01, 0x1c),
+        (1030, 0x05, 0x09),
+        (1040, 0x04, 0x41),
+        (1000 + 19656, 0x0b, 0x21),
+        (1000 + 2 * 19656 + 100, 0x04, 0x40),
+        (1000 + 2 * 19656 + 200, 0x36, 0x80),
+    ]
+    .iter()
+    {
+        log.writes.push(RegisterWrite { cycle, reg, value });
+    }
+    assert_eq!(
+        log.frames(19656).to_string(),
+        "| Frame | Freq WF ADSR Pul | Freq WF ADSR Pul | Freq WF ADSR Pul | FCut RC Typ V |\n\
+         +-------+------------------+------------------+------------------+---------------+\n\
+         |     0 | 1cd6 41 0900 000 | 0000 00 0000 000 | 0000 00 0000 000 | 0000 00 Low f |\n\
+         |     1 | .... .. .... ... | .... 21 .... ... | .... .. .... ... | .... .. ... . |\n\
+         |     2 | .... 40 .... ... | .... .. .... ... | .... .. .... ... | 0400 .. ... . |\n"
+    );
+}